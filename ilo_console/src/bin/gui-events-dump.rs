@@ -107,6 +107,7 @@ async fn run_transport(
     let session = Session::try_from(params)?;
 
     let mut transport = Transport::new(session, transport_rx.clone(), gui_tx.clone());
+    transport.set_proxy(auth.proxy.clone());
     let mut decoder = Decoder::new(gui_tx, transport_tx.clone());
 
     transport.run(&mut decoder).await?;
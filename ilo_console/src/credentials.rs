@@ -0,0 +1,305 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+use thiserror::Error;
+use tracing::{event, instrument, Level};
+
+/// Where the username and password for a node are read from
+///
+/// `{hostname}` and `{username}` in commands, attributes and descriptions are
+/// replaced with the node's hostname and username.
+///
+/// ```json
+/// { "source": "env", "username": "ILO_USER", "password": "ILO_PASSWORD" }
+/// { "source": "command", "command": "pass show ilo/{hostname}" }
+/// { "source": "netrc", "path": "/home/me/.netrc" }
+/// { "source": "secret_service", "attributes": { "service": "ilo", "host": "{hostname}" } }
+/// { "source": "kernel_keyring", "description": "ilo:{hostname}" }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum CredentialSource {
+    /// The username and password are stored in the endpoint file
+    #[default]
+    Inline,
+    /// Read from environment variables, the username variable is optional
+    Env {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        username: Option<String>,
+        password: String,
+    },
+    /// Run a shell command and use the first line it prints as the password
+    Command { command: String },
+    /// Read the login and password for the hostname from a netrc file,
+    /// `~/.netrc` by default
+    Netrc {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        path: Option<PathBuf>,
+    },
+    /// Look the password up in the Secret Service with `secret-tool`, by
+    /// default using the attributes `service=ilo host={hostname}`
+    SecretService {
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        attributes: BTreeMap<String, String>,
+    },
+    /// Read the password from a `user` key in the kernel keyring with `keyctl`,
+    /// `ilo:{hostname}` by default
+    KernelKeyring {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
+}
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("environment variable {name} is not set")]
+    MissingEnv { name: String },
+    #[error("couldn't run `{command}`: {source}")]
+    Spawn { command: String, source: io::Error },
+    #[error("`{command}` failed with {status}: {stderr}")]
+    CommandFailed {
+        command: String,
+        status: process::ExitStatus,
+        stderr: String,
+    },
+    #[error("`{command}` printed invalid utf8")]
+    InvalidUtf8 { command: String },
+    #[error("couldn't read netrc file {path:?}: {source}")]
+    Netrc { path: PathBuf, source: io::Error },
+    #[error("no entry for {hostname} in netrc file {path:?}")]
+    NoNetrcEntry { path: PathBuf, hostname: String },
+    #[error("couldn't find home directory for the default netrc file")]
+    NoHome,
+    #[error("no password returned for {hostname}")]
+    Empty { hostname: String },
+}
+
+/// Credentials read from a [CredentialSource]
+#[derive(Clone, PartialEq)]
+pub struct Credentials {
+    /// Replaces the configured username when set
+    pub username: Option<String>,
+    pub password: String,
+}
+
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &"********")
+            .finish()
+    }
+}
+
+impl CredentialSource {
+    /// True when the credentials are stored in the endpoint file
+    pub fn is_inline(&self) -> bool {
+        *self == CredentialSource::Inline
+    }
+
+    /// Read the credentials for `hostname`, returns `Ok(None)` for
+    /// [CredentialSource::Inline]
+    #[instrument]
+    pub fn resolve(&self, hostname: &str, username: &str) -> Result<Option<Credentials>, Error> {
+        let expand = |template: &str| {
+            template
+                .replace("{hostname}", hostname)
+                .replace("{username}", username)
+        };
+        let credentials = match self {
+            CredentialSource::Inline => return Ok(None),
+            CredentialSource::Env { username, password } => Credentials {
+                username: username.as_deref().map(env_var).transpose()?,
+                password: env_var(password)?,
+            },
+            CredentialSource::Command { command } => Credentials {
+                username: None,
+                password: first_line(&run(shell(&expand(command)))?).to_string(),
+            },
+            CredentialSource::Netrc { path } => {
+                let path = match path {
+                    Some(path) => path.clone(),
+                    None => default_netrc()?,
+                };
+                netrc(&path, hostname)?
+            }
+            CredentialSource::SecretService { attributes } => {
+                let mut command = process::Command::new("secret-tool");
+                command.arg("lookup");
+                if attributes.is_empty() {
+                    command.args(["service", "ilo", "host", hostname]);
+                } else {
+                    for (key, value) in attributes {
+                        command.arg(expand(key)).arg(expand(value));
+                    }
+                }
+                Credentials {
+                    username: None,
+                    password: first_line(&run(command)?).to_string(),
+                }
+            }
+            CredentialSource::KernelKeyring { description } => {
+                let description = match description {
+                    Some(description) => expand(description),
+                    None => format!("ilo:{}", hostname),
+                };
+                let mut command = process::Command::new("keyctl");
+                command.arg("pipe").arg(format!("%user:{}", description));
+                Credentials {
+                    username: None,
+                    password: first_line(&run(command)?).to_string(),
+                }
+            }
+        };
+        if credentials.password.is_empty() {
+            return Err(Error::Empty {
+                hostname: hostname.to_string(),
+            });
+        }
+        event!(Level::DEBUG, source = ?self, "credentials resolved");
+        Ok(Some(credentials))
+    }
+}
+
+fn env_var(name: &str) -> Result<String, Error> {
+    env::var(name).map_err(|_| Error::MissingEnv {
+        name: name.to_string(),
+    })
+}
+
+fn first_line(output: &str) -> &str {
+    output.lines().next().unwrap_or_default()
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> process::Command {
+    let mut shell = process::Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+/// Run a command returning what it printed on stdout
+fn run(mut command: process::Command) -> Result<String, Error> {
+    let description = format!("{:?}", command);
+    let output = command
+        .stdin(process::Stdio::inherit())
+        .output()
+        .map_err(|source| Error::Spawn {
+            command: description.clone(),
+            source,
+        })?;
+    if !output.status.success() {
+        return Err(Error::CommandFailed {
+            command: description,
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    String::from_utf8(output.stdout).map_err(|_| Error::InvalidUtf8 {
+        command: description,
+    })
+}
+
+fn default_netrc() -> Result<PathBuf, Error> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".netrc"))
+        .ok_or(Error::NoHome)
+}
+
+/// Read the entry for `hostname` from a netrc file, falling back to the
+/// `default` entry
+fn netrc(path: &Path, hostname: &str) -> Result<Credentials, Error> {
+    let contents = fs::read_to_string(path).map_err(|source| Error::Netrc {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    parse_netrc(&contents, hostname).ok_or_else(|| Error::NoNetrcEntry {
+        path: path.to_path_buf(),
+        hostname: hostname.to_string(),
+    })
+}
+
+fn parse_netrc(contents: &str, hostname: &str) -> Option<Credentials> {
+    let mut tokens = contents.split_whitespace();
+    let mut machine: Option<String> = None;
+    let mut login = None;
+    let mut password = None;
+    let mut default = None;
+    let mut finish = |machine: Option<String>, login: Option<&str>, password: Option<&str>| {
+        let credentials = password.map(|password| Credentials {
+            username: login.map(String::from),
+            password: password.to_string(),
+        });
+        match machine.as_deref() {
+            Some(machine) if machine == hostname => credentials,
+            None if default.is_none() => {
+                default = credentials;
+                None
+            }
+            _ => None,
+        }
+    };
+    let mut in_entry = false;
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" | "default" => {
+                if in_entry {
+                    if let Some(credentials) = finish(machine.take(), login, password) {
+                        return Some(credentials);
+                    }
+                }
+                in_entry = true;
+                machine = if token == "machine" {
+                    tokens.next().map(String::from)
+                } else {
+                    None
+                };
+                login = None;
+                password = None;
+            }
+            "login" => login = tokens.next(),
+            "password" => password = tokens.next(),
+            "account" => {
+                tokens.next();
+            }
+            // macros aren't supported, stop at the first one
+            "macdef" => break,
+            _ => {}
+        }
+    }
+    if in_entry {
+        if let Some(credentials) = finish(machine, login, password) {
+            return Some(credentials);
+        }
+    }
+    default
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn netrc_matches_machine_then_default() {
+        let netrc = "machine ilo1 login admin password one\n\
+                     default login root password fallback\n\
+                     machine ilo2\n  login operator\n  password two\n";
+        let ilo2 = parse_netrc(netrc, "ilo2").unwrap();
+        assert_eq!(ilo2.username.as_deref(), Some("operator"));
+        assert_eq!(ilo2.password, "two");
+        assert_eq!(parse_netrc(netrc, "ilo3").unwrap().password, "fallback");
+    }
+}
@@ -0,0 +1,355 @@
+//! HTTP/1.1 requests to a node's web server.
+//!
+//! Requests are written to connections opened with [TlsPolicy::connect], so the
+//! certificate checked against the policy is the one presented on the
+//! connection the request is sent on.  HTTP clients such as reqwest don't expose
+//! the certificate of their connections, which leaves a pinned fingerprint
+//! unchecked.
+use native_tls::TlsStream;
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::{event, instrument, Level};
+
+use crate::{proxy::Proxy, tls, tls::TlsPolicy};
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("couldn't connect: {0}")]
+    Tls(#[from] tls::Error),
+    #[error("couldn't send the request: {0}")]
+    Write(#[source] io::Error),
+    #[error("couldn't read the response: {0}")]
+    Read(#[source] io::Error),
+    #[error("connection closed by node before a response was received")]
+    Closed,
+    #[error("invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl Error {
+    /// Returns true if a socket timeout expired
+    pub fn is_timeout(&self) -> bool {
+        let err = match self {
+            Error::Write(err) | Error::Read(err) => err,
+            Error::Tls(tls::Error::Io(err)) => err,
+            _ => return false,
+        };
+        matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        )
+    }
+}
+
+/// A request for a path on the node
+#[derive(Debug, Clone, Copy)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    /// The path and query, e.g. `/xmldata?item=All`
+    pub path: &'a str,
+    pub headers: &'a [(&'a str, &'a str)],
+    pub body: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    pub fn get(path: &'a str) -> Self {
+        Self {
+            method: "GET",
+            path,
+            headers: &[],
+            body: &[],
+        }
+    }
+
+    pub fn post(path: &'a str, body: &'a [u8]) -> Self {
+        Self {
+            method: "POST",
+            path,
+            headers: &[],
+            body,
+        }
+    }
+
+    pub fn headers(self, headers: &'a [(&'a str, &'a str)]) -> Self {
+        Self { headers, ..self }
+    }
+
+    /// Write the request, `host` is the value of the Host header
+    pub fn write<W: Write>(&self, writer: &mut W, host: &str, keep_alive: bool) -> io::Result<()> {
+        let mut head = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: {}\r\n",
+            self.method,
+            self.path,
+            host,
+            if keep_alive { "keep-alive" } else { "close" }
+        );
+        for (name, value) in self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        if !self.body.is_empty() || self.method == "POST" {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        writer.write_all(head.as_bytes())?;
+        writer.write_all(self.body)?;
+        writer.flush()
+    }
+}
+
+/// A response from the node
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// The node allows further requests on the connection
+    pub keep_alive: bool,
+}
+
+impl Response {
+    /// The values of the headers called `name`
+    pub fn header<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns true for a 2xx status
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    /// The body, with invalid utf8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+/// Read a response framed by its Content-Length, chunked encoding or the end of
+/// the connection
+pub fn read_response<R: Read>(reader: &mut R) -> Result<Response, Error> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    if reader.read_line(&mut line).map_err(Error::Read)? == 0 {
+        return Err(Error::Closed);
+    }
+    let mut status_line = line.split_whitespace();
+    let version = status_line.next().unwrap_or_default().to_string();
+    let status = status_line
+        .next()
+        .and_then(|status| status.parse().ok())
+        .filter(|_| version.starts_with("HTTP/1."))
+        .ok_or_else(|| Error::InvalidResponse(line.trim().to_string()))?;
+
+    let mut headers = vec![];
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(Error::Read)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| Error::InvalidResponse(header.to_string()))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    let mut response = Response {
+        status,
+        headers,
+        body: vec![],
+        keep_alive: version != "HTTP/1.0",
+    };
+    let connection = response
+        .header("connection")
+        .next()
+        .map(str::to_ascii_lowercase);
+    if let Some(connection) = connection {
+        response.keep_alive =
+            connection != "close" && (version != "HTTP/1.0" || connection == "keep-alive");
+    }
+
+    let chunked = response
+        .header("transfer-encoding")
+        .any(|encoding| encoding.to_ascii_lowercase().contains("chunked"));
+    let length = response
+        .header("content-length")
+        .next()
+        .map(|length| {
+            length
+                .parse::<usize>()
+                .map_err(|_| Error::InvalidResponse(format!("Content-Length: {}", length)))
+        })
+        .transpose()?;
+    if chunked {
+        response.body = read_chunked(&mut reader)?;
+    } else if let Some(length) = length {
+        response.body = vec![0; length];
+        reader.read_exact(&mut response.body).map_err(Error::Read)?;
+    } else if !(status == 204 || status == 304 || (100..200).contains(&status)) {
+        reader
+            .read_to_end(&mut response.body)
+            .map_err(Error::Read)?;
+        response.keep_alive = false;
+    }
+    Ok(response)
+}
+
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, Error> {
+    let mut body = vec![];
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line).map_err(Error::Read)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)
+            .map_err(|_| Error::InvalidResponse(format!("chunk size {:?}", size)))?;
+        if size == 0 {
+            // skip any trailers
+            loop {
+                line.clear();
+                if reader.read_line(&mut line).map_err(Error::Read)? == 0 || line.trim().is_empty()
+                {
+                    return Ok(body);
+                }
+            }
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..]).map_err(Error::Read)?;
+        line.clear();
+        reader.read_line(&mut line).map_err(Error::Read)?;
+    }
+}
+
+/// The Host header for a node, IPv6 addresses are bracketed
+pub fn host_header(host: &str, port: u16) -> String {
+    let host = if host.contains(':') {
+        format!("[{}]", host)
+    } else {
+        host.to_string()
+    };
+    if port == 443 {
+        host
+    } else {
+        format!("{}:{}", host, port)
+    }
+}
+
+/// Returns true if a kept open connection can be reused, the node hasn't closed
+/// it or sent anything since the last response
+pub fn is_reusable(stream: &TlsStream<TcpStream>) -> bool {
+    let socket = stream.get_ref();
+    if socket.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let idle = matches!(
+        socket.peek(&mut [0; 1]),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock
+    );
+    socket.set_nonblocking(false).is_ok() && idle
+}
+
+/// Write a request on an open connection and read the response, the timeout
+/// applies to each read and write
+pub fn exchange(
+    stream: &mut TlsStream<TcpStream>,
+    host: &str,
+    request: &Request<'_>,
+    keep_alive: bool,
+    timeout: Option<Duration>,
+) -> Result<Response, Error> {
+    stream
+        .get_ref()
+        .set_write_timeout(timeout)
+        .map_err(Error::Write)?;
+    stream
+        .get_ref()
+        .set_read_timeout(timeout)
+        .map_err(Error::Read)?;
+    request
+        .write(stream, host, keep_alive)
+        .map_err(Error::Write)?;
+    let mut response = read_response(stream)?;
+    response.keep_alive &= keep_alive;
+    Ok(response)
+}
+
+/// Send a request to the node on a new connection, checking its certificate
+/// against the policy and recording the fingerprint on first use
+#[instrument(skip(tls, proxy, request), fields(method = request.method, path = request.path))]
+pub fn send(
+    tls: &mut TlsPolicy,
+    proxy: Option<&Proxy>,
+    host: &str,
+    port: u16,
+    request: &Request<'_>,
+    timeout: Option<Duration>,
+) -> Result<Response, Error> {
+    let mut stream = tls.connect(host, port, timeout, proxy)?;
+    let response = exchange(
+        &mut stream,
+        &host_header(host, port),
+        request,
+        false,
+        timeout,
+    )?;
+    event!(Level::DEBUG, status = response.status);
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_are_written_with_host_and_length() {
+        let mut written = vec![];
+        Request::post("/ribcl", b"<ribcl/>")
+            .headers(&[("Cookie", "session=1")])
+            .write(&mut written, &host_header("fe80::1", 8443), true)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(written).unwrap(),
+            "POST /ribcl HTTP/1.1\r\nHost: [fe80::1]:8443\r\nConnection: keep-alive\r\n\
+             Cookie: session=1\r\nContent-Length: 8\r\n\r\n<ribcl/>"
+        );
+    }
+
+    #[test]
+    fn responses_are_framed_by_length_chunks_or_close() {
+        let mut sized: &[u8] =
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nSet-Cookie: a=1\r\n\r\nhello";
+        let response = read_response(&mut sized).unwrap();
+        assert_eq!(
+            (response.status, response.text()),
+            (200, String::from("hello"))
+        );
+        assert_eq!(response.header("set-cookie").collect::<Vec<_>>(), ["a=1"]);
+        assert!(response.keep_alive);
+
+        let mut chunked: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\nhell\r\n1;ext\r\no\r\n0\r\n\r\n";
+        assert_eq!(read_response(&mut chunked).unwrap().text(), "hello");
+
+        let mut closed: &[u8] = b"HTTP/1.0 404 Not Found\r\n\r\nmissing";
+        let response = read_response(&mut closed).unwrap();
+        assert_eq!(
+            (response.status, response.text()),
+            (404, String::from("missing"))
+        );
+        assert!(!response.keep_alive && !response.is_success());
+
+        assert!(matches!(read_response(&mut &b""[..]), Err(Error::Closed)));
+        assert!(matches!(
+            read_response(&mut &b"<RIBCL>"[..]),
+            Err(Error::InvalidResponse(_))
+        ));
+    }
+}
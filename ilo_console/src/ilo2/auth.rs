@@ -1,51 +1,116 @@
 extern crate base64;
-extern crate reqwest;
 
-use reqwest::header::HeaderMap;
-use serde::{Deserialize, Serialize};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
 use std::{default::Default, time::Duration};
 use thiserror::Error;
-use tokio::time::delay_for;
+use tokio::{task, time::delay_for};
 use tracing::{event, instrument, Level};
 
-use crate::{find, ilo2::session, ilo2::session::Parameters};
+use crate::{
+    credentials, credentials::CredentialSource, find, https, ilo2::session,
+    ilo2::session::Parameters, proxy, proxy::Proxy, tls::TlsPolicy,
+};
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Headers sent with every request, as Internet Explorer sends them
+const HEADERS: &[(&str, &str)] = &[
+    ("Accept", "text/html, application/xhtml+xml, image/jxr, */*"),
+    ("Accept-Language", "un-US"),
+    (
+        "User-Agent",
+        "Mozilla/5.0 (Windows NT 10.0; WOW64; Trident/7.0; rv:11.0) like Gecko",
+    ),
+];
+
+#[derive(Deserialize, Clone, Default)]
 pub struct Auth {
-    #[serde(skip, default = "client_default")]
-    client: Option<reqwest::Client>,
     #[serde(skip)]
     parameters: Option<Parameters>,
     #[serde(skip)]
     config_file: String,
+    /// DNS name, IPv4 address or IPv6 address, optionally in brackets
     pub hostname: String,
+    /// HTTPS port when the node isn't reachable on 443, e.g. through port forwarding
+    #[serde(default)]
+    pub https_port: Option<u16>,
+    /// Remote console port, overriding the one announced by the node
+    #[serde(default)]
+    pub console_port: Option<u16>,
+    #[serde(default)]
     pub username: String,
+    #[serde(default)]
     pub password: String,
+    /// Where the username and password are read from when they aren't stored
+    /// in the endpoint file
+    #[serde(default)]
+    pub credentials: CredentialSource,
     #[serde(default)]
     session_index: String,
     #[serde(default)]
     session_key: String,
     #[serde(default)]
     cookie: String,
+    #[serde(default)]
+    pub tls: TlsPolicy,
+    /// Proxy used for every connection to the node
+    #[serde(default)]
+    pub proxy: Option<Proxy>,
 }
 
-fn client_default() -> Option<reqwest::Client> {
-    None
+/// Shown in place of secrets when an [Auth] is logged
+const REDACTED: &str = "********";
+
+fn redacted(secret: &str) -> &str {
+    if secret.is_empty() {
+        secret
+    } else {
+        REDACTED
+    }
 }
 
-impl Default for Auth {
-    fn default() -> Self {
-        Auth {
-            client: client_default(),
-            parameters: None,
-            config_file: Default::default(),
-            hostname: Default::default(),
-            username: Default::default(),
-            password: Default::default(),
-            session_index: Default::default(),
-            session_key: Default::default(),
-            cookie: Default::default(),
+// Auth is logged by instrumented functions so secrets are left out
+impl std::fmt::Debug for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Auth")
+            .field("hostname", &self.hostname)
+            .field("https_port", &self.https_port)
+            .field("console_port", &self.console_port)
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("credentials", &self.credentials)
+            .field("session_index", &self.session_index)
+            .field("session_key", &redacted(&self.session_key))
+            .field("cookie", &redacted(&self.cookie))
+            .field("tls", &self.tls)
+            .field("proxy", &self.proxy)
+            .finish()
+    }
+}
+
+// The password is only written back when it's stored in the endpoint file
+impl Serialize for Auth {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Auth", 11)?;
+        state.serialize_field("hostname", &self.hostname)?;
+        if let Some(https_port) = &self.https_port {
+            state.serialize_field("https_port", https_port)?;
+        }
+        if let Some(console_port) = &self.console_port {
+            state.serialize_field("console_port", console_port)?;
+        }
+        state.serialize_field("username", &self.username)?;
+        if self.credentials.is_inline() {
+            state.serialize_field("password", &self.password)?;
+        } else {
+            state.serialize_field("credentials", &self.credentials)?;
         }
+        state.serialize_field("session_index", &self.session_index)?;
+        state.serialize_field("session_key", &self.session_key)?;
+        state.serialize_field("cookie", &self.cookie)?;
+        state.serialize_field("tls", &self.tls)?;
+        if let Some(proxy) = &self.proxy {
+            state.serialize_field("proxy", proxy)?;
+        }
+        state.end()
     }
 }
 
@@ -53,7 +118,7 @@ impl Default for Auth {
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("error requesting page from ilo node: `{0}`")]
-    Reqwest(#[from] reqwest::Error),
+    Https(#[from] https::Error),
     #[error("error searching for value in web page: `{0}`")]
     Find(#[from] find::Error),
     #[error("error ocurred loading session config: `{0}`")]
@@ -62,60 +127,89 @@ pub enum Error {
     Session(#[from] session::Error),
     #[error("failed to connect to node")]
     ConnectionFailed,
-    #[error("client not loaded")]
-    ClientNotLoaded,
+    #[error("blocking task failed: `{0}`")]
+    Join(#[from] task::JoinError),
+    #[error("couldn't read credentials: `{0}`")]
+    Credentials(#[from] credentials::Error),
+    #[error("invalid proxy: `{0}`")]
+    Proxy(#[from] proxy::Error),
 }
 
 impl Auth {
-    fn client(&mut self) -> &mut Option<reqwest::Client> {
-        if self.client.is_none() {
-            // set default headers
-            let mut headers = HeaderMap::new();
-            {
-                use reqwest::header::{HeaderValue, ACCEPT, ACCEPT_LANGUAGE, CONNECTION};
-                headers.insert(
-                    ACCEPT,
-                    HeaderValue::from_static("text/html, application/xhtml+xml, image/jxr, */*"),
-                );
-                headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("un-US"));
-                headers.insert(CONNECTION, HeaderValue::from_static("Keep-Alive"));
+    /// Request a page from the node on the blocking thread pool, checking its
+    /// certificate and recording the fingerprint on first use
+    async fn get(&mut self, path: &str, cookie: Option<String>) -> Result<https::Response, Error> {
+        let mut tls = self.tls.clone();
+        let proxy = self.proxy.clone();
+        let host = self.host().to_string();
+        let port = self.https_port();
+        let path = path.to_string();
+        let (tls, response) = task::spawn_blocking(move || {
+            let mut headers = HEADERS.to_vec();
+            if let Some(cookie) = &cookie {
+                headers.push(("Cookie", cookie));
             }
+            let request = https::Request::get(&path).headers(&headers);
+            let response = https::send(&mut tls, proxy.as_ref(), &host, port, &request, None);
+            (tls, response)
+        })
+        .await?;
+        self.tls = tls;
+        Ok(response?)
+    }
+
+    /// The hostname or address to connect to, IPv6 addresses are returned
+    /// without brackets
+    pub fn host(&self) -> &str {
+        self.hostname
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(&self.hostname)
+    }
 
-            // setup http client
-            let client = reqwest::Client::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .use_native_tls()
-                .user_agent("Mozilla/5.0 (Windows NT 10.0; WOW64; Trident/7.0; rv:11.0) like Gecko")
-                .default_headers(headers)
-                .cookie_store(true)
-                .build()
-                .unwrap();
-            self.client = Some(client);
+    /// The HTTPS port, 443 unless overridden
+    pub fn https_port(&self) -> u16 {
+        self.https_port.unwrap_or(443)
+    }
+
+    /// The url of `path` on the node's web server
+    pub fn url(&self, path: &str) -> String {
+        let host = self.host();
+        if host.contains(':') {
+            format!("https://[{}]:{}{}", host, self.https_port(), path)
+        } else {
+            format!("https://{}:{}{}", host, self.https_port(), path)
+        }
+    }
+
+    /// Read the username and password from the credential source, they're
+    /// left unchanged for inline credentials
+    pub fn resolve_credentials(&mut self) -> Result<(), credentials::Error> {
+        if let Some(credentials) = self.credentials.resolve(&self.hostname, &self.username)? {
+            if let Some(username) = credentials.username {
+                self.username = username;
+            }
+            self.password = credentials.password;
         }
-        &mut self.client
+        Ok(())
     }
 
     #[instrument(skip(self))]
     pub async fn authenticate(&mut self) -> Result<(), Error> {
+        if self.password.is_empty() {
+            // password commands may prompt or wait on an agent
+            let mut auth = self.clone();
+            *self =
+                task::spawn_blocking(move || auth.resolve_credentials().map(|_| auth)).await??;
+        }
         if self.cookie.is_empty() {
             event!(Level::INFO, "no cookie found generating cookie");
             self.generate_cookie().await?;
         } else {
             // get session key and index
             event!(Level::INFO, "cookie found testing validity");
-            let url = format!("https://{}/ie_index.htm", &self.hostname);
-            let body = if let Some(client) = &mut self.client() {
-                client
-                    .get(&url)
-                    .header(reqwest::header::COOKIE, &self.cookie)
-                    .send()
-                    .await?
-                    .text()
-                    .await?
-            } else {
-                return Err(Error::ClientNotLoaded);
-            };
+            let cookie = self.cookie.clone();
+            let body = self.get("/ie_index.htm", Some(cookie)).await?.text();
             if find::has(r#"Login Delay"#, &body)?
                 || find::has(r#"Integrated Lights-Out 2 Login"#, &body)?
             {
@@ -141,21 +235,12 @@ impl Auth {
     #[instrument]
     async fn load_parameters(&mut self) -> Result<(), Error> {
         let mut body;
-        let url = format!("https://{}/drc2fram.htm?restart=1", &self.hostname);
         loop {
-            body = if let Some(client) = &mut self.client() {
-                client
-                    .get(&url)
-                    .header(reqwest::header::COOKIE, &self.cookie)
-                    .send()
-                    .await
-                    .unwrap()
-                    .text()
-                    .await
-                    .unwrap()
-            } else {
-                return Err(Error::ClientNotLoaded);
-            };
+            let cookie = self.cookie.clone();
+            body = self
+                .get("/drc2fram.htm?restart=1", Some(cookie))
+                .await?
+                .text();
             event!(Level::TRACE, ?body);
             if find::has(
                 r#"The Remote Console is unavailable, it is already in use by a different client."#,
@@ -167,20 +252,19 @@ impl Auth {
                 break;
             }
         }
-        self.parameters = Some(Parameters::try_from(&body, self.hostname.clone())?);
+        let mut parameters = Parameters::try_from(&body, self.host().to_string())?;
+        if let Some(port) = self.console_port {
+            parameters.set_port(port);
+        }
+        self.parameters = Some(parameters);
         Ok(())
     }
 
     #[instrument(skip(self))]
     async fn generate_cookie(&mut self) -> Result<(), Error> {
         // get session key and index
-        let url = format!("https://{}/index.htm", &self.hostname);
         loop {
-            let body = if let Some(client) = &mut self.client() {
-                client.get(&url).send().await?.text().await?
-            } else {
-                return Err(Error::ClientNotLoaded);
-            };
+            let body = self.get("/index.htm", None).await?.text();
             let session_key = find::by_regex(r#"var sessionkey="([^"]+)";"#, &body)?.to_string();
             if session_key != "NONEAVAILABLE" {
                 self.session_key = session_key;
@@ -195,42 +279,46 @@ impl Auth {
             );
             delay_for(Duration::from_millis(1000)).await;
         }
-        event!(Level::DEBUG, ?self.session_key, ?self.session_index);
+        event!(Level::DEBUG, ?self.session_index, "session key found");
 
         // step 2
-        let login_url = format!("https://{}/index.htm", self.hostname);
-        let res = if let Some(client) = &mut self.client() {
-            client
-                .get(&login_url)
-                .header(
-                    reqwest::header::COOKIE,
-                    format!(
-                        "hp-iLO-Login={}:{}:{}:{}",
-                        self.session_index,
-                        base64::encode(self.username.as_bytes()),
-                        base64::encode(self.password.as_bytes()),
-                        self.session_key
-                    ),
-                )
-                .send()
-                .await
-                .unwrap()
-        } else {
-            return Err(Error::ClientNotLoaded);
-        };
-        for cookie in res.headers().get_all("Set-Cookie").iter() {
-            event!(Level::TRACE, ?cookie);
+        let login = format!(
+            "hp-iLO-Login={}:{}:{}:{}",
+            self.session_index,
+            base64::encode(self.username.as_bytes()),
+            base64::encode(self.password.as_bytes()),
+            self.session_key
+        );
+        let res = self.get("/index.htm", Some(login)).await?;
+        for cookie in res.header("Set-Cookie") {
+            let name = cookie.split('=').next();
+            event!(Level::TRACE, ?name, "cookie set");
         }
         let session_cookie = res
-            .headers()
-            .get_all("Set-Cookie")
-            .iter()
-            .map(|c| c.to_str().unwrap_or(""))
+            .header("Set-Cookie")
             .find(|c| c.starts_with("hp-iLO-Session="))
-            .unwrap();
-        event!(Level::DEBUG, session_cookie);
+            .ok_or(Error::ConnectionFailed)?;
+        event!(Level::DEBUG, "session cookie found");
         self.cookie = String::from(session_cookie);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn urls_bracket_ipv6_and_use_port_override() {
+        let mut auth = Auth {
+            hostname: String::from("[fe80::1]"),
+            ..Default::default()
+        };
+        assert_eq!(auth.host(), "fe80::1");
+        assert_eq!(auth.url("/xmldata"), "https://[fe80::1]:443/xmldata");
+        auth.hostname = String::from("ilo.example.com");
+        auth.https_port = Some(8443);
+        assert_eq!(auth.url("/ribcl"), "https://ilo.example.com:8443/ribcl");
+    }
+}
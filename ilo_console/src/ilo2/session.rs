@@ -53,6 +53,11 @@ impl Parameters {
         })
     }
 
+    /// Override the remote console port announced by the node
+    pub fn set_port(&mut self, port: u16) {
+        self.info6 = port.to_string();
+    }
+
     #[instrument]
     fn find<'a>(sym: &str, body: &'a str, wrapped: bool) -> Result<String, ParameterError> {
         let rxp = if wrapped {
@@ -117,7 +122,7 @@ pub enum Error {
 }
 
 impl Session {
-    #[instrument]
+    #[instrument(skip(info0))]
     fn parse_login(info0: &str) -> Result<String, Error> {
         let rxp = Regex::new(r#"Compaq-RIB-Login=(.{56})(.{32})"#).unwrap();
         use Error::*;
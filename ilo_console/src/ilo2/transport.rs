@@ -1,4 +1,4 @@
-use crate::{dvc, gui, ilo2::session::Session, rc4::Rc4, transport::Event};
+use crate::{dvc, gui, ilo2::session::Session, proxy, proxy::Proxy, rc4::Rc4, transport::Event};
 use crossbeam_channel::{Receiver, Sender, TryRecvError};
 use std::io;
 use thiserror::Error;
@@ -17,6 +17,7 @@ pub struct Transport {
     stream: Option<TcpStream>,
     decryptor: Option<Rc4>,
     encrypter: Option<Rc4>,
+    proxy: Option<Proxy>,
     pub dvc_mode: bool,
     pub dvc_encryption: bool,
 }
@@ -26,6 +27,8 @@ pub struct Transport {
 pub enum Error {
     #[error("an io error occurred")]
     IoError(#[from] io::Error),
+    #[error("couldn't connect through proxy: {0}")]
+    Proxy(#[from] proxy::Error),
 }
 #[derive(PartialEq)]
 enum Modifier {
@@ -36,7 +39,7 @@ enum Modifier {
 }
 
 impl Transport {
-    #[instrument]
+    #[instrument(skip(session))]
     pub fn new(
         session: Session,
         transport_rx: Receiver<Event>,
@@ -48,12 +51,18 @@ impl Transport {
             stream: None,
             encrypter: Some(Rc4::new(&session.encrypt_key)),
             decryptor: Some(Rc4::new(&session.decrypt_key)),
+            proxy: None,
             session,
             dvc_mode: false,
             dvc_encryption: false,
         }
     }
 
+    /// Connect to the remote console through a proxy
+    pub fn set_proxy(&mut self, proxy: Option<Proxy>) {
+        self.proxy = proxy;
+    }
+
     #[instrument(skip(self))]
     pub async fn key_typed(&mut self, key: String) -> Result<(), Error> {
         let data = Self::translate_key(&key);
@@ -237,9 +246,23 @@ impl Transport {
     pub async fn connect(&mut self) -> Result<(), Error> {
         match self.stream {
             None => {
-                let mut stream =
-                    TcpStream::connect(format!("{}:{}", self.session.host, self.session.port))
-                        .await?;
+                let mut stream = match &self.proxy {
+                    Some(proxy) => {
+                        let proxy = proxy.clone();
+                        let host = self.session.host.clone();
+                        let port = self.session.port as u16;
+                        let stream =
+                            tokio::task::spawn_blocking(move || proxy.connect(&host, port, None))
+                                .await
+                                .map_err(io::Error::other)??;
+                        stream.set_nonblocking(true)?;
+                        TcpStream::from_std(stream)?
+                    }
+                    None => {
+                        TcpStream::connect((self.session.host.as_str(), self.session.port as u16))
+                            .await?
+                    }
+                };
                 stream.set_linger(None).unwrap_or_else(|_e| {
                     event!(Level::WARN, "Failed to set linger on socket");
                 });
@@ -1,6 +1,10 @@
+pub mod credentials;
 pub mod dvc;
 mod find;
 pub mod gui;
+pub mod https;
 pub mod ilo2;
+pub mod proxy;
 pub mod rc4;
+pub mod tls;
 pub mod transport;
@@ -0,0 +1,309 @@
+use crypto::{digest::Digest, sha2::Sha256};
+use native_tls::{Certificate, TlsConnector, TlsStream};
+use serde::{Deserialize, Serialize};
+use std::{fs, io, net::TcpStream, path::PathBuf, time::Duration};
+use thiserror::Error;
+use tracing::{event, instrument, Level};
+
+use crate::{proxy, proxy::Proxy};
+
+/// How the certificate presented by an iLO is trusted
+///
+/// ```json
+/// { "mode": "pinned", "sha256": "AB:CD:..." }
+/// { "mode": "ca_bundle", "path": "/etc/ilo/ca.pem" }
+/// { "mode": "insecure" }
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum TlsPolicy {
+    /// Only accept a certificate with the given SHA-256 fingerprint, when no
+    /// fingerprint is set the first certificate seen is trusted and recorded.
+    Pinned {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sha256: Option<String>,
+    },
+    /// Verify the certificate chain against the CA certificates in a PEM file
+    CaBundle { path: PathBuf },
+    /// Accept any certificate
+    Insecure,
+}
+
+impl Default for TlsPolicy {
+    fn default() -> Self {
+        TlsPolicy::Pinned { sha256: None }
+    }
+}
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("certificate fingerprint {found} does not match pinned fingerprint {expected}")]
+    FingerprintMismatch { expected: String, found: String },
+    #[error("node did not present a certificate")]
+    NoCertificate,
+    #[error("couldn't load CA bundle {path:?}: {source}")]
+    CaBundle { path: PathBuf, source: io::Error },
+    #[error("tls error: {0}")]
+    Tls(#[from] native_tls::Error),
+    #[error("tls handshake failed: {0}")]
+    Handshake(String),
+    #[error("connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("proxy error: {0}")]
+    Proxy(#[from] proxy::Error),
+}
+
+/// Returns the SHA-256 fingerprint of a DER encoded certificate as colon separated hex
+pub fn fingerprint(der: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(der);
+    let mut digest = [0u8; 32];
+    hasher.result(&mut digest);
+    digest
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn normalize(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|c| c.is_ascii_hexdigit())
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+impl TlsPolicy {
+    /// Returns true when the policy is waiting to record a fingerprint on first use
+    pub fn needs_fingerprint(&self) -> bool {
+        matches!(self, TlsPolicy::Pinned { sha256: None })
+    }
+
+    /// Load every certificate in a CA bundle
+    fn ca_bundle(path: &PathBuf) -> Result<Vec<Certificate>, Error> {
+        let error = |source| Error::CaBundle {
+            path: path.clone(),
+            source,
+        };
+        let pem = fs::read(path).map_err(error)?;
+        let certificates = pem_certificates(&pem)
+            .into_iter()
+            .map(Certificate::from_pem)
+            .collect::<Result<Vec<_>, _>>()?;
+        if certificates.is_empty() {
+            return Err(error(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no certificates found",
+            )));
+        }
+        Ok(certificates)
+    }
+
+    /// Build a connector for this policy, pinned certificates are checked by
+    /// [TlsPolicy::verify] once the handshake completes rather than used as
+    /// trust anchors, which would reject certificates that aren't self-signed
+    /// and certificates that have expired.
+    pub fn connector(&self) -> Result<TlsConnector, Error> {
+        let mut builder = TlsConnector::builder();
+        match self {
+            TlsPolicy::CaBundle { path } => {
+                for certificate in Self::ca_bundle(path)? {
+                    builder.add_root_certificate(certificate);
+                }
+            }
+            TlsPolicy::Pinned { .. } | TlsPolicy::Insecure => {
+                builder.danger_accept_invalid_certs(true);
+            }
+        }
+        Ok(builder.build()?)
+    }
+
+    /// Check the certificate presented by the node, recording its fingerprint on first use
+    #[instrument(skip(certificate))]
+    pub fn verify(&mut self, certificate: Option<Certificate>) -> Result<(), Error> {
+        let sha256 = match self {
+            TlsPolicy::Pinned { sha256 } => sha256,
+            _ => return Ok(()),
+        };
+        let found = fingerprint(&certificate.ok_or(Error::NoCertificate)?.to_der()?);
+        match sha256 {
+            Some(expected) if normalize(expected) == normalize(&found) => Ok(()),
+            Some(expected) => Err(Error::FingerprintMismatch {
+                expected: expected.clone(),
+                found,
+            }),
+            None => {
+                event!(Level::INFO, fingerprint = %found, "trusting certificate on first use");
+                *sha256 = Some(found);
+                Ok(())
+            }
+        }
+    }
+
+    /// Open a TLS connection to the node, through the proxy when one is set, and
+    /// verify its certificate.  The timeout applies to each address connected to
+    /// and to reads and writes during the handshake
+    #[instrument(skip(self, proxy))]
+    pub fn connect(
+        &mut self,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+        proxy: Option<&Proxy>,
+    ) -> Result<TlsStream<TcpStream>, Error> {
+        let connector = self.connector()?;
+        let stream = proxy::connect(proxy, host, port, timeout)?;
+        stream.set_read_timeout(timeout)?;
+        stream.set_write_timeout(timeout)?;
+        let stream = connector
+            .connect(host, stream)
+            .map_err(|err| Error::Handshake(err.to_string()))?;
+        self.verify(stream.peer_certificate()?)?;
+        Ok(stream)
+    }
+
+    /// Verify the certificate of a pinned host, recording its fingerprint on first use
+    pub fn check_host(
+        &mut self,
+        host: &str,
+        port: u16,
+        timeout: Option<Duration>,
+        proxy: Option<&Proxy>,
+    ) -> Result<(), Error> {
+        if let TlsPolicy::Pinned { .. } = self {
+            self.connect(host, port, timeout, proxy)?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a PEM file into its certificates
+fn pem_certificates(pem: &[u8]) -> Vec<&[u8]> {
+    const END: &[u8] = b"-----END CERTIFICATE-----";
+    let mut certificates = vec![];
+    let mut rest = pem;
+    while let Some(index) = rest.windows(END.len()).position(|window| window == END) {
+        let (certificate, tail) = rest.split_at(index + END.len());
+        certificates.push(certificate);
+        rest = tail;
+    }
+    certificates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pinned_fingerprint_ignores_case_and_separators() {
+        let found = fingerprint(b"certificate");
+        let expected = found.replace(":", "").to_ascii_lowercase();
+        assert_eq!(normalize(&expected), normalize(&found));
+        assert_eq!(found.len(), 32 * 3 - 1);
+    }
+
+    #[test]
+    fn ca_bundle_is_split_into_certificates() {
+        let pem = b"-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n\
+                    -----BEGIN CERTIFICATE-----\nBBBB\n-----END CERTIFICATE-----\n";
+        let certificates = pem_certificates(pem);
+        assert_eq!(certificates.len(), 2);
+        assert!(certificates[1].ends_with(b"BBBB\n-----END CERTIFICATE-----"));
+        assert!(pem_certificates(b"not a certificate").is_empty());
+    }
+
+    /// Serve `connections` handshakes with an expired certificate signed by a CA,
+    /// as iLOs present a certificate from "Default Issuer (Do not trust)"
+    fn serve_signed_certificate(connections: usize) -> (u16, String) {
+        use native_tls::{Identity, TlsAcceptor};
+        use openssl::{
+            asn1::Asn1Time,
+            hash::MessageDigest,
+            pkcs12::Pkcs12,
+            pkey::PKey,
+            rsa::Rsa,
+            x509::{X509NameBuilder, X509},
+        };
+        use std::{net::TcpListener, thread};
+
+        let certificate = |subject: &str, key, issuer: Option<(&X509, &PKey<_>)>| {
+            let mut name = X509NameBuilder::new().unwrap();
+            name.append_entry_by_text("CN", subject).unwrap();
+            let name = name.build();
+            let mut builder = X509::builder().unwrap();
+            builder.set_version(2).unwrap();
+            builder.set_subject_name(&name).unwrap();
+            builder.set_pubkey(key).unwrap();
+            let not_before = Asn1Time::from_unix(1_262_304_000).unwrap();
+            let not_after = Asn1Time::from_unix(1_293_840_000).unwrap();
+            builder.set_not_before(&not_before).unwrap();
+            builder.set_not_after(&not_after).unwrap();
+            match issuer {
+                Some((issuer, issuer_key)) => {
+                    builder.set_issuer_name(issuer.subject_name()).unwrap();
+                    builder.sign(issuer_key, MessageDigest::sha256()).unwrap();
+                }
+                None => {
+                    builder.set_issuer_name(&name).unwrap();
+                    builder.sign(key, MessageDigest::sha256()).unwrap();
+                }
+            }
+            builder.build()
+        };
+        let ca_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let ca = certificate("Default Issuer (Do not trust)", &ca_key, None);
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let leaf = certificate("ilo.example.com", &key, Some((&ca, &ca_key)));
+        let pkcs12 = Pkcs12::builder()
+            .name("ilo")
+            .pkey(&key)
+            .cert(&leaf)
+            .build2("test")
+            .unwrap();
+        let identity = Identity::from_pkcs12(&pkcs12.to_der().unwrap(), "test").unwrap();
+        let acceptor = TlsAcceptor::new(identity).unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for stream in listener.incoming().take(connections) {
+                let _ = acceptor.accept(stream.unwrap());
+            }
+        });
+        (port, fingerprint(&leaf.to_der().unwrap()))
+    }
+
+    #[test]
+    fn pinned_certificates_are_checked_on_the_connection() {
+        let (port, found) = serve_signed_certificate(3);
+        let timeout = Some(Duration::from_secs(5));
+
+        let mut policy = TlsPolicy::default();
+        policy.connect("127.0.0.1", port, timeout, None).unwrap();
+        assert_eq!(
+            policy,
+            TlsPolicy::Pinned {
+                sha256: Some(found.clone())
+            }
+        );
+        // an expired certificate that isn't self-signed is accepted once pinned
+        policy.connect("127.0.0.1", port, timeout, None).unwrap();
+
+        let expected = fingerprint(b"another certificate");
+        let mut policy = TlsPolicy::Pinned {
+            sha256: Some(expected.clone()),
+        };
+        match policy.connect("127.0.0.1", port, timeout, None) {
+            Err(Error::FingerprintMismatch {
+                expected: mismatch,
+                found: presented,
+            }) => assert_eq!((mismatch, presented), (expected, found)),
+            result => panic!(
+                "expected a fingerprint mismatch, got {:?}",
+                result.map(|_| ())
+            ),
+        }
+    }
+}
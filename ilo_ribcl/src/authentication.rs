@@ -2,12 +2,16 @@ use crate::{
     client, commands,
     into_ribcl::IntoRibcl,
     ribcl_footer, ribcl_header, types,
-    types::{BoolBuilder, Certificate, CertificateBuilder, SimpleBuilder},
+    types::{BoolBuilder, Certificate, CertificateBuilder, SimpleBuilder, Version},
 };
 use ilo_ribcl_derive::{BuilderParse, WriteRibcl};
 use serde::Serialize;
 use serde_with::skip_serializing_none;
-use std::convert::TryInto;
+use std::{
+    convert::TryInto,
+    fmt::{self, Write},
+    str::FromStr,
+};
 
 //simple_builder_alias!(Login, String);
 pub type Login = String;
@@ -59,13 +63,145 @@ pub struct TwofactorSettings {
     pub import_user_certificate_user_login: Option<Login>,
 }
 
-impl client::Node {
-    #[tracing::instrument]
-    pub async fn import_ssh_key(&mut self, content: String) -> Result<String, commands::Error> {
-        // write
-        unimplemented!()
+/// Key types accepted by iLO 3 and iLO 4, iLO 2 only accepts DSA keys
+const SSH_KEY_ALGORITHMS: [&str; 2] = ["ssh-rsa", "ssh-dss"];
+
+/// An OpenSSH public key, as found on a line of an `authorized_keys` file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SshPublicKey {
+    pub algorithm: String,
+    /// The base64 encoded key
+    pub key: String,
+    pub comment: Option<String>,
+}
+
+impl SshPublicKey {
+    /// The SHA-256 fingerprint in the format shown by `ssh-keygen -l`
+    pub fn fingerprint(&self) -> String {
+        let blob = base64::decode(&self.key).unwrap_or_default();
+        format!(
+            "SHA256:{}",
+            base64::encode_config(openssl::sha::sha256(&blob), base64::STANDARD_NO_PAD)
+        )
     }
 
+    /// The key as `processor` imports it.  The iLO assigns the key to the
+    /// user named after it, iLO 2 also needs the key wrapped in PEM style
+    /// markers.
+    fn to_ribcl(&self, user_login: &str, processor: &Version) -> Result<String, commands::Error> {
+        if user_login.is_empty() || user_login.contains(char::is_whitespace) {
+            return Err(commands::Error::InvalidSshKey {
+                reason: format!("user login {:?} can't be written after the key", user_login),
+            });
+        }
+        let line = format!("{} {} {}", self.algorithm, self.key, user_login);
+        match processor {
+            Version::Ilo2 if self.algorithm != "ssh-dss" => Err(commands::Error::InvalidSshKey {
+                reason: format!("iLO 2 only accepts ssh-dss keys, not {}", self.algorithm),
+            }),
+            Version::Ilo2 => Ok(format!(
+                "-----BEGIN SSH KEY-----\r\n{}\r\n-----END SSH KEY-----",
+                line
+            )),
+            Version::Ilo3 | Version::Ilo4 | Version::Ilo5 => Ok(line),
+        }
+    }
+}
+
+impl FromStr for SshPublicKey {
+    type Err = commands::Error;
+
+    /// Parse an `authorized_keys` line, ignoring any options before the key type
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| commands::Error::InvalidSshKey { reason };
+        let mut fields = line.split_whitespace();
+        let algorithm = fields
+            .by_ref()
+            .find(|field| {
+                SSH_KEY_ALGORITHMS.contains(field)
+                    || field.starts_with("ssh-")
+                    || field.starts_with("ecdsa-")
+            })
+            .ok_or_else(|| invalid(String::from("no key type found")))?;
+        if !SSH_KEY_ALGORITHMS.contains(&algorithm) {
+            return Err(invalid(format!("{} keys aren't supported", algorithm)));
+        }
+        let key = fields
+            .next()
+            .ok_or_else(|| invalid(String::from("the key is missing")))?;
+        let blob = base64::decode(key).map_err(|err| invalid(err.to_string()))?;
+        // the key starts with its type as a length prefixed string
+        let encoded_algorithm = blob
+            .get(..4)
+            .map(|length| u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize)
+            .and_then(|length| blob.get(4..4 + length));
+        if encoded_algorithm != Some(algorithm.as_bytes()) {
+            return Err(invalid(format!("the key isn't an {} key", algorithm)));
+        }
+        let comment = fields.collect::<Vec<_>>().join(" ");
+        Ok(Self {
+            algorithm: algorithm.to_string(),
+            key: key.to_string(),
+            comment: Some(comment).filter(|comment| !comment.is_empty()),
+        })
+    }
+}
+
+impl fmt::Display for SshPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.algorithm, self.key)?;
+        if let Some(comment) = &self.comment {
+            write!(f, " {}", comment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse the keys in an `authorized_keys` file, skipping blank lines and comments
+pub fn parse_authorized_keys(content: &str) -> Result<Vec<SshPublicKey>, commands::Error> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::parse)
+        .collect()
+}
+
+impl client::Node {
+    mod_method!(
+        /// Authorize `key` to log in to the SSH command line as the existing user
+        /// `user_login`
+        rib_info.import_ssh_key(user_login: String, key: SshPublicKey)
+        => |request, node| {
+            // the key format differs between iLO versions, so the version must be known
+            let processor = node
+                .firmware()
+                .and_then(|firmware| firmware.management_processor)
+                .ok_or(commands::Error::NotSupported {
+                    requirements: "a known iL0 version",
+                })?;
+            let content = key.to_ribcl(&user_login, &processor)?;
+            ribcl_command_body!(request, import_ssh_key, {
+                write!(request, "\r\n{}\r\n", content)?;
+            });
+        }
+    );
+
+    mod_method!(
+        /// Remove the SSH keys authorized for a local user
+        user_info.del_users_ssh_key(user_login: String) : "mod_user",
+        "iL0 3 or iL0 4",
+        (Ilo3),
+        (Ilo4)
+        => |request| {
+            write!(
+                request,
+                "<mod_user user_login=\"{}\"><del_users_ssh_key/></mod_user>",
+                user_login.into_ribcl()?
+            )?;
+        }
+    );
+
     get_method!(
         /// Returns the iLO Two-Factor Authentication settings
         rib_info.get_twofactor_settings -> TwofactorSettings,
@@ -80,3 +216,82 @@ impl client::Node {
         (Ilo2, "1.10")
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a DSA key blob truncated after its type
+    const DSS_KEY: &str = "AAAAB3NzaC1kc3MAAACBAP8=";
+
+    #[test]
+    fn ssh_key_formats() {
+        let keys = parse_authorized_keys(&format!(
+            "# team keys\n\nno-pty ssh-dss {} alice laptop\nssh-ed25519 AAAA bob\n",
+            DSS_KEY
+        ));
+        assert!(matches!(keys, Err(commands::Error::InvalidSshKey { .. })));
+
+        let key: SshPublicKey = format!("no-pty ssh-dss {} alice laptop", DSS_KEY)
+            .parse()
+            .unwrap();
+        assert_eq!(key.algorithm, "ssh-dss");
+        assert_eq!(key.comment.as_deref(), Some("alice laptop"));
+        assert!(format!("ssh-rsa {}", DSS_KEY)
+            .parse::<SshPublicKey>()
+            .is_err());
+
+        assert_eq!(
+            key.to_ribcl("alice", &Version::Ilo4).unwrap(),
+            format!("ssh-dss {} alice", DSS_KEY)
+        );
+        assert_eq!(
+            key.to_ribcl("alice", &Version::Ilo2).unwrap(),
+            format!(
+                "-----BEGIN SSH KEY-----\r\nssh-dss {} alice\r\n-----END SSH KEY-----",
+                DSS_KEY
+            )
+        );
+        assert!(key.to_ribcl("alice smith", &Version::Ilo4).is_err());
+        let rsa = SshPublicKey {
+            algorithm: String::from("ssh-rsa"),
+            ..key
+        };
+        assert!(rsa.to_ribcl("alice", &Version::Ilo2).is_err());
+    }
+
+    #[test]
+    fn ssh_key_commands_are_sent_for_the_firmware() {
+        let key: SshPublicKey = format!("ssh-dss {} alice", DSS_KEY).parse().unwrap();
+        let firmware = types::FwVersion {
+            management_processor: Some(Version::Ilo2),
+            ..Default::default()
+        };
+        let node = client::Node::new_with_fw(Default::default(), firmware).unwrap();
+        let command = node
+            .import_ssh_key_command(String::from("alice"), key)
+            .unwrap();
+        assert!(command
+            .request
+            .body
+            .starts_with("<import_ssh_key>\r\n-----BEGIN SSH KEY-----"));
+        assert!(matches!(
+            node.del_users_ssh_key_command(String::from("alice")),
+            Err(commands::Error::NotSupported { .. })
+        ));
+
+        let firmware = types::FwVersion {
+            management_processor: Some(Version::Ilo4),
+            ..Default::default()
+        };
+        let node = client::Node::new_with_fw(Default::default(), firmware).unwrap();
+        let command = node
+            .del_users_ssh_key_command(String::from("alice"))
+            .unwrap();
+        assert_eq!(command.name(), "mod_user");
+        assert_eq!(
+            command.request.body,
+            "<mod_user user_login=\"alice\"><del_users_ssh_key/></mod_user>"
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{
+    client::Node,
+    commands::{self, Command, Request},
+    xml,
+};
+use std::fmt;
+use tracing::{event, instrument, Level};
+
+/// Commands queued to be sent to a node under a single login.
+///
+/// ```no_run
+/// # async fn run(node: &mut ilo_ribcl::client::Node) -> Result<(), ilo_ribcl::commands::Error> {
+/// use ilo_ribcl::client::Node;
+///
+/// let mut batch = node.batch();
+/// let firmware = batch.push(Node::get_fw_version_command)?;
+/// let power = batch.push(Node::get_host_power_status_command)?;
+/// let response = batch.send().await?;
+/// println!("{:?} {:?}", response.get(&firmware)?, response.get(&power)?);
+/// # Ok(())
+/// # }
+/// ```
+pub struct Batch<'a> {
+    node: &'a mut Node,
+    requests: Vec<Request>,
+}
+
+/// Refers to the result of a command queued in a [Batch]
+pub struct Handle<T> {
+    index: usize,
+    parse: fn(&str) -> Result<T, commands::Error>,
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle")
+            .field("index", &self.index)
+            .finish()
+    }
+}
+
+/// The response to all the commands sent in a [Batch]
+#[derive(Debug)]
+pub struct BatchResponse {
+    response: String,
+    documents: Vec<String>,
+}
+
+impl<'a> Batch<'a> {
+    pub(crate) fn new(node: &'a mut Node) -> Self {
+        Self {
+            node,
+            requests: vec![],
+        }
+    }
+
+    /// Queue a command built from the node, e.g. `Node::get_fw_version_command`
+    /// or `|node| node.mod_network_settings_command(settings)`.
+    pub fn push<T, F>(&mut self, command: F) -> Result<Handle<T>, commands::Error>
+    where
+        F: FnOnce(&Node) -> Result<Command<T>, commands::Error>,
+    {
+        let Command { request, parse, .. } = command(&*self.node)?;
+        self.requests.push(request);
+        Ok(Handle {
+            index: self.requests.len() - 1,
+            parse,
+        })
+    }
+
+    /// The number of queued commands
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// Returns true if no commands have been queued
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Send all queued commands in one RIBCL document
+    #[instrument(skip(self))]
+    pub async fn send(self) -> Result<BatchResponse, commands::Error> {
+        let mut request = String::new();
+        commands::write_document(&mut request, &self.node.auth(), &self.requests)?;
+        let response = self
+            .node
+            .send_requests(request.into_bytes(), &self.requests)
+            .await?;
+        BatchResponse::new(response, self.requests.len())
+    }
+}
+
+impl BatchResponse {
+    fn new(response: String, commands: usize) -> Result<Self, commands::Error> {
+        // the iLO replies with a document for the login followed by one per
+        // command, stopping at the first error status
+        let mut documents: Vec<String> = xml::documents(&response).map(String::from).collect();
+        if documents.len() != commands + 1 {
+            event!(
+                Level::DEBUG,
+                documents = documents.len(),
+                commands,
+                "response documents don't match commands"
+            );
+            // report the status the iLO stopped at, e.g. a failed login
+            for document in &documents {
+                xml::response_status(document)?;
+            }
+            return Err(commands::Error::ResponseCount {
+                documents: documents.len(),
+                commands,
+            });
+        }
+        documents.remove(0);
+        Ok(Self {
+            response,
+            documents,
+        })
+    }
+
+    /// Returns the result of a queued command
+    pub fn get<T>(&self, handle: &Handle<T>) -> Result<T, commands::Error> {
+        let document = self
+            .documents
+            .get(handle.index)
+            .ok_or(commands::Error::UnknownHandle {
+                index: handle.index,
+            })?;
+        (handle.parse)(document)
+    }
+
+    /// The raw response returned by the node
+    pub fn response(&self) -> &str {
+        &self.response
+    }
+}
+
+impl Node {
+    /// Start a batch of commands to send under a single login
+    pub fn batch(&mut self) -> Batch<'_> {
+        Batch::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::tests::response,
+        power::PowerStatus,
+        types::{FwVersion, Version},
+    };
+
+    fn power(value: &str) -> String {
+        response(
+            0,
+            "No error",
+            &format!(
+                "<GET_HOST_POWER\r\n    HOST_POWER=\"{}\"\r\n     />\r\n",
+                value
+            ),
+        )
+    }
+
+    #[test]
+    fn documents_are_matched_to_commands_after_the_login() {
+        let firmware = FwVersion {
+            management_processor: Some(Version::Ilo4),
+            ..Default::default()
+        };
+        let mut node = Node::new_with_fw(Default::default(), firmware).unwrap();
+        let mut batch = node.batch();
+        let before = batch.push(Node::get_host_power_status_command).unwrap();
+        batch
+            .push(|node| node.set_host_power_command(PowerStatus::Off))
+            .unwrap();
+        let after = batch.push(Node::get_host_power_status_command).unwrap();
+        let ok = response(0, "No error", "");
+        let documents = [ok.clone(), power("ON"), ok, power("OFF")];
+        let batch_response = BatchResponse::new(documents.concat(), batch.len()).unwrap();
+
+        assert_eq!(batch_response.get(&before).unwrap(), PowerStatus::On);
+        assert_eq!(batch_response.get(&after).unwrap(), PowerStatus::Off);
+        let unknown = Handle {
+            index: 3,
+            parse: before.parse,
+        };
+        assert!(matches!(
+            batch_response.get(&unknown),
+            Err(commands::Error::UnknownHandle { index: 3 })
+        ));
+
+        // a missing document would shift the results onto the wrong handles
+        assert!(matches!(
+            BatchResponse::new(documents[..3].concat(), batch.len()),
+            Err(commands::Error::ResponseCount {
+                documents: 3,
+                commands: 3
+            })
+        ));
+        assert!(matches!(
+            BatchResponse::new(response(0x005F, "Login failed.", ""), batch.len()),
+            Err(commands::Error::Ribcl { status: 0x005F, .. })
+        ));
+    }
+}
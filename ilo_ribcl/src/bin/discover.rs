@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Result};
+use ilo_console::{ilo2::auth::Auth, proxy::Proxy};
+use ilo_ribcl::discover::{Discovered, Discovery, Range};
+use std::{fs, path::PathBuf};
+use structopt::StructOpt;
+use tracing_subscriber::{filter::EnvFilter, FmtSubscriber};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "discover", about = "find iLO endpoints on a network")]
+struct Opt {
+    /// IPv4 networks in CIDR notation, e.g. 10.0.0.0/24, or single addresses
+    #[structopt(required = true)]
+    ranges: Vec<Range>,
+
+    /// Maximum number of addresses to query at once
+    #[structopt(short, long, default_value = "64")]
+    concurrency: usize,
+
+    /// Write an endpoint file named after each address found to this directory,
+    /// existing files are left unchanged
+    #[structopt(short, long, parse(from_os_str))]
+    output_dir: Option<PathBuf>,
+
+    /// Print the nodes found and their endpoints as json
+    #[structopt(short, long)]
+    json: bool,
+
+    /// HTTPS port to query instead of 443
+    #[structopt(long)]
+    https_port: Option<u16>,
+
+    /// Proxy to connect through, e.g. socks5h://jump-host:1080
+    #[structopt(long)]
+    proxy: Option<String>,
+}
+
+fn print_table(found: &[Discovered]) {
+    println!(
+        "{:15}  {:8}  {:8}  {:30}  SERIAL",
+        "ADDRESS", "MP", "FIRMWARE", "MODEL"
+    );
+    for node in found {
+        let firmware = node.firmware.as_ref();
+        let processor = firmware
+            .and_then(|firmware| firmware.management_processor.as_ref())
+            .map(|processor| format!("{:?}", processor))
+            .unwrap_or_else(|| String::from("unknown"));
+        let version = firmware
+            .and_then(|firmware| firmware.firmware_version.as_deref())
+            .unwrap_or("");
+        let server = node.rimp.server.as_ref();
+        let model = server
+            .and_then(|server| server.product_name.as_deref())
+            .unwrap_or("");
+        let serial = server
+            .and_then(|server| server.serial_number.as_deref())
+            .unwrap_or("");
+        println!(
+            "{:15}  {:8}  {:8}  {:30}  {}",
+            node.hostname,
+            processor,
+            version,
+            model,
+            serial.trim()
+        );
+    }
+    println!("{} found", found.len());
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    // setup tracing
+    let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("warn"))?;
+    let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let mut template = Auth::default();
+    template.https_port = opt.https_port;
+    template.proxy = opt.proxy.clone().map(|url| Proxy { url });
+    let found = Discovery::new(opt.concurrency)
+        .with_auth_template(template)
+        .scan(&opt.ranges)
+        .await;
+
+    if opt.json {
+        let nodes: Vec<_> = found
+            .iter()
+            .map(|node| {
+                serde_json::json!({
+                    "node": node,
+                    "endpoint": node.endpoint(),
+                })
+            })
+            .collect();
+        println!("{}", serde_json::to_string_pretty(&nodes)?);
+    } else {
+        print_table(&found);
+    }
+
+    if let Some(dir) = &opt.output_dir {
+        fs::create_dir_all(dir)?;
+        for node in &found {
+            let path = dir.join(format!("{}.json", node.hostname));
+            if path.exists() {
+                eprintln!("{} exists, not overwriting", path.display());
+                continue;
+            }
+            fs::write(&path, serde_json::to_string_pretty(&node.endpoint())?)?;
+        }
+    }
+
+    if found.is_empty() {
+        Err(anyhow!("no iLO endpoints found"))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::{anyhow, Result};
+use ilo_ribcl::{
+    fleet::{Fleet, Report},
+    metrics::Registry,
+    power::PowerStatus,
+};
+use serde::Serialize;
+use std::{fmt, fs, path::PathBuf, sync::Arc};
+use structopt::StructOpt;
+use tracing_subscriber::{filter::EnvFilter, FmtSubscriber};
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "fleet", about = "run a command across many endpoints")]
+struct Opt {
+    /// Is one of firmware, power, on or off
+    command: String,
+
+    /// endpoint files, one per node
+    #[structopt(parse(from_os_str), required = true)]
+    endpoints: Vec<PathBuf>,
+
+    /// Maximum number of nodes to run the command on at once
+    #[structopt(short, long, default_value = "16")]
+    concurrency: usize,
+
+    /// Print the report as json
+    #[structopt(short, long)]
+    json: bool,
+
+    /// Don't update the endpoint files
+    #[structopt(short, long)]
+    no_update: bool,
+
+    /// Write request metrics to this file, as json when it ends in .json and
+    /// in the Prometheus text format otherwise
+    #[structopt(short, long, parse(from_os_str))]
+    metrics: Option<PathBuf>,
+}
+
+fn print_report<T: Serialize + fmt::Debug, E: fmt::Display>(
+    report: &Report<T, E>,
+    json: bool,
+) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+    } else {
+        println!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    // setup tracing
+    let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("warn"))?;
+    let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    // load endpoints
+    let (mut fleet, loaded) = Fleet::load(&opt.endpoints, opt.concurrency).await;
+    if !loaded.is_success() {
+        print_report(&loaded, opt.json)?;
+    }
+    if !opt.no_update {
+        let saved = fleet.save();
+        if !saved.is_success() {
+            print_report(&saved, opt.json)?;
+        }
+    }
+
+    let registry = Arc::new(Registry::default());
+    if opt.metrics.is_some() {
+        for node in fleet.nodes_mut() {
+            node.set_metrics_recorder(registry.clone());
+        }
+    }
+
+    let success = match opt.command.as_str() {
+        "firmware" => {
+            let report = fleet.run(|node| Box::pin(node.get_fw_version())).await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        "power" => {
+            let report = fleet
+                .run(|node| Box::pin(node.get_host_power_status()))
+                .await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        "on" => {
+            let report = fleet
+                .run(|node| Box::pin(node.set_host_power(PowerStatus::On)))
+                .await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        "off" => {
+            let report = fleet
+                .run(|node| Box::pin(node.set_host_power(PowerStatus::Off)))
+                .await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        command => {
+            return Err(anyhow!(
+                "Invalid command: {}\nmust be one of firmware power on off",
+                command
+            ));
+        }
+    };
+
+    if let Some(path) = &opt.metrics {
+        let metrics = match path.extension() {
+            Some(extension) if extension == "json" => registry.to_json()?,
+            _ => registry.to_prometheus(),
+        };
+        fs::write(path, metrics)?;
+    }
+
+    if success && loaded.is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("command failed on some nodes"))
+    }
+}
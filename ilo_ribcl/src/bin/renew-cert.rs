@@ -0,0 +1,127 @@
+use anyhow::{anyhow, Result};
+use ilo_ribcl::{
+    certificate::{LocalCa, RenewOptions},
+    fleet::{Fleet, Report},
+};
+use serde::Serialize;
+use std::{fmt, path::PathBuf, time::Duration};
+use structopt::StructOpt;
+use tracing_subscriber::{filter::EnvFilter, FmtSubscriber};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "renew-cert",
+    about = "report certificate expiry and renew expiring certificates with a local CA"
+)]
+struct Opt {
+    /// endpoint files, one per node
+    #[structopt(parse(from_os_str), required = true)]
+    endpoints: Vec<PathBuf>,
+
+    /// PEM certificate of the CA that signs the renewed certificates
+    #[structopt(long, parse(from_os_str), required_unless = "check")]
+    ca_cert: Option<PathBuf>,
+
+    /// PEM private key of the CA
+    #[structopt(long, parse(from_os_str), required_unless = "check")]
+    ca_key: Option<PathBuf>,
+
+    /// Only report when each certificate expires, the endpoint files are left
+    /// untouched
+    #[structopt(long)]
+    check: bool,
+
+    /// Renew certificates that expire within this many days
+    #[structopt(short, long, default_value = "30")]
+    window: i32,
+
+    /// How many days the renewed certificates are valid for
+    #[structopt(short, long, default_value = "365")]
+    days: u32,
+
+    /// How many seconds each iLO may take to reset after the import
+    #[structopt(long, default_value = "600")]
+    reset_timeout: u64,
+
+    /// Maximum number of nodes to work on at once
+    #[structopt(short, long, default_value = "16")]
+    concurrency: usize,
+
+    /// Print the report as json
+    #[structopt(short, long)]
+    json: bool,
+
+    /// Don't update the endpoint files, e.g. with the fingerprints of the new
+    /// certificates
+    #[structopt(short, long)]
+    no_update: bool,
+}
+
+fn print_report<T: Serialize + fmt::Debug, E: fmt::Display>(
+    report: &Report<T, E>,
+    json: bool,
+) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+    } else {
+        println!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    // setup tracing
+    let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("warn"))?;
+    let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let ca = match (&opt.ca_cert, &opt.ca_key) {
+        (Some(certificate), Some(key)) if !opt.check => {
+            Some(LocalCa::from_pem_files(certificate, key)?)
+        }
+        _ => None,
+    };
+    let options = RenewOptions {
+        days: opt.days,
+        window_days: opt.window,
+        reset_timeout: Duration::from_secs(opt.reset_timeout),
+        fqdn: None,
+    };
+
+    let (mut fleet, loaded) = Fleet::load(&opt.endpoints, opt.concurrency).await;
+    if !loaded.is_success() {
+        print_report(&loaded, opt.json)?;
+    }
+
+    let success = match &ca {
+        None => {
+            let report = fleet.run(|node| Box::pin(node.certificate_status())).await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        Some(ca) => {
+            let options = &options;
+            let report = fleet
+                .run(|node| Box::pin(node.renew_certificate_if_expiring(ca, options)))
+                .await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+    };
+
+    if !opt.no_update && !opt.check {
+        let saved = fleet.save();
+        if !saved.is_success() {
+            print_report(&saved, opt.json)?;
+        }
+    }
+
+    if success && loaded.is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("failed on some nodes"))
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::{anyhow, Result};
+use ilo_ribcl::{simulator::Simulator, types::Version};
+use std::thread;
+use structopt::StructOpt;
+use tracing_subscriber::{filter::EnvFilter, FmtSubscriber};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "simulator",
+    about = "simulate an iLO answering RIBCL over HTTPS and raw TLS"
+)]
+struct Opt {
+    /// Is one of ilo2, ilo3, ilo4 or ilo5
+    #[structopt(short, long, default_value = "ilo4")]
+    firmware: String,
+
+    /// Address to listen on
+    #[structopt(short, long, default_value = "127.0.0.1:443")]
+    listen: String,
+
+    /// Username accepted by the simulator
+    #[structopt(short, long, default_value = "admin")]
+    username: String,
+
+    /// Password accepted by the simulator
+    #[structopt(short, long, default_value = "password")]
+    password: String,
+}
+
+fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    // setup tracing
+    let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?;
+    let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    let version = match opt.firmware.to_ascii_lowercase().as_str() {
+        "ilo2" => Version::Ilo2,
+        "ilo3" => Version::Ilo3,
+        "ilo4" => Version::Ilo4,
+        "ilo5" => Version::Ilo5,
+        firmware => {
+            return Err(anyhow!(
+                "Invalid firmware: {}\nmust be one of ilo2 ilo3 ilo4 ilo5",
+                firmware
+            ))
+        }
+    };
+
+    let simulator = Simulator::with_credentials(version, &opt.username, &opt.password);
+    let server = simulator.serve(opt.listen.as_str())?;
+    println!("listening on {}", server.addr);
+    println!("certificate sha256 {}", server.fingerprint);
+
+    // the simulator runs on background threads
+    loop {
+        thread::park();
+    }
+}
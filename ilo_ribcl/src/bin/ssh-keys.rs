@@ -0,0 +1,163 @@
+use anyhow::{anyhow, Result};
+use ilo_ribcl::{
+    authentication::{parse_authorized_keys, SshPublicKey},
+    fleet::{Fleet, Report},
+};
+use serde::Serialize;
+use std::{fmt, fs, path::PathBuf};
+use structopt::StructOpt;
+use tracing_subscriber::{filter::EnvFilter, FmtSubscriber};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "ssh-keys",
+    about = "authorize SSH public keys for iLO users across many endpoints"
+)]
+struct Opt {
+    /// Is one of list, push or delete
+    command: String,
+
+    /// endpoint files, one per node
+    #[structopt(parse(from_os_str))]
+    endpoints: Vec<PathBuf>,
+
+    /// authorized_keys file, each key is assigned to the iLO user named by
+    /// its comment unless --user is given
+    #[structopt(short, long, parse(from_os_str))]
+    keys: Option<PathBuf>,
+
+    /// Assign every key to this iLO user instead
+    #[structopt(short, long)]
+    user: Option<String>,
+
+    /// Maximum number of nodes to run the command on at once
+    #[structopt(short, long, default_value = "16")]
+    concurrency: usize,
+
+    /// Print the report as json
+    #[structopt(short, long)]
+    json: bool,
+}
+
+fn print_report<T: Serialize + fmt::Debug, E: fmt::Display>(
+    report: &Report<T, E>,
+    json: bool,
+) -> Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(report)?);
+    } else {
+        println!("{}", report.to_table());
+    }
+    Ok(())
+}
+
+/// The keys in the keys file paired with the iLO user each is assigned to
+fn load_keys(opt: &Opt) -> Result<Vec<(String, SshPublicKey)>> {
+    let path = opt
+        .keys
+        .as_ref()
+        .ok_or_else(|| anyhow!("--keys is required"))?;
+    parse_authorized_keys(&fs::read_to_string(path)?)?
+        .into_iter()
+        .map(|key| {
+            if let Some(user) = &opt.user {
+                return Ok((user.clone(), key));
+            }
+            match &key.comment {
+                // ssh-keygen comments keys with user@host, which isn't an iLO login
+                Some(comment) if comment.contains('@') => Err(anyhow!(
+                    "{} is commented {:?} rather than with an iLO user, use --user",
+                    key.fingerprint(),
+                    comment
+                )),
+                Some(comment) => Ok((comment.clone(), key)),
+                None => Err(anyhow!(
+                    "{} has no comment naming its user, use --user",
+                    key.fingerprint()
+                )),
+            }
+        })
+        .collect()
+}
+
+/// The iLO users to remove keys from
+fn key_users(opt: &Opt) -> Result<Vec<String>> {
+    if let Some(user) = &opt.user {
+        return Ok(vec![user.clone()]);
+    }
+    let mut users: Vec<_> = load_keys(opt)?.into_iter().map(|(user, _)| user).collect();
+    users.sort();
+    users.dedup();
+    Ok(users)
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    // setup tracing
+    let filter = EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("warn"))?;
+    let subscriber = FmtSubscriber::builder().with_env_filter(filter).finish();
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    if opt.command == "list" {
+        // RIBCL doesn't return the keys installed on a node, only those to push are listed
+        for (user, key) in load_keys(&opt)? {
+            println!("{:16}  {:8}  {}", user, key.algorithm, key.fingerprint());
+        }
+        return Ok(());
+    }
+    if opt.endpoints.is_empty() {
+        return Err(anyhow!("no endpoint files given"));
+    }
+
+    let (mut fleet, loaded) = Fleet::load(&opt.endpoints, opt.concurrency).await;
+    if !loaded.is_success() {
+        print_report(&loaded, opt.json)?;
+    }
+
+    let success = match opt.command.as_str() {
+        "push" => {
+            let keys = &load_keys(&opt)?;
+            let report = fleet
+                .run(move |node| {
+                    Box::pin(async move {
+                        for (user, key) in keys {
+                            node.import_ssh_key(user.clone(), key.clone()).await?;
+                        }
+                        Ok::<_, ilo_ribcl::commands::Error>(keys.len())
+                    })
+                })
+                .await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        "delete" => {
+            let users = &key_users(&opt)?;
+            let report = fleet
+                .run(move |node| {
+                    Box::pin(async move {
+                        for user in users {
+                            node.del_users_ssh_key(user.clone()).await?;
+                        }
+                        Ok::<_, ilo_ribcl::commands::Error>(users.len())
+                    })
+                })
+                .await;
+            print_report(&report, opt.json)?;
+            report.is_success()
+        }
+        command => {
+            return Err(anyhow!(
+                "Invalid command: {}\nmust be one of list push delete",
+                command
+            ));
+        }
+    };
+
+    if success && loaded.is_success() {
+        Ok(())
+    } else {
+        Err(anyhow!("command failed on some nodes"))
+    }
+}
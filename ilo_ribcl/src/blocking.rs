@@ -0,0 +1,232 @@
+//! A synchronous [Node] for use outside of an async runtime.
+//!
+//! Every getter and setter of [client::Node] is available with the same name
+//! and arguments, blocking until the command completes.
+//!
+//! ```no_run
+//! # fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use ilo_ribcl::{blocking::Node, power::PowerStatus};
+//!
+//! let mut node = Node::from_json(&std::fs::read_to_string("endpoint.json")?)?;
+//! if node.get_host_power_status()? == PowerStatus::Off {
+//!     node.set_host_power(PowerStatus::On)?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The node owns a single threaded runtime, so its methods must not be called
+//! from inside another runtime.
+use ilo_console::ilo2::auth::Auth;
+use std::{future::Future, path::Path, sync::Arc, time::Duration};
+use tokio::runtime::{self, Runtime};
+
+use crate::{
+    batch::{Batch, BatchResponse},
+    client::{self, ConnectionSettings},
+    commands::{self, Command},
+    metrics::Recorder,
+    retry::RetryPolicy,
+    types::FwVersion,
+};
+
+/// Generates blocking versions of [client::Node] methods
+macro_rules! blocking_methods {
+    ($( $fn_name:ident ( $($arg:ident : $arg_type:ty),* ) -> $ret_type:ty; )+) => {
+        $(
+            pub fn $fn_name(&mut self, $($arg: $arg_type),*) -> Result<$ret_type, commands::Error> {
+                self.block_on(|node| node.$fn_name($($arg),*))
+            }
+        )+
+    };
+}
+
+/// A [client::Node] whose methods block until the command completes
+#[derive(Debug)]
+pub struct Node {
+    node: client::Node,
+    runtime: Runtime,
+}
+
+fn build_runtime() -> Result<Runtime, client::Error> {
+    runtime::Builder::new()
+        .basic_scheduler()
+        .enable_all()
+        .build()
+        .map_err(|source| client::Error::Runtime { source })
+}
+
+impl Node {
+    /// Load a node from the contents of an endpoint file, see [client::Node::from_json]
+    pub fn from_json(json: &str) -> Result<Self, client::Error> {
+        let mut runtime = build_runtime()?;
+        let node = runtime.block_on(client::Node::from_json(json))?;
+        Ok(Self { node, runtime })
+    }
+
+    /// Connect to a node detecting its firmware, see [client::Node::new]
+    pub fn new(auth: Auth) -> Result<Self, client::Error> {
+        let mut runtime = build_runtime()?;
+        let node = runtime.block_on(client::Node::new(auth))?;
+        Ok(Self { node, runtime })
+    }
+
+    pub fn new_with_fw(auth: Auth, fw_version: FwVersion) -> Result<Self, client::Error> {
+        let runtime = build_runtime()?;
+        // clients may need the runtime when they are created
+        let node = runtime.enter(|| client::Node::new_with_fw(auth, fw_version))?;
+        Ok(Self { node, runtime })
+    }
+
+    /// Wrap an existing node
+    pub fn from_async(node: client::Node) -> Result<Self, client::Error> {
+        Ok(Self {
+            node,
+            runtime: build_runtime()?,
+        })
+    }
+
+    /// Returns the wrapped node, it can only be used from an async runtime
+    pub fn into_async(self) -> client::Node {
+        self.node
+    }
+
+    /// Run an async [client::Node] method to completion
+    ///
+    /// ```no_run
+    /// # fn run(node: &mut ilo_ribcl::blocking::Node) -> Result<(), ilo_ribcl::commands::Error> {
+    /// let firmware = node.block_on(|node| node.get_fw_version())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn block_on<'a, F, T>(&'a mut self, call: impl FnOnce(&'a mut client::Node) -> F) -> T
+    where
+        F: Future<Output = T>,
+    {
+        let Self { node, runtime } = self;
+        runtime.block_on(call(node))
+    }
+
+    pub fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, client::Error> {
+        self.block_on(|node| node.send_ribcl(request))
+    }
+
+    pub fn get_xmldata(&mut self, item: &str) -> Result<String, client::Error> {
+        self.block_on(|node| node.get_xmldata(item))
+    }
+
+    /// Send a command built with one of the `*_command` methods of [client::Node]
+    pub fn send_command<T>(&mut self, command: Command<T>) -> Result<T, commands::Error> {
+        self.block_on(|node| node.send_command(command))
+    }
+
+    /// Send the commands queued by `build` under a single login
+    ///
+    /// ```no_run
+    /// # fn run(node: &mut ilo_ribcl::blocking::Node) -> Result<(), ilo_ribcl::commands::Error> {
+    /// use ilo_ribcl::client;
+    ///
+    /// let mut power = None;
+    /// let response = node.send_batch(|batch| {
+    ///     power = Some(batch.push(client::Node::get_host_power_status_command)?);
+    ///     Ok(())
+    /// })?;
+    /// println!("{:?}", response.get(&power.unwrap())?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn send_batch<F>(&mut self, build: F) -> Result<BatchResponse, commands::Error>
+    where
+        F: FnOnce(&mut Batch<'_>) -> Result<(), commands::Error>,
+    {
+        let Self { node, runtime } = self;
+        let mut batch = node.batch();
+        build(&mut batch)?;
+        runtime.block_on(batch.send())
+    }
+
+    pub fn auth(&self) -> Auth {
+        self.node.auth()
+    }
+
+    pub fn firmware(&self) -> Option<FwVersion> {
+        self.node.firmware()
+    }
+
+    pub fn connection_settings(&self) -> ConnectionSettings {
+        self.node.connection_settings()
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.node.retry_policy()
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.node.set_retry_policy(retry)
+    }
+
+    pub fn set_metrics_recorder(&mut self, recorder: Arc<dyn Recorder>) {
+        self.node.set_metrics_recorder(recorder)
+    }
+
+    /// Set how long a request may take before it is abandoned
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.node.set_request_timeout(timeout)
+    }
+
+    pub fn update_rib_firmware(
+        &mut self,
+        path: &Path,
+    ) -> Result<crate::types::FwVersion, commands::Error> {
+        self.block_on(|node| node.update_rib_firmware(path))
+    }
+
+    pub fn update_rib_firmware_with_progress<F>(
+        &mut self,
+        path: &Path,
+        progress: F,
+    ) -> Result<crate::types::FwVersion, commands::Error>
+    where
+        F: FnMut(crate::firmware::UpdateProgress) + Send + 'static,
+    {
+        self.block_on(|node| node.update_rib_firmware_with_progress(path, progress))
+    }
+
+    node_methods!(blocking_methods);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{power::PowerStatus, simulator::Simulator, types::Version};
+    use ilo_console::tls::TlsPolicy;
+
+    #[test]
+    fn commands_round_trip_to_the_simulator() {
+        // iLO 2 over the raw TLS port and iLO 4 over HTTPS
+        for version in [Version::Ilo2, Version::Ilo4] {
+            let simulator = Simulator::new(version.clone());
+            let server = simulator.serve("127.0.0.1:0").unwrap();
+            let mut auth = simulator.auth();
+            auth.hostname = server.addr.ip().to_string();
+            auth.https_port = Some(server.addr.port());
+            auth.tls = TlsPolicy::Pinned {
+                sha256: Some(server.fingerprint.clone()),
+            };
+            let mut node = Node::new_with_fw(auth, simulator.firmware()).unwrap();
+            node.set_host_power(PowerStatus::On).unwrap();
+            assert_eq!(node.get_host_power_status().unwrap(), PowerStatus::On);
+            let firmware = node.get_fw_version().unwrap();
+            assert_eq!(firmware.management_processor, Some(version));
+
+            let mut power = None;
+            let response = node
+                .send_batch(|batch| {
+                    power = Some(batch.push(client::Node::get_host_power_status_command)?);
+                    Ok(())
+                })
+                .unwrap();
+            assert_eq!(response.get(&power.unwrap()).unwrap(), PowerStatus::On);
+        }
+    }
+}
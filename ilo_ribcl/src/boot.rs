@@ -67,21 +67,27 @@ impl client::Node {
             (Ilo2, "2.06")
         );
         let mut request = String::new();
-        ribcl_command!(request, self.auth(), server_info, write, devices, {
-            use std::fmt::Write;
-            write!(request, "<set_persistent_boot>")?;
-            for device in devices {
-                write!(request, "<device value=\"{}\"/>", device.into_ribcl()?)?;
+        ribcl_command!(
+            request,
+            self.auth(),
+            server_info,
+            write,
+            set_persistent_boot,
+            {
+                use std::fmt::Write;
+                for device in devices {
+                    write!(request, "<device value=\"{}\"/>", device.into_ribcl()?)?;
+                }
             }
-            write!(request, "</set_persistent_boot/>")?;
-        });
+        );
         let response = self.send_ribcl(request.into_bytes()).await?;
         match ribcl_parse_response!(response) {
-            Ok(_)
+            Ok(Ok(_))
             | Err(crate::commands::Error::BuilderParse {
                 source: crate::builder_parse::Error::NotFound { target: _ },
                 ..
             }) => Ok(()),
+            Ok(Err(err)) => Err(crate::commands::Error::builder_parse("", err)),
             Err(err) => Err(err),
         }
     }
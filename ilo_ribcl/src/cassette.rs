@@ -0,0 +1,256 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    str,
+};
+use thiserror::Error;
+use tracing::{event, Level};
+
+use crate::{redact, types::FwVersion};
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("couldn't read or write cassette {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("invalid cassette {path:?}: {source}")]
+    SerdeJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    #[error("no recorded response in {path:?} for {request}")]
+    Miss { path: PathBuf, request: String },
+    #[error("invalid cassette mode `{0}` must be one of record, replay or pass_through")]
+    InvalidMode(String),
+}
+
+/// How a [crate::client::ProxyClient] uses its cassette
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CassetteMode {
+    /// Replay recorded responses, sending and recording requests that haven't been seen
+    #[default]
+    Record,
+    /// Only replay recorded responses, failing on requests that haven't been seen
+    Replay,
+    /// Send every request to the node without reading or writing the cassette
+    PassThrough,
+}
+
+impl str::FromStr for CassetteMode {
+    type Err = Error;
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        use CassetteMode::*;
+        match mode.to_lowercase().replace('-', "_").as_str() {
+            "record" => Ok(Record),
+            "replay" => Ok(Replay),
+            "pass_through" => Ok(PassThrough),
+            _ => Err(Error::InvalidMode(mode.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Ribcl,
+    Xmldata,
+}
+
+/// A recorded request and the node's response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub kind: Kind,
+    pub request: String,
+    pub response: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Tape {
+    hostname: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    firmware: Option<FwVersion>,
+    #[serde(default)]
+    interactions: Vec<Interaction>,
+}
+
+/// Requests and responses recorded from one node, stored as `{hostname}.cassette.json`
+/// in the cassette directory with credentials removed.
+pub struct Cassette {
+    path: PathBuf,
+    mode: CassetteMode,
+    password: String,
+    tape: Tape,
+}
+
+impl std::fmt::Debug for Cassette {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Cassette")
+            .field("path", &self.path)
+            .field("mode", &self.mode)
+            .finish()
+    }
+}
+
+lazy_static! {
+    static ref XML_DECL: Regex = Regex::new(r#"<\?xml[^>]*\?>"#).unwrap();
+    static ref LOGIN: Regex = Regex::new(r#"(?is)<login\b[^>]*>"#).unwrap();
+    static ref BETWEEN_TAGS: Regex = Regex::new(r#">\s+<"#).unwrap();
+}
+
+/// Remove secrets, see [redact::redact], and any occurrence of the password
+pub fn scrub(text: &str, password: &str) -> String {
+    let text = redact::redact(text);
+    if password.is_empty() {
+        text
+    } else {
+        text.replace(password, redact::REDACTED)
+    }
+}
+
+/// Normalize a RIBCL request so it matches regardless of credentials and formatting
+pub fn normalize(request: &str, password: &str) -> String {
+    let request = XML_DECL.replace_all(request, "");
+    let request = LOGIN.replace_all(&request, "<LOGIN>");
+    let request = BETWEEN_TAGS.replace_all(&request, "><");
+    scrub(request.trim(), password)
+}
+
+impl Cassette {
+    /// Load the cassette for `hostname` from `dir`, starting a new one if it doesn't exist
+    pub fn load<P: AsRef<Path>>(
+        dir: P,
+        hostname: &str,
+        password: &str,
+        mode: CassetteMode,
+    ) -> Result<Self, Error> {
+        let path = dir.as_ref().join(format!("{}.cassette.json", hostname));
+        let tape = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json).map_err(|source| Error::SerdeJson {
+                path: path.clone(),
+                source,
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Tape {
+                hostname: hostname.to_string(),
+                ..Default::default()
+            },
+            Err(source) => return Err(Error::Io { path, source }),
+        };
+        Ok(Self {
+            path,
+            mode,
+            password: password.to_string(),
+            tape,
+        })
+    }
+
+    pub fn mode(&self) -> CassetteMode {
+        self.mode
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn interactions(&self) -> &[Interaction] {
+        &self.tape.interactions
+    }
+
+    fn key(&self, kind: Kind, request: &str) -> String {
+        match kind {
+            Kind::Ribcl => normalize(request, &self.password),
+            Kind::Xmldata => request.to_string(),
+        }
+    }
+
+    /// Returns the recorded response to a request, `Ok(None)` means the request
+    /// should be sent to the node
+    pub fn play(&self, kind: Kind, request: &str) -> Result<Option<String>, Error> {
+        if self.mode == CassetteMode::PassThrough {
+            return Ok(None);
+        }
+        let key = self.key(kind, request);
+        let response = self
+            .tape
+            .interactions
+            .iter()
+            .find(|interaction| interaction.kind == kind && interaction.request == key)
+            .map(|interaction| interaction.response.clone());
+        match response {
+            None if self.mode == CassetteMode::Replay => Err(Error::Miss {
+                path: self.path.clone(),
+                request: key,
+            }),
+            response => Ok(response),
+        }
+    }
+
+    /// Record the node's response to a request and save the cassette
+    pub fn record(&mut self, kind: Kind, request: &str, response: &str) -> Result<(), Error> {
+        if self.mode != CassetteMode::Record {
+            return Ok(());
+        }
+        let interaction = Interaction {
+            kind,
+            request: self.key(kind, request),
+            response: scrub(response, &self.password),
+        };
+        event!(Level::DEBUG, path = ?self.path, request = %interaction.request, "recording");
+        self.tape.interactions.push(interaction);
+        self.save()
+    }
+
+    /// Record the node's firmware so the cassette can be replayed without autodetecting
+    pub fn set_firmware(&mut self, firmware: &FwVersion) -> Result<(), Error> {
+        if self.mode == CassetteMode::Record && self.tape.firmware.as_ref() != Some(firmware) {
+            self.tape.firmware = Some(firmware.clone());
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn firmware(&self) -> Option<&FwVersion> {
+        self.tape.firmware.as_ref()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|source| Error::Io {
+                path: dir.to_path_buf(),
+                source,
+            })?;
+        }
+        let json = serde_json::to_string_pretty(&self.tape).map_err(|source| Error::SerdeJson {
+            path: self.path.clone(),
+            source,
+        })?;
+        fs::write(&self.path, json).map_err(|source| Error::Io {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_strips_credentials_and_formatting() {
+        let a = r#"<?xml version="1.0"?>
+<ribcl version="2.0">
+  <login user_login="admin" password="secret">
+    <user_info mode="write"><mod_user user_login="bob"><password value="hunter2"/></mod_user></user_info>
+  </login>
+</ribcl>"#;
+        let b = r#"<?xml version="1.0"?><ribcl version="2.0"><login user_login="root" password="rotated"><user_info mode="write"><mod_user user_login="bob"><password value="other"/></mod_user></user_info></login></ribcl>"#;
+        let normalized = normalize(a, "secret");
+        assert!(!normalized.contains("secret"));
+        assert!(!normalized.contains("hunter2"));
+        assert!(!normalized.contains("admin"));
+        assert_eq!(normalized, normalize(b, "rotated"));
+    }
+}
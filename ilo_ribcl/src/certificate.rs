@@ -0,0 +1,494 @@
+//! Renew the certificate served by the iLO with one signed by a local CA.
+//!
+//! The iLO generates the key and a certificate signing request, the request is
+//! signed locally and the certificate imported, after which the iLO resets and
+//! serves it.  [Node::certificate_status](crate::client::Node::certificate_status)
+//! reports when the served certificate expires, so a fleet can be checked and
+//! only the nodes expiring within a window renewed.
+use crate::{
+    client::{self, Node},
+    commands,
+    security::{CertificateSigningRequest, CsrCertSettings},
+    types::Version,
+};
+use ilo_console::tls::{self, TlsPolicy};
+use openssl::{
+    asn1::Asn1Time,
+    bn::{BigNum, MsbOption},
+    error::ErrorStack,
+    hash::MessageDigest,
+    nid::Nid,
+    pkey::{PKey, Private},
+    x509::{
+        extension::{
+            AuthorityKeyIdentifier, BasicConstraints, ExtendedKeyUsage, KeyUsage,
+            SubjectAlternativeName, SubjectKeyIdentifier,
+        },
+        X509Name, X509NameRef, X509Req, X509,
+    },
+};
+use serde::Serialize;
+use std::{
+    fmt, fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::{task, time};
+use tracing::{event, instrument, Level};
+
+/// How long to wait between checks of the certificate served while the iLO resets
+const RESET_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("couldn't read {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("openssl error: {0}")]
+    Openssl(#[from] ErrorStack),
+    #[error("{0}")]
+    Command(#[from] commands::Error),
+    #[error("{0}")]
+    Client(#[from] client::Error),
+    #[error("{hostname} wasn't serving the new certificate after {timeout:?}")]
+    NotServed { hostname: String, timeout: Duration },
+    #[error("the node has no DNS name set, the fqdn must be given")]
+    UnknownFqdn,
+    #[error("the certificate signing request signature doesn't match its public key")]
+    InvalidSignature,
+}
+
+/// The certificate served by a node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CertificateStatus {
+    pub subject: String,
+    pub issuer: String,
+    pub not_after: String,
+    /// Negative once the certificate has expired
+    pub days_remaining: i32,
+    /// SHA-256 fingerprint in the format used by [TlsPolicy::Pinned]
+    pub sha256: String,
+}
+
+impl CertificateStatus {
+    pub fn from_x509(certificate: &X509) -> Result<Self, ErrorStack> {
+        let remaining = Asn1Time::days_from_now(0)?.diff(certificate.not_after())?;
+        Ok(Self {
+            subject: format_name(certificate.subject_name()),
+            issuer: format_name(certificate.issuer_name()),
+            not_after: certificate.not_after().to_string(),
+            days_remaining: remaining.days,
+            sha256: tls::fingerprint(&certificate.to_der()?),
+        })
+    }
+}
+
+/// Format a name as comma separated `CN=value` pairs
+fn format_name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string().unwrap_or_default();
+            format!("{}={}", key, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A certificate authority whose key signs the requests generated by the iLO
+pub struct LocalCa {
+    certificate: X509,
+    key: PKey<Private>,
+}
+
+// the key isn't logged
+impl fmt::Debug for LocalCa {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalCa")
+            .field("subject", &format_name(self.certificate.subject_name()))
+            .finish()
+    }
+}
+
+impl LocalCa {
+    pub fn from_pem(certificate: &[u8], key: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            certificate: X509::from_pem(certificate)?,
+            key: PKey::private_key_from_pem(key)?,
+        })
+    }
+
+    pub fn from_pem_files<P: AsRef<Path>>(certificate: P, key: P) -> Result<Self, Error> {
+        let read = |path: &Path| {
+            fs::read(path).map_err(|source| Error::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+        };
+        Self::from_pem(&read(certificate.as_ref())?, &read(key.as_ref())?)
+    }
+
+    pub fn certificate(&self) -> &X509 {
+        &self.certificate
+    }
+
+    /// Issue a server certificate for the key in `csr`, valid for `days`.
+    ///
+    /// The subject is taken from the request with the common name replaced by
+    /// `fqdn`, which is also the subject alternative name.
+    pub fn sign(
+        &self,
+        csr: &CertificateSigningRequest,
+        fqdn: &str,
+        days: u32,
+    ) -> Result<X509, Error> {
+        let request = X509Req::from_pem(csr.pem().as_bytes())?;
+        let public_key = request.public_key()?;
+        if !request.verify(&public_key)? {
+            return Err(Error::InvalidSignature);
+        }
+
+        let mut subject = X509Name::builder()?;
+        for entry in request.subject_name().entries() {
+            let nid = entry.object().nid();
+            if nid != Nid::COMMONNAME {
+                subject.append_entry_by_nid(nid, &entry.data().to_string()?)?;
+            }
+        }
+        subject.append_entry_by_nid(Nid::COMMONNAME, fqdn)?;
+        let subject = subject.build();
+
+        let mut serial = BigNum::new()?;
+        serial.rand(128, MsbOption::MAYBE_ZERO, false)?;
+
+        let serial = serial.to_asn1_integer()?;
+        let not_before = Asn1Time::days_from_now(0)?;
+        let not_after = Asn1Time::days_from_now(days)?;
+
+        let mut builder = X509::builder()?;
+        builder.set_version(2)?;
+        builder.set_serial_number(&serial)?;
+        builder.set_subject_name(&subject)?;
+        builder.set_issuer_name(self.certificate.subject_name())?;
+        builder.set_pubkey(&public_key)?;
+        builder.set_not_before(&not_before)?;
+        builder.set_not_after(&not_after)?;
+        builder.append_extension(BasicConstraints::new().critical().build()?)?;
+        builder.append_extension(
+            KeyUsage::new()
+                .critical()
+                .digital_signature()
+                .key_encipherment()
+                .build()?,
+        )?;
+        builder.append_extension(ExtendedKeyUsage::new().server_auth().build()?)?;
+        let subject_key_id = SubjectKeyIdentifier::new()
+            .build(&builder.x509v3_context(Some(&self.certificate), None))?;
+        builder.append_extension(subject_key_id)?;
+        // openssl can't name the issuer's key when the CA certificate doesn't
+        if self.certificate.subject_key_id().is_some() {
+            let authority_key_id = AuthorityKeyIdentifier::new()
+                .keyid(false)
+                .build(&builder.x509v3_context(Some(&self.certificate), None))?;
+            builder.append_extension(authority_key_id)?;
+        }
+        let mut alternative_name = SubjectAlternativeName::new();
+        if fqdn.parse::<IpAddr>().is_ok() {
+            alternative_name.ip(fqdn);
+        } else {
+            alternative_name.dns(fqdn);
+        }
+        let alternative_name =
+            alternative_name.build(&builder.x509v3_context(Some(&self.certificate), None))?;
+        builder.append_extension(alternative_name)?;
+        builder.sign(&self.key, MessageDigest::sha256())?;
+        Ok(builder.build())
+    }
+}
+
+/// How certificates are renewed
+#[derive(Debug, Clone)]
+pub struct RenewOptions {
+    /// How long the issued certificate is valid for
+    pub days: u32,
+    /// Renew certificates that expire within this many days
+    pub window_days: i32,
+    /// How long the iLO may take to reset and serve the imported certificate
+    pub reset_timeout: Duration,
+    /// The name to issue the certificate for, the DNS name and domain set on
+    /// the iLO when `None`
+    pub fqdn: Option<String>,
+}
+
+impl Default for RenewOptions {
+    fn default() -> Self {
+        Self {
+            days: 365,
+            window_days: 30,
+            reset_timeout: Duration::from_secs(10 * 60),
+            fqdn: None,
+        }
+    }
+}
+
+/// The result of [Node::renew_certificate_if_expiring]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Renewal {
+    pub renewed: bool,
+    /// The certificate served once the command finished
+    pub status: CertificateStatus,
+}
+
+impl Node {
+    /// Returns the certificate the node serves over HTTPS.
+    ///
+    /// The certificate is read without checking it against the node's
+    /// [TlsPolicy], so a pinned node can be checked after it's been replaced.
+    #[instrument(skip(self))]
+    pub async fn served_certificate(&self) -> Result<X509, Error> {
+        let auth = self.auth();
+        let host = auth.host().to_string();
+        let port = auth.https_port();
+        let proxy = auth.proxy.clone();
+        let timeout = Duration::from_millis(self.connection_settings().connect_timeout_ms);
+        let der = task::spawn_blocking(move || -> Result<Vec<u8>, tls::Error> {
+            let stream = TlsPolicy::Insecure.connect(&host, port, Some(timeout), proxy.as_ref())?;
+            let certificate = stream
+                .peer_certificate()?
+                .ok_or(tls::Error::NoCertificate)?;
+            Ok(certificate.to_der()?)
+        })
+        .await
+        .map_err(client::Error::from)?
+        .map_err(client::Error::from)?;
+        Ok(X509::from_der(&der)?)
+    }
+
+    /// Returns the subject, issuer and expiry of the certificate the node serves
+    pub async fn certificate_status(&self) -> Result<CertificateStatus, Error> {
+        let certificate = self.served_certificate().await?;
+        Ok(CertificateStatus::from_x509(&certificate)?)
+    }
+
+    /// Returns the DNS name and domain set on the iLO
+    pub async fn fqdn(&mut self) -> Result<String, Error> {
+        let settings = self.get_network_settings().await?;
+        let name = settings
+            .dns_name
+            .filter(|name| !name.is_empty())
+            .ok_or(Error::UnknownFqdn)?;
+        Ok(
+            match settings.domain_name.filter(|domain| !domain.is_empty()) {
+                Some(domain) => format!("{}.{}", name, domain),
+                None => name,
+            },
+        )
+    }
+
+    /// Ask the iLO to use `fqdn` as the subject of its certificate signing
+    /// requests, iLO 3 and iLO 4 always use their DNS name
+    async fn set_csr_subject(&mut self, fqdn: &str) -> Result<(), Error> {
+        let settings = CsrCertSettings {
+            csr_use_cert_custom_subject: Some(true),
+            csr_subject_common_name: Some(fqdn.to_string()),
+            ..Default::default()
+        };
+        match self.csr_cert_settings(settings).await {
+            Err(commands::Error::NotSupported { .. }) => {}
+            result => return Ok(result?),
+        }
+        let is_ilo2 = self
+            .firmware()
+            .is_some_and(|firmware| firmware.management_processor == Some(Version::Ilo2));
+        if is_ilo2 {
+            self.cert_fqdn(true).await?;
+        }
+        Ok(())
+    }
+
+    /// Replace the node's certificate with one signed by `ca`.
+    ///
+    /// Waits for the iLO to reset and serve the new certificate, updating a
+    /// pinned fingerprint to match it.
+    #[instrument(skip(self, ca))]
+    pub async fn renew_certificate(
+        &mut self,
+        ca: &LocalCa,
+        options: &RenewOptions,
+    ) -> Result<CertificateStatus, Error> {
+        let fqdn = match &options.fqdn {
+            Some(fqdn) => fqdn.clone(),
+            None => self.fqdn().await?,
+        };
+        self.set_csr_subject(&fqdn).await?;
+        let csr = self.certificate_signing_request().await?;
+        let certificate = ca.sign(&csr, &fqdn, options.days)?;
+        let expected = CertificateStatus::from_x509(&certificate)?;
+        let pem = String::from_utf8_lossy(&certificate.to_pem()?).into_owned();
+        self.import_certificate(pem).await?;
+        event!(Level::INFO, %fqdn, fingerprint = %expected.sha256, "certificate imported");
+
+        let hostname = self.auth().hostname;
+        let start = Instant::now();
+        loop {
+            match self.served_certificate().await {
+                Ok(served) => {
+                    if tls::fingerprint(&served.to_der()?) == expected.sha256 {
+                        break;
+                    }
+                    event!(Level::DEBUG, "still serving the old certificate");
+                }
+                Err(err) => event!(Level::DEBUG, %err, "waiting for the reset"),
+            }
+            if start.elapsed() >= options.reset_timeout {
+                return Err(Error::NotServed {
+                    hostname,
+                    timeout: options.reset_timeout,
+                });
+            }
+            time::delay_for(RESET_POLL_INTERVAL).await;
+        }
+
+        let tls = match self.auth().tls {
+            TlsPolicy::Pinned { .. } => TlsPolicy::Pinned {
+                sha256: Some(expected.sha256.clone()),
+            },
+            tls => tls,
+        };
+        self.set_tls_policy(tls);
+        Ok(expected)
+    }
+
+    /// Renew the node's certificate when it expires within `options.window_days`
+    #[instrument(skip(self, ca))]
+    pub async fn renew_certificate_if_expiring(
+        &mut self,
+        ca: &LocalCa,
+        options: &RenewOptions,
+    ) -> Result<Renewal, Error> {
+        let status = self.certificate_status().await?;
+        if status.days_remaining > options.window_days {
+            return Ok(Renewal {
+                renewed: false,
+                status,
+            });
+        }
+        event!(
+            Level::INFO,
+            days_remaining = status.days_remaining,
+            "renewing certificate"
+        );
+        Ok(Renewal {
+            renewed: true,
+            status: self.renew_certificate(ca, options).await?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::Simulator;
+    use openssl::{rsa::Rsa, x509::X509ReqBuilder};
+
+    fn name(common_name: &str) -> X509Name {
+        let mut name = X509Name::builder().unwrap();
+        name.append_entry_by_nid(Nid::ORGANIZATIONNAME, "Example")
+            .unwrap();
+        name.append_entry_by_nid(Nid::COMMONNAME, common_name)
+            .unwrap();
+        name.build()
+    }
+
+    /// A CA named `Example CA` and its key
+    fn local_ca() -> (LocalCa, PKey<Private>) {
+        let ca_key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut ca = X509::builder().unwrap();
+        ca.set_version(2).unwrap();
+        ca.set_subject_name(&name("Example CA")).unwrap();
+        ca.set_issuer_name(&name("Example CA")).unwrap();
+        ca.set_pubkey(&ca_key).unwrap();
+        ca.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        ca.set_not_after(&Asn1Time::days_from_now(3650).unwrap())
+            .unwrap();
+        ca.sign(&ca_key, MessageDigest::sha256()).unwrap();
+        let ca = LocalCa::from_pem(
+            &ca.build().to_pem().unwrap(),
+            &ca_key.private_key_to_pem_pkcs8().unwrap(),
+        )
+        .unwrap();
+        (ca, ca_key)
+    }
+
+    #[test]
+    fn sign_request() {
+        let (ca, ca_key) = local_ca();
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut request = X509ReqBuilder::new().unwrap();
+        request.set_subject_name(&name("ilo")).unwrap();
+        request.set_pubkey(&key).unwrap();
+        request.sign(&key, MessageDigest::sha256()).unwrap();
+        let pem = request.build().to_pem().unwrap();
+        let csr = CertificateSigningRequest::from_pem(std::str::from_utf8(&pem).unwrap()).unwrap();
+
+        let certificate = ca.sign(&csr, "ilo.example.com", 365).unwrap();
+        assert!(certificate.verify(&ca_key).unwrap());
+        let names: Vec<_> = certificate
+            .subject_alt_names()
+            .unwrap()
+            .iter()
+            .filter_map(|name| name.dnsname().map(String::from))
+            .collect();
+        assert_eq!(names, vec!["ilo.example.com"]);
+
+        let status = CertificateStatus::from_x509(&certificate).unwrap();
+        assert_eq!(status.subject, "O=Example,CN=ilo.example.com");
+        assert_eq!(status.issuer, "O=Example,CN=Example CA");
+        assert!((364..=365).contains(&status.days_remaining));
+    }
+
+    #[tokio::test]
+    async fn renewed_certificate_is_served() {
+        let simulator = Simulator::new(Version::Ilo4);
+        let server = simulator.serve("127.0.0.1:0").unwrap();
+        let mut auth = simulator.auth();
+        auth.hostname = server.addr.ip().to_string();
+        auth.https_port = Some(server.addr.port());
+        auth.tls = TlsPolicy::Pinned {
+            sha256: Some(server.fingerprint.clone()),
+        };
+        let mut node = Node::new_with_fw(auth, simulator.firmware()).unwrap();
+        let (ca, _) = local_ca();
+        let options = RenewOptions {
+            fqdn: Some(String::from("ilo.example.com")),
+            reset_timeout: Duration::from_secs(5),
+            ..Default::default()
+        };
+
+        let status = node.renew_certificate(&ca, &options).await.unwrap();
+        assert_ne!(status.sha256, server.fingerprint);
+        assert_eq!(status.issuer, "O=Example,CN=Example CA");
+        assert_eq!(
+            node.certificate_status().await.unwrap().sha256,
+            status.sha256
+        );
+        // the new certificate is pinned and used for the following requests
+        assert_eq!(
+            node.auth().tls,
+            TlsPolicy::Pinned {
+                sha256: Some(status.sha256.clone())
+            }
+        );
+        let firmware = node.get_fw_version().await.unwrap();
+        assert_eq!(firmware.management_processor, Some(Version::Ilo4));
+
+        let renewal = node
+            .renew_certificate_if_expiring(&ca, &options)
+            .await
+            .unwrap();
+        assert!(!renewal.renewed);
+    }
+}
@@ -4,6 +4,7 @@ macro_rules! parse_node_auth {
         use anyhow::Context;
         use ilo_console::ilo2::auth::Auth;
         use ilo_ribcl::{
+            cassette::{Cassette, CassetteMode},
             client::{Node, ProxyClient},
             commands,
             types::FwVersion,
@@ -14,9 +15,17 @@ macro_rules! parse_node_auth {
 
         let endpoint_json = fs::read_to_string(&$opt.endpoint)
             .with_context(|| format!("missing or invalid endpoint file {}", endpoint_filename))?;
-        let mut node: Node = Node::from_json(&endpoint_json).await?;
 
-        if !$opt.no_update {
+        // don't contact the node when only replaying a cassette
+        let replay = $opt.proxy_cache && $opt.cassette_mode == CassetteMode::Replay;
+        let mut node: Node = if replay {
+            serde_json::from_str(&endpoint_json)
+                .with_context(|| format!("invalid endpoint file {}", endpoint_filename))?
+        } else {
+            Node::from_json(&endpoint_json).await?
+        };
+
+        if !$opt.no_update && !replay {
             serde_json::to_writer_pretty(
                 &fs::File::create(&$opt.endpoint)
                     .with_context(|| format!("couldn't open or create {}", endpoint_filename))?,
@@ -27,9 +36,26 @@ macro_rules! parse_node_auth {
 
         let mut node = if $opt.proxy_cache {
             let auth = node.auth();
-            let firmware = node.firmware().unwrap();
-            let client = Node::client_from_auth_and_fw(&auth, &firmware).unwrap();
-            let proxy_client = Box::new(ProxyClient::new(auth.clone(), firmware.clone(), client));
+            let cassette = Cassette::load(
+                &$opt.cassette_dir,
+                &auth.hostname,
+                &auth.password,
+                $opt.cassette_mode,
+            )
+            .with_context(|| format!("couldn't load cassette from {:?}", $opt.cassette_dir))?;
+            let firmware = node
+                .firmware()
+                .or_else(|| cassette.firmware().cloned())
+                .ok_or_else(|| {
+                    anyhow::anyhow!("no firmware version in {} or cassette", endpoint_filename)
+                })?;
+            let client = Node::client_from_settings(&auth, &firmware, &node.connection_settings())?;
+            let proxy_client = Box::new(ProxyClient::with_cassette(
+                auth.clone(),
+                firmware.clone(),
+                client,
+                cassette,
+            ));
             Node::new_with_fw_and_client(auth, firmware, proxy_client)
         } else {
             node
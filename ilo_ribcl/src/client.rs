@@ -1,26 +1,37 @@
 use async_recursion::async_recursion;
 use async_trait::async_trait;
-use ilo_console::ilo2::auth::Auth;
-use native_tls::TlsConnector;
+use ilo_console::{
+    https,
+    ilo2::auth::Auth,
+    proxy,
+    tls::{self, TlsPolicy},
+};
 use native_tls::TlsStream;
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "backtrace")]
 use std::backtrace::Backtrace;
 use std::{
-    fs::File,
     io::{Read, Write},
+    iter,
     net::TcpStream,
-    path::Path,
     result::Result,
     str,
+    sync::Arc,
+    time::{Duration, Instant},
     vec::Vec,
 };
 use thiserror::Error;
+use tokio::{task, time};
 use tracing::{event, instrument, Level};
 
 use crate::{
-    commands,
+    cassette::{self, Cassette, CassetteMode, Kind},
+    commands, firmware,
+    metrics::{Label, Recorder, RequestMetric},
+    redact,
+    retry::RetryPolicy,
     types::{FwVersion, Version},
+    xml,
 };
 
 #[non_exhaustive]
@@ -60,9 +71,32 @@ pub enum Error {
         backtrace: Backtrace,
     },
 
-    #[error("https client builder error: `{:#?}`", source)]
-    HttpsClientBuilder {
-        source: reqwest::Error,
+    #[error("couldn't start runtime: {source}")]
+    Runtime { source: std::io::Error },
+
+    #[error("the thread running the node has stopped")]
+    NodeStopped,
+
+    #[error("couldn't read credentials: `{source}`")]
+    Credentials {
+        #[from]
+        source: ilo_console::credentials::Error,
+        #[cfg(feature = "backtrace")]
+        backtrace: Backtrace,
+    },
+
+    #[error("certificate check failed: `{source}`")]
+    TlsPolicy {
+        #[from]
+        source: ilo_console::tls::Error,
+        #[cfg(feature = "backtrace")]
+        backtrace: Backtrace,
+    },
+
+    #[error("proxy error: `{source}`")]
+    Proxy {
+        #[from]
+        source: proxy::Error,
         #[cfg(feature = "backtrace")]
         backtrace: Backtrace,
     },
@@ -70,14 +104,46 @@ pub enum Error {
     #[error("failed to make https connect")]
     HttpsConnection,
 
-    #[error("https send error: `{:#?}`", source)]
-    HttpsSend {
+    #[error("connection closed by node before a response was received")]
+    ConnectionClosed,
+
+    /// The request couldn't be written, so the node didn't receive it
+    #[error("couldn't send the request: {source}")]
+    RequestWrite { source: std::io::Error },
+
+    #[error("timed out after {timeout:?} waiting to {operation}")]
+    Timeout {
+        operation: &'static str,
+        timeout: Duration,
+    },
+
+    #[error("cassette error: `{source}`")]
+    Cassette {
         #[from]
-        source: reqwest::Error,
+        source: cassette::Error,
+        #[cfg(feature = "backtrace")]
+        backtrace: Backtrace,
+    },
+
+    #[error("blocking io task failed: `{source}`")]
+    Join {
+        #[from]
+        source: task::JoinError,
         #[cfg(feature = "backtrace")]
         backtrace: Backtrace,
     },
 
+    #[error("https error: `{source}`")]
+    Https {
+        #[from]
+        source: https::Error,
+        #[cfg(feature = "backtrace")]
+        backtrace: Backtrace,
+    },
+
+    #[error("node responded with HTTP status {status}")]
+    HttpStatus { status: u16 },
+
     /*
     #[error("error requesting page with reqwest `{:#?}`", source)]
     Reqwest {
@@ -117,6 +183,9 @@ pub enum Error {
     #[error("unrecognized firmware version")]
     UnrecognizedFirmware(FwVersion),
 
+    #[error("upload rejected by the node: {status}")]
+    UploadRejected { status: String },
+
     #[error("invalid endpoint json file: {source}")]
     SerdeJson {
         #[from]
@@ -126,6 +195,24 @@ pub enum Error {
     },
 }
 
+impl Error {
+    /// Returns true if the request failed before it was written to the node,
+    /// while connecting or sending it
+    pub fn is_unsent(&self) -> bool {
+        use Error::*;
+        match self {
+            TlsHandshake { .. }
+            | TlsPolicy { .. }
+            | Proxy { .. }
+            | HttpsConnection
+            | RequestWrite { .. } => true,
+            Timeout { operation, .. } => ["connect", "send the request"].contains(operation),
+            Command { source, .. } => source.is_unsent(),
+            _ => false,
+        }
+    }
+}
+
 impl str::FromStr for Version {
     type Err = &'static str;
     fn from_str(version: &str) -> Result<Self, Self::Err> {
@@ -134,45 +221,178 @@ impl str::FromStr for Version {
             "2" => Ok(Ilo2),
             "3" => Ok(Ilo3),
             "4" => Ok(Ilo4),
-            _ => Err("Only version 2,3,4 or auto are supported value"),
+            "5" => Ok(Ilo5),
+            _ => Err("Only version 2,3,4,5 or auto are supported value"),
+        }
+    }
+}
+
+/// Controls how connections to a node are reused between requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ConnectionSettings {
+    /// Keep connections open between requests where the firmware allows it,
+    /// HTTP keep-alive on iLO 3/4 and the raw TLS port on iLO 2
+    pub keep_alive: bool,
+    /// How long to wait for more data after an iLO 2 response document reports
+    /// an error before treating the response as finished
+    pub response_idle_ms: u64,
+    /// Close pooled HTTPS connections that have been unused for this long
+    pub pool_idle_ms: u64,
+    /// How long to wait when opening a connection and for the TLS handshake
+    pub connect_timeout_ms: u64,
+    /// How long to wait for the node to send more of the response
+    pub read_timeout_ms: u64,
+    /// How long a whole request may take, including reconnecting, before it is abandoned
+    pub request_timeout_ms: u64,
+    /// How long a command may take across all of its attempts, including the
+    /// delays between retries
+    pub total_timeout_ms: u64,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        Self {
+            keep_alive: true,
+            response_idle_ms: 250,
+            pool_idle_ms: 30_000,
+            connect_timeout_ms: 10_000,
+            read_timeout_ms: 60_000,
+            request_timeout_ms: 120_000,
+            total_timeout_ms: 180_000,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn response_idle(&self) -> Duration {
+        Duration::from_millis(self.response_idle_ms)
+    }
+
+    fn pool_idle(&self) -> Duration {
+        Duration::from_millis(self.pool_idle_ms)
+    }
+
+    fn connect_timeout(&self) -> Duration {
+        Duration::from_millis(self.connect_timeout_ms)
+    }
+
+    fn read_timeout(&self) -> Duration {
+        Duration::from_millis(self.read_timeout_ms)
+    }
+
+    fn request_timeout(&self) -> Duration {
+        Duration::from_millis(self.request_timeout_ms)
+    }
+
+    fn total_timeout(&self) -> Duration {
+        Duration::from_millis(self.total_timeout_ms)
+    }
+
+    /// The settings with the timeouts overridden for a single call
+    fn with_timeouts(&self, timeouts: &Timeouts) -> Self {
+        let millis = |timeout: Option<Duration>, default| {
+            timeout.map_or(default, |timeout| timeout.as_millis() as u64)
+        };
+        Self {
+            connect_timeout_ms: millis(timeouts.connect, self.connect_timeout_ms),
+            read_timeout_ms: millis(timeouts.read, self.read_timeout_ms),
+            request_timeout_ms: millis(timeouts.request, self.request_timeout_ms),
+            total_timeout_ms: millis(timeouts.total, self.total_timeout_ms),
+            ..self.clone()
         }
     }
 }
 
+/// Timeouts for a single call, overriding the node's [ConnectionSettings]
+/// where set
+///
+/// ```
+/// use ilo_ribcl::client::Timeouts;
+/// use std::time::Duration;
+///
+/// let timeouts = Timeouts {
+///     connect: Some(Duration::from_secs(2)),
+///     total: Some(Duration::from_secs(30)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timeouts {
+    /// How long to wait when opening a connection and for the TLS handshake
+    pub connect: Option<Duration>,
+    /// How long to wait for the node to send more of the response
+    pub read: Option<Duration>,
+    /// How long each attempt may take
+    pub request: Option<Duration>,
+    /// How long the call may take across all of its attempts
+    pub total: Option<Duration>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Node {
     auth: Auth,
     firmware: Option<FwVersion>,
+    #[serde(default, skip_serializing_if = "ConnectionSettings::is_default")]
+    connection: ConnectionSettings,
+    #[serde(default, skip_serializing_if = "RetryPolicy::is_default")]
+    retry: RetryPolicy,
+    #[serde(skip)]
+    metrics: Option<Arc<dyn Recorder>>,
     #[serde(skip)]
     client: Option<Box<dyn Client>>,
 }
 
 impl Node {
     pub async fn from_json(json: &str) -> Result<Self, Error> {
-        match serde_json::from_str(json)? {
-            Node {
-                auth,
-                firmware: None,
-                ..
-            } => Node::new(auth).await,
-            Node {
-                auth,
-                firmware: Some(fw),
-                ..
-            } => Node::new_with_fw(auth, fw),
+        let Node {
+            mut auth,
+            firmware,
+            connection,
+            retry,
+            ..
+        } = serde_json::from_str(json)?;
+        // password commands may prompt or wait on an agent
+        let mut auth =
+            task::spawn_blocking(move || auth.resolve_credentials().map(|_| auth)).await??;
+        if auth.tls.needs_fingerprint() {
+            // record the fingerprint now so it's saved with the endpoint
+            check_host(&mut auth, connection.connect_timeout()).await?;
         }
+        let mut node = match firmware {
+            None => Self::auto_detect(auth, connection).await?,
+            Some(fw) => Self::new_with_fw_and_settings(auth, fw, connection)?,
+        };
+        node.retry = retry;
+        Ok(node)
     }
 
     pub async fn new(auth: Auth) -> Result<Self, Error> {
-        Self::auto_detect(auth).await
+        Self::auto_detect(auth, ConnectionSettings::default()).await
     }
 
     pub fn new_with_fw(auth: Auth, fw_version: FwVersion) -> Result<Self, Error> {
-        Ok(Self::new_with_fw_and_client(
-            auth.clone(),
-            fw_version.clone(),
-            Self::client_from_auth_and_fw(&auth, &fw_version)?,
-        ))
+        Self::new_with_fw_and_settings(auth, fw_version, ConnectionSettings::default())
+    }
+
+    pub fn new_with_fw_and_settings(
+        auth: Auth,
+        fw_version: FwVersion,
+        connection: ConnectionSettings,
+    ) -> Result<Self, Error> {
+        let client = Self::client_from_settings(&auth, &fw_version, &connection)?;
+        Ok(Self {
+            auth,
+            firmware: Some(fw_version),
+            connection,
+            retry: RetryPolicy::default(),
+            metrics: None,
+            client: Some(client),
+        })
     }
 
     pub fn new_with_fw_and_client(
@@ -183,46 +403,274 @@ impl Node {
         Self {
             auth,
             firmware: Some(fw_version),
+            connection: ConnectionSettings::default(),
+            retry: RetryPolicy::default(),
+            metrics: None,
             client: Some(client),
         }
     }
 
-    #[async_recursion(?Send)]
-    #[instrument(skip(self))]
     pub async fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, Error> {
+        let timeout = self.connection.request_timeout();
+        self.send_ribcl_with_timeout(request, timeout).await
+    }
+
+    /// Send a request, abandoning it if no response is received within `timeout`
+    pub async fn send_ribcl_with_timeout(
+        &mut self,
+        request: Vec<u8>,
+        timeout: Duration,
+    ) -> Result<String, Error> {
+        self.send_labelled(request, timeout, None).await
+    }
+
+    /// Send a request built from `requests`, recording its metrics under their names
+    pub(crate) async fn send_requests(
+        &mut self,
+        request: Vec<u8>,
+        requests: &[commands::Request],
+    ) -> Result<String, Error> {
+        let timeout = self.connection.request_timeout();
+        self.send_labelled(request, timeout, Some(Label::commands(requests)))
+            .await
+    }
+
+    /// Send a request, recording its metrics under `label` or, for raw requests
+    /// without one, the first command in the request
+    pub(crate) async fn send_labelled(
+        &mut self,
+        request: Vec<u8>,
+        timeout: Duration,
+        label: Option<Label>,
+    ) -> Result<String, Error> {
+        let timeouts = Timeouts {
+            request: Some(timeout),
+            ..Default::default()
+        };
+        self.send_with_timeouts(request, &timeouts, label).await
+    }
+
+    /// Send a request with the node's timeouts overridden by `timeouts`
+    #[async_recursion(?Send)]
+    #[instrument(skip(self, request, label))]
+    async fn send_with_timeouts(
+        &mut self,
+        request: Vec<u8>,
+        timeouts: &Timeouts,
+        label: Option<Label>,
+    ) -> Result<String, Error> {
+        let connection = self.connection.with_timeouts(timeouts);
+        let timeout = connection.request_timeout();
+        let label = self
+            .metrics
+            .as_ref()
+            .map(|_| label.unwrap_or_else(|| Label::scrape(&request)));
+        let request_bytes = request.len();
         loop {
             let firmware = self.firmware.clone();
             match self.client {
-                Some(ref mut client) => return client.send_ribcl(request).await,
+                Some(ref mut client) => {
+                    let start = Instant::now();
+                    client.set_connection_settings(&connection);
+                    let result = time::timeout(timeout, client.send_ribcl(request))
+                        .await
+                        .unwrap_or(Err(Error::Timeout {
+                            operation: "complete the request",
+                            timeout,
+                        }));
+                    client.set_connection_settings(&self.connection);
+                    if let Some(tls) = client.tls_policy() {
+                        self.auth.tls = tls;
+                    }
+                    if let (Some(metrics), Some(label)) = (&self.metrics, label) {
+                        metrics.record(&RequestMetric::ribcl(
+                            &self.auth.hostname,
+                            firmware.as_ref(),
+                            label,
+                            request_bytes,
+                            start.elapsed(),
+                            &result,
+                        ));
+                    }
+                    return result;
+                }
                 _ => match firmware {
                     Some(firmware) => {
-                        let auth = self.auth.clone();
-                        self.client = Some(Self::client_from_auth_and_fw(&auth, &firmware)?);
+                        self.client = Some(Self::client_from_settings(
+                            &self.auth,
+                            &firmware,
+                            &self.connection,
+                        )?);
                     }
                     _ => {
                         let auth = self.auth.clone();
-                        *self = Self::auto_detect(auth).await?;
+                        let retry = self.retry.clone();
+                        let metrics = self.metrics.take();
+                        *self = Self::auto_detect(auth, self.connection.clone()).await?;
+                        self.retry = retry;
+                        self.metrics = metrics;
                     }
                 },
             }
         }
     }
 
+    /// Send a single command and parse its response, retrying transient errors
+    /// according to the node's [RetryPolicy].  Commands that aren't
+    /// [idempotent](commands::Command::is_idempotent) are only retried when
+    /// they failed before reaching the node.
+    pub async fn send_command<T>(
+        &mut self,
+        command: commands::Command<T>,
+    ) -> Result<T, commands::Error> {
+        let timeout = self.connection.request_timeout();
+        self.send_command_with_timeout(command, timeout).await
+    }
+
+    /// Send a single command with a timeout for each attempt, overriding the
+    /// node's request timeout, see [Node::send_command_with_timeouts]
+    ///
+    /// ```no_run
+    /// # async fn run(node: &mut ilo_ribcl::client::Node) -> Result<(), ilo_ribcl::commands::Error> {
+    /// use std::time::Duration;
+    ///
+    /// let command = node.get_fw_version_command()?;
+    /// let firmware = node
+    ///     .send_command_with_timeout(command, Duration::from_secs(5))
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn send_command_with_timeout<T>(
+        &mut self,
+        command: commands::Command<T>,
+        timeout: Duration,
+    ) -> Result<T, commands::Error> {
+        let timeouts = Timeouts {
+            request: Some(timeout),
+            ..Default::default()
+        };
+        self.send_command_with_timeouts(command, timeouts).await
+    }
+
+    /// Send a single command with the node's timeouts overridden by
+    /// `timeouts`.  Attempts are cut short and no more retries are made once
+    /// the total timeout has passed.
+    ///
+    /// ```no_run
+    /// # async fn run(node: &mut ilo_ribcl::client::Node) -> Result<(), ilo_ribcl::commands::Error> {
+    /// use ilo_ribcl::client::Timeouts;
+    /// use std::time::Duration;
+    ///
+    /// let command = node.get_fw_version_command()?;
+    /// let timeouts = Timeouts {
+    ///     connect: Some(Duration::from_secs(2)),
+    ///     read: Some(Duration::from_secs(10)),
+    ///     total: Some(Duration::from_secs(30)),
+    ///     ..Default::default()
+    /// };
+    /// let firmware = node.send_command_with_timeouts(command, timeouts).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[instrument(
+        skip(self, command),
+        fields(section = command.section(), mode = command.mode(), command = command.name())
+    )]
+    pub async fn send_command_with_timeouts<T>(
+        &mut self,
+        command: commands::Command<T>,
+        mut timeouts: Timeouts,
+    ) -> Result<T, commands::Error> {
+        let mut request = String::new();
+        commands::write_document(&mut request, &self.auth, std::iter::once(&command.request))?;
+        let connection = self.connection.with_timeouts(&timeouts);
+        let total = connection.total_timeout();
+        let start = Instant::now();
+        let mut attempt = 1;
+        loop {
+            let remaining = match total.checked_sub(start.elapsed()) {
+                Some(remaining) if remaining > Duration::from_millis(0) => remaining,
+                _ => {
+                    return Err(Error::Timeout {
+                        operation: "complete the command",
+                        timeout: total,
+                    }
+                    .into())
+                }
+            };
+            timeouts.request = Some(connection.request_timeout().min(remaining));
+            let label = Label::commands(iter::once(&command.request));
+            let result = match self
+                .send_with_timeouts(request.clone().into_bytes(), &timeouts, Some(label))
+                .await
+            {
+                Ok(response) => command.parse(&response),
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                // a write that may have reached the node isn't repeated
+                Err(err)
+                    if (command.is_idempotent() || err.is_unsent())
+                        && self.retry.should_retry(attempt, &err) =>
+                {
+                    let delay = self.retry.backoff(attempt);
+                    if start.elapsed() + delay >= total {
+                        return Err(err);
+                    }
+                    event!(Level::WARN, %err, attempt, ?delay, "transient error, retrying");
+                    tokio::time::delay_for(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
     #[async_recursion(?Send)]
     #[instrument(skip(self))]
     pub async fn get_xmldata(&mut self, item: &str) -> Result<String, Error> {
         loop {
             let firmware = self.firmware.clone();
             match self.client {
-                Some(ref mut client) => return client.get_xmldata(item).await,
+                Some(ref mut client) => {
+                    let timeout = self.connection.request_timeout();
+                    let start = Instant::now();
+                    let result = time::timeout(timeout, client.get_xmldata(item))
+                        .await
+                        .unwrap_or(Err(Error::Timeout {
+                            operation: "complete the request",
+                            timeout,
+                        }));
+                    if let Some(tls) = client.tls_policy() {
+                        self.auth.tls = tls;
+                    }
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record(&RequestMetric::xmldata(
+                            &self.auth.hostname,
+                            firmware.as_ref(),
+                            item,
+                            start.elapsed(),
+                            &result,
+                        ));
+                    }
+                    return result;
+                }
                 _ => match firmware {
                     Some(firmware) => {
-                        let auth = self.auth.clone();
-                        self.client = Some(Self::client_from_auth_and_fw(&auth, &firmware)?);
+                        self.client = Some(Self::client_from_settings(
+                            &self.auth,
+                            &firmware,
+                            &self.connection,
+                        )?);
                     }
                     _ => {
                         let auth = self.auth.clone();
-                        *self = Self::auto_detect(auth).await?;
+                        let retry = self.retry.clone();
+                        let metrics = self.metrics.take();
+                        *self = Self::auto_detect(auth, self.connection.clone()).await?;
+                        self.retry = retry;
+                        self.metrics = metrics;
                     }
                 },
             }
@@ -232,13 +680,24 @@ impl Node {
     pub fn client_from_auth_and_fw(
         auth: &Auth,
         firmware: &FwVersion,
+    ) -> Result<Box<dyn Client + Send>, Error> {
+        Self::client_from_settings(auth, firmware, &ConnectionSettings::default())
+    }
+
+    pub fn client_from_settings(
+        auth: &Auth,
+        firmware: &FwVersion,
+        connection: &ConnectionSettings,
     ) -> Result<Box<dyn Client + Send>, Error> {
         use Version::*;
         match firmware {
             FwVersion {
                 management_processor: Some(Ilo2),
                 ..
-            } => Ok(Box::new(TlsClient::new(auth.clone()))),
+            } => Ok(Box::new(TlsClient::with_settings(
+                auth.clone(),
+                connection.clone(),
+            ))),
             FwVersion {
                 management_processor: Some(Ilo3),
                 ..
@@ -246,24 +705,77 @@ impl Node {
             | FwVersion {
                 management_processor: Some(Ilo4),
                 ..
-            } => Ok(Box::new(HttpsClient::new(auth.clone()))),
+            }
+            | FwVersion {
+                management_processor: Some(Ilo5),
+                ..
+            } => Ok(Box::new(HttpsClient::with_settings(
+                auth.clone(),
+                connection.clone(),
+            ))),
             _ => Err(Error::UnrecognizedFirmware(firmware.clone())),
         }
     }
 
+    /// Detect the management processor from the unauthenticated `/xmldata`
+    /// response, falling back to logging in over HTTPS and then raw TLS when
+    /// the request fails or the node doesn't publish it
     #[instrument]
-    async fn auto_detect(auth: Auth) -> Result<Self, Error> {
+    async fn auto_detect(auth: Auth, connection: ConnectionSettings) -> Result<Self, Error> {
+        let mut https = HttpsClient::with_settings(auth, connection.clone());
+        let firmware = match https.get_xmldata("All").await {
+            Ok(response) => firmware::fw_version_from_xmldata(&response).unwrap_or_else(|err| {
+                event!(Level::DEBUG, %err, "xmldata isn't a RIMP document");
+                None
+            }),
+            // logging in won't be allowed with a certificate that doesn't match either
+            Err(err @ Error::TlsPolicy { .. }) => return Err(err),
+            Err(err) => {
+                event!(Level::DEBUG, %err, "xmldata unavailable");
+                None
+            }
+        };
+        // the https client records the certificate fingerprint on first use
+        let auth = https.auth.clone();
+        if let Some(firmware) = firmware {
+            event!(Level::DEBUG, ?firmware, "detected from xmldata");
+            let client: Box<dyn Client> = match firmware.management_processor {
+                Some(Version::Ilo3) | Some(Version::Ilo4) | Some(Version::Ilo5) => Box::new(https),
+                _ => Self::client_from_settings(&auth, &firmware, &connection)?,
+            };
+            return Ok(Self {
+                auth,
+                firmware: Some(firmware),
+                connection,
+                retry: RetryPolicy::default(),
+                metrics: None,
+                client: Some(client),
+            });
+        }
+
+        event!(
+            Level::DEBUG,
+            "xmldata doesn't identify the node, logging in"
+        );
         let mut node = Self {
-            auth: auth.clone(),
+            auth,
             firmware: None,
-            client: Some(Box::new(HttpsClient::new(auth.clone()))),
+            connection: connection.clone(),
+            retry: RetryPolicy::default(),
+            metrics: None,
+            client: Some(Box::new(https)),
         };
         match node.get_fw_version().await {
             Ok(firmware) => {
                 node.firmware = Some(firmware);
             }
-            _ => {
-                node.client = Some(Box::new(TlsClient::new(auth.clone())));
+            // the node answered, e.g. rejecting the password, so the transport is right
+            Err(err) if err.response_code().is_some() => return Err(Box::new(err).into()),
+            Err(_) => {
+                node.client = Some(Box::new(TlsClient::with_settings(
+                    node.auth.clone(),
+                    connection,
+                )));
                 node.firmware =
                     Some(
                         node.get_fw_version()
@@ -284,52 +796,465 @@ impl Node {
     pub fn firmware(&self) -> Option<FwVersion> {
         self.firmware.clone()
     }
+
+    pub fn connection_settings(&self) -> ConnectionSettings {
+        self.connection.clone()
+    }
+
+    /// Replace the certificate policy, e.g. after the node's certificate has
+    /// been replaced, reconnecting on the next request
+    pub fn set_tls_policy(&mut self, tls: TlsPolicy) {
+        self.auth.tls = tls;
+        self.client = None;
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry.clone()
+    }
+
+    pub fn set_retry_policy(&mut self, retry: RetryPolicy) {
+        self.retry = retry;
+    }
+
+    /// Report latency, size and outcome of every request to `recorder`
+    pub fn set_metrics_recorder(&mut self, recorder: Arc<dyn Recorder>) {
+        self.metrics = Some(recorder);
+    }
+
+    /// Set how long a request may take before it is abandoned
+    pub fn set_request_timeout(&mut self, timeout: Duration) {
+        self.connection.request_timeout_ms = timeout.as_millis() as u64;
+    }
+
+    /// Record firmware reported after an update so it's saved with the endpoint
+    pub(crate) fn set_firmware(&mut self, firmware: FwVersion) {
+        self.firmware = Some(firmware);
+    }
+
+    /// Send a RIBCL request on a new raw TLS connection with `image` written
+    /// between `header` and `footer`, as iLO 2 expects firmware images
+    #[instrument(skip(self, header, image, footer, progress))]
+    pub(crate) async fn send_ribcl_with_image(
+        &mut self,
+        header: Vec<u8>,
+        image: Vec<u8>,
+        footer: Vec<u8>,
+        timeout: Duration,
+        label: Label,
+        mut progress: Box<dyn FnMut(u64, u64) + Send>,
+    ) -> Result<String, Error> {
+        event!(Level::DEBUG, request = %redact::redact_bytes(&header), image = image.len());
+        let mut auth = self.auth.clone();
+        let connection = self.connection.clone();
+        let request_bytes = header.len() + image.len() + footer.len();
+        let start = Instant::now();
+        let (auth, response) = task::spawn_blocking(move || {
+            let parts = [header.as_slice(), image.as_slice(), footer.as_slice()];
+            let response = upload(&mut auth, &connection, &parts, timeout, &mut *progress);
+            (auth, response)
+        })
+        .await?;
+        self.auth.tls = auth.tls;
+        let result = response.and_then(|response| Ok(String::from_utf8(response)?));
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&RequestMetric::ribcl(
+                &self.auth.hostname,
+                self.firmware.as_ref(),
+                label,
+                request_bytes,
+                start.elapsed(),
+                &result,
+            ));
+        }
+        let response = result?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
+        Ok(response)
+    }
+
+    /// Upload a file with a `multipart/form-data` post to `/cgi-bin/uploadRibclFiles`,
+    /// as iLO 3 and iLO 4 expect firmware images, returning the session cookie
+    /// the command using the file is sent with
+    #[instrument(skip(self, data, progress))]
+    pub(crate) async fn upload_ribcl_file(
+        &mut self,
+        file_name: &str,
+        data: Vec<u8>,
+        timeout: Duration,
+        mut progress: Box<dyn FnMut(u64, u64) + Send>,
+    ) -> Result<Option<String>, Error> {
+        let boundary = format!("------ilo_ribcl{}z", rand::random::<u64>());
+        // percent encode the characters that would end the quoted file name, as browsers do
+        let file_name = file_name
+            .replace('"', "%22")
+            .replace('\r', "%0D")
+            .replace('\n', "%0A");
+        let head = format!(
+            "--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"fileType\"\r\n\r\n\
+             \r\n--{boundary}\r\n\
+             Content-Disposition: form-data; name=\"fwimgfile\"; filename=\"{file_name}\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\n",
+            boundary = boundary,
+            file_name = file_name
+        );
+        let tail = format!("\r\n--{}--\r\n", boundary);
+        let headers = format!(
+            "POST /cgi-bin/uploadRibclFiles HTTP/1.1\r\n\
+             Host: {}\r\n\
+             Connection: Close\r\n\
+             Content-Length: {}\r\n\
+             Content-Type: multipart/form-data; boundary={}\r\n\r\n",
+            self.auth.host(),
+            head.len() + data.len() + tail.len(),
+            boundary
+        );
+        let mut auth = self.auth.clone();
+        let connection = self.connection.clone();
+        let request_bytes = headers.len() + head.len() + data.len() + tail.len();
+        let start = Instant::now();
+        let (auth, response) = task::spawn_blocking(move || {
+            let parts = [
+                headers.as_bytes(),
+                head.as_bytes(),
+                data.as_slice(),
+                tail.as_bytes(),
+            ];
+            let response = upload(&mut auth, &connection, &parts, timeout, &mut *progress);
+            (auth, response)
+        })
+        .await?;
+        self.auth.tls = auth.tls;
+        let result = response.map(|response| String::from_utf8_lossy(&response).into_owned());
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&RequestMetric::upload(
+                &self.auth.hostname,
+                self.firmware.as_ref(),
+                request_bytes,
+                start.elapsed(),
+                &result,
+            ));
+        }
+        let response = result?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
+        upload_cookie(&response)
+    }
+
+    /// Send a RIBCL request over HTTPS with a session cookie returned by
+    /// [Node::upload_ribcl_file]
+    #[instrument(skip(self, request, cookie))]
+    pub(crate) async fn send_ribcl_with_cookie(
+        &mut self,
+        request: Vec<u8>,
+        cookie: &str,
+        timeout: Duration,
+        label: Label,
+    ) -> Result<String, Error> {
+        event!(Level::DEBUG, request = %redact::redact_bytes(&request));
+        let request_bytes = request.len();
+        // the node flashes the image before it responds
+        let mut connection = self.connection.clone();
+        connection.read_timeout_ms = timeout.as_millis() as u64;
+        let connection = HttpConnection {
+            auth: self.auth.clone(),
+            connection,
+            stream: None,
+        };
+        let cookie = cookie.to_string();
+        let start = Instant::now();
+        let (connection, response) = connection
+            .spawn(move |connection| {
+                let headers = [("Cookie", cookie.as_str())];
+                connection.send(&https::Request::post("/ribcl", &request).headers(&headers))
+            })
+            .await?;
+        self.auth.tls = connection.auth.tls;
+        let result = response.and_then(|response| Ok(String::from_utf8(response.body)?));
+        if let Some(metrics) = &self.metrics {
+            metrics.record(&RequestMetric::ribcl(
+                &self.auth.hostname,
+                self.firmware.as_ref(),
+                label,
+                request_bytes,
+                start.elapsed(),
+                &result,
+            ));
+        }
+        let response = result?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
+        Ok(response)
+    }
 }
 
 #[async_trait]
 pub trait Client: std::fmt::Debug + Send {
     async fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, Error>;
     async fn get_xmldata(&mut self, item: &str) -> Result<String, Error>;
+
+    /// The certificate policy in use, including any fingerprint recorded on first use
+    fn tls_policy(&self) -> Option<TlsPolicy> {
+        None
+    }
+
+    /// Use `connection`'s timeouts for the following requests
+    fn set_connection_settings(&mut self, _connection: &ConnectionSettings) {}
+}
+
+/// Convert an io error on a socket with a timeout set, reporting an expired
+/// timeout as [Error::Timeout]
+fn io_error(operation: &'static str, timeout: Duration, err: std::io::Error) -> Error {
+    use std::io::ErrorKind::*;
+    match err.kind() {
+        WouldBlock | TimedOut => Error::Timeout { operation, timeout },
+        _ => err.into(),
+    }
+}
+
+/// Convert an error writing a request to the node
+fn write_error(timeout: Duration, err: std::io::Error) -> Error {
+    match io_error("send the request", timeout, err) {
+        Error::TlsWrite { source, .. } => Error::RequestWrite { source },
+        err => err,
+    }
+}
+
+fn tls_error(timeout: Duration, err: tls::Error) -> Error {
+    match err {
+        tls::Error::Io(err) | tls::Error::Proxy(proxy::Error::Io(err)) => {
+            io_error("connect", timeout, err)
+        }
+        err => err.into(),
+    }
+}
+
+/// Convert an error exchanging an HTTP request with the node
+fn https_error(timeout: Duration, err: https::Error) -> Error {
+    match err {
+        https::Error::Tls(err) => tls_error(timeout, err),
+        https::Error::Write(err) => write_error(timeout, err),
+        https::Error::Read(err) => io_error("receive the response", timeout, err),
+        https::Error::Closed => Error::ConnectionClosed,
+        err => err.into(),
+    }
+}
+
+/// Check the certificate of a pinned node on the blocking thread pool,
+/// recording the fingerprint in `auth` on first use
+async fn check_host(auth: &mut Auth, timeout: Duration) -> Result<(), Error> {
+    let mut tls = auth.tls.clone();
+    let host = auth.host().to_string();
+    let port = auth.https_port();
+    let proxy = auth.proxy.clone();
+    auth.tls = task::spawn_blocking(move || {
+        tls.check_host(&host, port, Some(timeout), proxy.as_ref())
+            .map(|_| tls)
+            .map_err(|err| tls_error(timeout, err))
+    })
+    .await??;
+    Ok(())
+}
+
+fn connect_tls(
+    auth: &mut Auth,
+    connection: &ConnectionSettings,
+) -> Result<TlsStream<TcpStream>, Error> {
+    let timeout = connection.connect_timeout();
+    let host = auth.host().to_string();
+    let port = auth.https_port();
+    auth.tls
+        .connect(&host, port, Some(timeout), auth.proxy.as_ref())
+        .map_err(|err| tls_error(timeout, err))
+}
+
+/// Read an iLO 2 response, returning it and whether the connection can be reused.
+///
+/// The raw TLS port doesn't frame responses. With `documents`, the response is
+/// complete once that many documents have been closed, or once the node stops
+/// sending after a document with an error status. Otherwise it is read until the
+/// node closes the connection.
+fn read_response(
+    stream: &mut TlsStream<TcpStream>,
+    documents: Option<usize>,
+    idle: Duration,
+    timeout: Duration,
+) -> Result<(Vec<u8>, bool), Error> {
+    let mut response = vec![];
+    let documents = match documents {
+        Some(documents) => documents,
+        None => {
+            stream.get_ref().set_read_timeout(Some(timeout))?;
+            stream
+                .read_to_end(&mut response)
+                .map_err(|err| io_error("receive the response", timeout, err))?;
+            return Ok((response, false));
+        }
+    };
+    stream.get_ref().set_read_timeout(Some(idle.min(timeout)))?;
+    let mut buf = [0u8; 8192];
+    let mut last_read = Instant::now();
+    loop {
+        match stream.read(&mut buf) {
+            Ok(0) if response.is_empty() => return Err(Error::ConnectionClosed),
+            Ok(0) => return Ok((response, false)),
+            Ok(n) => {
+                response.extend_from_slice(&buf[..n]);
+                last_read = Instant::now();
+                let complete = str::from_utf8(&response)
+                    .map(|r| xml::complete_documents(r) >= documents)
+                    .unwrap_or(false);
+                if complete {
+                    return Ok((response, true));
+                }
+            }
+            Err(err)
+                if err.kind() == std::io::ErrorKind::WouldBlock
+                    || err.kind() == std::io::ErrorKind::TimedOut =>
+            {
+                // the remaining documents may still arrive, so don't reuse the connection
+                let failed = str::from_utf8(&response)
+                    .map(xml::ends_with_error)
+                    .unwrap_or(false);
+                if failed {
+                    return Ok((response, false));
+                }
+                if last_read.elapsed() >= timeout {
+                    return Err(Error::Timeout {
+                        operation: "receive the response",
+                        timeout,
+                    });
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Size of the writes an upload is sent in, progress is reported after each
+const UPLOAD_CHUNK_LEN: usize = 64 * 1024;
+
+/// Write `parts` to a new raw TLS connection, reporting the bytes sent and the
+/// total after each chunk, then read the response until the node closes the
+/// connection or `timeout` passes
+fn upload(
+    auth: &mut Auth,
+    connection: &ConnectionSettings,
+    parts: &[&[u8]],
+    timeout: Duration,
+    progress: &mut (dyn FnMut(u64, u64) + Send),
+) -> Result<Vec<u8>, Error> {
+    let mut stream = connect_tls(auth, connection)?;
+    let write_timeout = connection.read_timeout();
+    stream.get_ref().set_write_timeout(Some(write_timeout))?;
+    let total = parts.iter().map(|part| part.len() as u64).sum();
+    let mut sent = 0;
+    for chunk in parts.iter().flat_map(|part| part.chunks(UPLOAD_CHUNK_LEN)) {
+        stream
+            .write_all(chunk)
+            .map_err(|err| io_error("send the upload", write_timeout, err))?;
+        sent += chunk.len() as u64;
+        progress(sent, total);
+    }
+    stream.flush()?;
+    let (response, _) = read_response(&mut stream, None, connection.response_idle(), timeout)?;
+    Ok(response)
+}
+
+/// Check the status of an upload response and return the session cookie it sets
+fn upload_cookie(response: &str) -> Result<Option<String>, Error> {
+    let mut lines = response.lines();
+    let status = lines.next().unwrap_or_default().trim();
+    if status.split_whitespace().nth(1) != Some("200") {
+        return Err(Error::UploadRejected {
+            status: status.to_string(),
+        });
+    }
+    Ok(lines
+        .take_while(|line| !line.trim().is_empty())
+        .filter_map(|line| {
+            let index = line.find(':')?;
+            if line[..index].trim().eq_ignore_ascii_case("set-cookie") {
+                line[index + 1..]
+                    .split(';')
+                    .next()
+                    .map(|cookie| cookie.trim().to_string())
+            } else {
+                None
+            }
+        })
+        .next())
+}
+
+/// The blocking state of a [TlsClient], moved onto the blocking thread pool for
+/// each request so a hung node doesn't stall the executor
+#[derive(Debug)]
+struct TlsConnection {
+    auth: Auth,
+    connection: ConnectionSettings,
+    stream: Option<TlsStream<TcpStream>>,
+}
+
+impl TlsConnection {
+    /// Send the request on the open connection or a new one and read the response
+    fn exchange(&mut self, request: &[u8]) -> Result<String, Error> {
+        let mut stream = match self.stream.take() {
+            Some(stream) => stream,
+            None => connect_tls(&mut self.auth, &self.connection)?,
+        };
+        let timeout = self.connection.read_timeout();
+        stream.get_ref().set_write_timeout(Some(timeout))?;
+        stream
+            .write_all(request)
+            .map_err(|err| write_error(timeout, err))?;
+        let documents = if self.connection.keep_alive {
+            xml::expected_documents(request)
+        } else {
+            None
+        };
+        let idle = self.connection.response_idle();
+        let (response, reusable) = read_response(&mut stream, documents, idle, timeout)?;
+        if reusable {
+            self.stream = Some(stream);
+        }
+        Ok(String::from_utf8(response)?)
+    }
+
+    fn send_ribcl(&mut self, request: &[u8]) -> Result<String, Error> {
+        let reused = self.stream.is_some();
+        match self.exchange(request) {
+            Err(err) if reused && err.is_unsent() => {
+                // the node closed the kept open connection before the request
+                // reached it, send it again on a new one
+                event!(Level::DEBUG, ?err, "reconnecting");
+                self.exchange(request)
+            }
+            result => result,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct TlsClient {
     pub auth: Auth,
-    http_client: Option<reqwest::Client>,
+    connection: ConnectionSettings,
+    stream: Option<TlsStream<TcpStream>>,
 }
 
 impl TlsClient {
     pub fn new(auth: Auth) -> Self {
+        Self::with_settings(auth, ConnectionSettings::default())
+    }
+
+    pub fn with_settings(auth: Auth, connection: ConnectionSettings) -> Self {
         Self {
             auth,
-            http_client: None,
+            connection,
+            stream: None,
         }
     }
 
+    /// Open a new connection to the node, this blocks until the handshake completes
     #[instrument(skip(self))]
     pub fn tls_stream(&mut self) -> Result<TlsStream<TcpStream>, Error> {
-        let mut builder = TlsConnector::builder();
-        builder.danger_accept_invalid_certs(true);
-        let connector = builder.build()?;
-        let url = format!("{}:443", self.auth.hostname);
-        let stream = TcpStream::connect(&url)?;
-        let tls_stream = connector.connect(&self.auth.hostname, stream)?;
-        Ok(tls_stream)
-    }
-
-    #[instrument(skip(self))]
-    pub fn http_client(&mut self) -> Result<&mut Option<reqwest::Client>, Error> {
-        if self.http_client.is_none() {
-            let client = reqwest::Client::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .use_native_tls()
-                .cookie_store(true)
-                .build()
-                .map_err(|source| Error::HttpsClientBuilder { source })?;
-            self.http_client = Some(client);
-        }
-        Ok(&mut self.http_client)
+        connect_tls(&mut self.auth, &self.connection)
     }
 }
 
@@ -337,157 +1262,390 @@ impl TlsClient {
 impl Client for TlsClient {
     #[instrument(skip(self))]
     async fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, Error> {
-        let mut stream = self.tls_stream()?;
-        stream
-            .write_all(&request)
-            .map_err(|source| Error::TlsWrite { source })?;
-        let mut response = vec![];
-        event!(
-            Level::DEBUG,
-            request = String::from_utf8_lossy(&request).as_ref()
-        );
-        stream.read_to_end(&mut response)?;
-        let response = String::from_utf8(response)?;
-        event!(Level::DEBUG, ?response);
+        event!(Level::DEBUG, request = %redact::redact_bytes(&request));
+        // if this future is dropped the connection is closed once the blocking
+        // task finishes and the next request opens a new one.
+        let mut connection = TlsConnection {
+            auth: self.auth.clone(),
+            connection: self.connection.clone(),
+            stream: self.stream.take(),
+        };
+        let (connection, response) = task::spawn_blocking(move || {
+            let response = connection.send_ribcl(&request);
+            (connection, response)
+        })
+        .await?;
+        self.auth.tls = connection.auth.tls;
+        self.stream = connection.stream;
+        let response = response?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
         Ok(response)
     }
 
     async fn get_xmldata(&mut self, item: &str) -> Result<String, Error> {
-        let url = format!("https://{}:443/xmldata?item={}", &self.auth.hostname, item);
-        if let Some(client) = self.http_client()? {
-            event!(Level::DEBUG, item);
-            let response = client.get(&url).send().await?.text().await?;
-            event!(Level::DEBUG, ?response);
-            Ok(response)
-        } else {
-            Err(Error::HttpsConnection)
-        }
+        event!(Level::DEBUG, item);
+        // iLO 2 serves xmldata over HTTPS, each request on a new connection
+        let connection = HttpConnection {
+            auth: self.auth.clone(),
+            connection: self.connection.clone(),
+            stream: None,
+        };
+        let path = xmldata_path(item);
+        let (connection, response) = connection
+            .spawn(move |connection| connection.send(&https::Request::get(&path)))
+            .await?;
+        self.auth.tls = connection.auth.tls;
+        let response = xmldata_text(response?)?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
+        Ok(response)
+    }
+
+    fn tls_policy(&self) -> Option<TlsPolicy> {
+        Some(self.auth.tls.clone())
+    }
+
+    fn set_connection_settings(&mut self, connection: &ConnectionSettings) {
+        self.connection = connection.clone();
     }
 }
 
 #[derive(Debug)]
 pub struct HttpsClient {
     pub auth: Auth,
-    http_client: Option<reqwest::Client>,
+    connection: ConnectionSettings,
+    /// The kept open connection and when it was last used
+    stream: Option<(TlsStream<TcpStream>, Instant)>,
 }
 
 impl HttpsClient {
     pub fn new(auth: Auth) -> Self {
+        Self::with_settings(auth, ConnectionSettings::default())
+    }
+
+    pub fn with_settings(auth: Auth, connection: ConnectionSettings) -> Self {
         Self {
             auth,
-            http_client: None,
+            connection,
+            stream: None,
         }
     }
 
-    #[instrument(skip(self))]
-    pub fn http_client(&mut self) -> Result<&mut Option<reqwest::Client>, Error> {
-        if self.http_client.is_none() {
-            let client = reqwest::Client::builder()
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true)
-                .use_native_tls()
-                .cookie_store(true)
-                .build()
-                .map_err(|source| Error::HttpsClientBuilder { source })?;
-            self.http_client = Some(client);
-        }
-        Ok(&mut self.http_client)
+    /// Send a request on the kept open connection or a new one
+    async fn send<F>(&mut self, exchange: F) -> Result<https::Response, Error>
+    where
+        F: FnOnce(&mut HttpConnection) -> Result<https::Response, Error> + Send + 'static,
+    {
+        // if this future is dropped the connection is closed once the blocking
+        // task finishes and the next request opens a new one.
+        let connection = HttpConnection {
+            auth: self.auth.clone(),
+            connection: self.connection.clone(),
+            stream: self.stream.take(),
+        };
+        let (connection, response) = connection.spawn(exchange).await?;
+        self.auth.tls = connection.auth.tls;
+        self.stream = connection.stream;
+        response
     }
 }
 
 #[async_trait]
 impl Client for HttpsClient {
     async fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, Error> {
-        let url = format!("https://{}:443/ribcl", &self.auth.hostname);
-        if let Some(client) = self.http_client()? {
-            event!(
-                Level::DEBUG,
-                request = String::from_utf8_lossy(&request).as_ref()
-            );
-            let response = client.post(&url).body(request).send().await?.text().await?;
-            event!(Level::DEBUG, ?response);
-            Ok(response)
-        } else {
-            Err(Error::HttpsConnection)
-        }
+        event!(Level::DEBUG, request = %redact::redact_bytes(&request));
+        let response = self
+            .send(move |connection| connection.send(&https::Request::post("/ribcl", &request)))
+            .await?;
+        let response = String::from_utf8(response.body)?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
+        Ok(response)
     }
 
     async fn get_xmldata(&mut self, item: &str) -> Result<String, Error> {
-        let url = format!("https://{}:443/xmldata?item={}", &self.auth.hostname, item);
-        if let Some(client) = self.http_client()? {
-            event!(Level::DEBUG, item);
-            let response = client.get(&url).send().await?.text().await?;
-            event!(Level::DEBUG, ?response);
-            Ok(response)
-        } else {
-            Err(Error::HttpsConnection)
+        event!(Level::DEBUG, item);
+        let path = xmldata_path(item);
+        let response = self
+            .send(move |connection| connection.send(&https::Request::get(&path)))
+            .await?;
+        let response = xmldata_text(response)?;
+        event!(Level::DEBUG, response = %redact::redact(&response));
+        Ok(response)
+    }
+
+    fn tls_policy(&self) -> Option<TlsPolicy> {
+        Some(self.auth.tls.clone())
+    }
+
+    fn set_connection_settings(&mut self, connection: &ConnectionSettings) {
+        self.connection = connection.clone();
+    }
+}
+
+fn xmldata_path(item: &str) -> String {
+    let item: String = url::form_urlencoded::byte_serialize(item.as_bytes()).collect();
+    format!("/xmldata?item={}", item)
+}
+
+/// The body of an xmldata response, which is only sent with a success status
+fn xmldata_text(response: https::Response) -> Result<String, Error> {
+    if !response.is_success() {
+        return Err(Error::HttpStatus {
+            status: response.status,
+        });
+    }
+    Ok(String::from_utf8(response.body)?)
+}
+
+/// The blocking state of a [HttpsClient], moved onto the blocking thread pool
+/// for each request like [TlsConnection]
+#[derive(Debug)]
+struct HttpConnection {
+    auth: Auth,
+    connection: ConnectionSettings,
+    stream: Option<(TlsStream<TcpStream>, Instant)>,
+}
+
+impl HttpConnection {
+    /// Run `exchange` on the blocking thread pool, returning the connection so
+    /// its stream can be reused
+    async fn spawn<F>(
+        mut self,
+        exchange: F,
+    ) -> Result<(Self, Result<https::Response, Error>), Error>
+    where
+        F: FnOnce(&mut Self) -> Result<https::Response, Error> + Send + 'static,
+    {
+        Ok(task::spawn_blocking(move || {
+            let response = exchange(&mut self);
+            (self, response)
+        })
+        .await?)
+    }
+
+    /// Send the request on the open connection or a new one and read the response
+    fn exchange(&mut self, request: &https::Request<'_>) -> Result<https::Response, Error> {
+        let mut stream = match self.stream.take() {
+            Some((stream, used))
+                if used.elapsed() < self.connection.pool_idle() && https::is_reusable(&stream) =>
+            {
+                stream
+            }
+            _ => connect_tls(&mut self.auth, &self.connection)?,
+        };
+        let timeout = self.connection.read_timeout();
+        let host = https::host_header(self.auth.host(), self.auth.https_port());
+        let response = https::exchange(
+            &mut stream,
+            &host,
+            request,
+            self.connection.keep_alive,
+            Some(timeout),
+        )
+        .map_err(|err| https_error(timeout, err))?;
+        if response.keep_alive {
+            self.stream = Some((stream, Instant::now()));
+        }
+        Ok(response)
+    }
+
+    fn send(&mut self, request: &https::Request<'_>) -> Result<https::Response, Error> {
+        let reused = self.stream.is_some();
+        match self.exchange(request) {
+            Err(err) if reused && err.is_unsent() => {
+                // the node closed the kept open connection before the request
+                // reached it, send it again on a new one
+                event!(Level::DEBUG, ?err, "reconnecting");
+                self.exchange(request)
+            }
+            result => result,
         }
     }
 }
 
+/// Wraps another client, recording its responses to a [Cassette] and replaying them
 #[derive(Debug)]
 pub struct ProxyClient {
     auth: Auth,
     firmware: FwVersion,
     client: Box<dyn Client + Send>,
+    cassette: Cassette,
 }
 
 impl ProxyClient {
-    pub fn new(auth: Auth, firmware: FwVersion, client: Box<dyn Client + Send>) -> Self {
+    /// Record to a cassette in the current directory
+    pub fn new(
+        auth: Auth,
+        firmware: FwVersion,
+        client: Box<dyn Client + Send>,
+    ) -> Result<Self, Error> {
+        let cassette = Cassette::load(".", &auth.hostname, &auth.password, CassetteMode::Record)?;
+        Ok(Self::with_cassette(auth, firmware, client, cassette))
+    }
+
+    pub fn with_cassette(
+        auth: Auth,
+        firmware: FwVersion,
+        client: Box<dyn Client + Send>,
+        cassette: Cassette,
+    ) -> Self {
         Self {
             auth,
             firmware,
             client,
+            cassette,
         }
     }
 
-    fn hash_request(&self, request: &[u8]) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::Hasher;
-        let mut hasher = DefaultHasher::new();
-        hasher.write(request);
-        hasher.finish()
+    pub fn cassette(&self) -> &Cassette {
+        &self.cassette
     }
 }
 
 #[async_trait]
 impl Client for ProxyClient {
     async fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, Error> {
-        let hash_id = self.hash_request(&request);
-        let request_filename = format!("{}-{}-request.xml", self.auth.hostname, hash_id);
-        let response_filename = format!("{}-{}-response.xml", self.auth.hostname, hash_id);
-        if Path::new(&request_filename).is_file() && Path::new(&response_filename).is_file() {
+        let request = String::from_utf8(request)?;
+        if let Some(response) = self.cassette.play(Kind::Ribcl, &request)? {
             event!(
                 Level::DEBUG,
-                request = String::from_utf8_lossy(&request).as_ref()
+                hostname = %self.auth.hostname,
+                request = %redact::redact(&request),
+                "replaying"
             );
-            let mut f = File::open(&response_filename)?;
-            let mut response = String::new();
-            f.read_to_string(&mut response)?;
-            event!(Level::DEBUG, ?response);
-            Ok(response)
-        } else {
-            File::create(&request_filename)?.write_all(&request)?;
-            let response = self.client.send_ribcl(request).await?;
-            File::create(&response_filename)?.write_all(response.as_bytes())?;
-            Ok(response)
+            event!(Level::DEBUG, response = %redact::redact(&response));
+            return Ok(response);
         }
+        let response = self.client.send_ribcl(request.clone().into_bytes()).await?;
+        self.cassette.set_firmware(&self.firmware)?;
+        self.cassette.record(Kind::Ribcl, &request, &response)?;
+        Ok(response)
     }
 
     async fn get_xmldata(&mut self, item: &str) -> Result<String, Error> {
-        let hash_id = item;
-        let response_filename = format!("{}-{}-response.xml", self.auth.hostname, hash_id);
-        if Path::new(&response_filename).is_file() {
-            event!(Level::DEBUG, item);
-            let mut f = File::open(&response_filename)?;
-            let mut response = String::new();
-            f.read_to_string(&mut response)?;
-            event!(Level::DEBUG, ?response);
-            Ok(response)
-        } else {
-            let response = self.client.get_xmldata(item).await?;
-            File::create(&response_filename)?.write_all(response.as_bytes())?;
-            Ok(response)
+        if let Some(response) = self.cassette.play(Kind::Xmldata, item)? {
+            event!(Level::DEBUG, hostname = %self.auth.hostname, item, "replaying");
+            event!(Level::DEBUG, response = %redact::redact(&response));
+            return Ok(response);
         }
+        let response = self.client.get_xmldata(item).await?;
+        self.cassette.set_firmware(&self.firmware)?;
+        self.cassette.record(Kind::Xmldata, item, &response)?;
+        Ok(response)
+    }
+
+    fn tls_policy(&self) -> Option<TlsPolicy> {
+        self.client.tls_policy()
+    }
+
+    fn set_connection_settings(&mut self, connection: &ConnectionSettings) {
+        self.client.set_connection_settings(connection)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    /// A node that accepts requests and never answers
+    #[derive(Debug, Default)]
+    struct Hung {
+        sent: Arc<AtomicUsize>,
+        dropped: Arc<AtomicBool>,
+        read_timeouts: Arc<Mutex<Vec<u64>>>,
+    }
+
+    /// Records that the request future was dropped
+    struct Dropped(Arc<AtomicBool>);
+
+    impl Drop for Dropped {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[async_trait]
+    impl Client for Hung {
+        async fn send_ribcl(&mut self, _request: Vec<u8>) -> Result<String, Error> {
+            self.sent.fetch_add(1, Ordering::SeqCst);
+            let _dropped = Dropped(self.dropped.clone());
+            futures::future::pending().await
+        }
+
+        async fn get_xmldata(&mut self, _item: &str) -> Result<String, Error> {
+            futures::future::pending().await
+        }
+
+        fn set_connection_settings(&mut self, connection: &ConnectionSettings) {
+            self.read_timeouts
+                .lock()
+                .unwrap()
+                .push(connection.read_timeout_ms);
+        }
+    }
+
+    fn node(client: Hung) -> Node {
+        let firmware = FwVersion {
+            management_processor: Some(Version::Ilo4),
+            ..Default::default()
+        };
+        let mut node = Node::new_with_fw_and_client(Default::default(), firmware, Box::new(client));
+        node.set_retry_policy(RetryPolicy {
+            max_attempts: 10,
+            initial_backoff_ms: 10,
+            multiplier: 1.0,
+            jitter: 0.0,
+            ..Default::default()
+        });
+        node
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_total_timeout() {
+        let client = Hung::default();
+        let sent = client.sent.clone();
+        let read_timeouts = client.read_timeouts.clone();
+        let mut node = node(client);
+        let command = node.get_fw_version_command().unwrap();
+        let timeouts = Timeouts {
+            read: Some(Duration::from_millis(5)),
+            request: Some(Duration::from_millis(50)),
+            total: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        let err = node
+            .send_command_with_timeouts(command, timeouts)
+            .await
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(matches!(
+            err,
+            commands::Error::Client {
+                source: Error::Timeout { .. },
+                ..
+            }
+        ));
+        let sent = sent.load(Ordering::SeqCst);
+        assert!((2..10).contains(&sent), "{} attempts", sent);
+        // the read timeout is only overridden for the call
+        let read_timeouts = read_timeouts.lock().unwrap();
+        assert_eq!(read_timeouts.len(), 2 * sent);
+        assert!(read_timeouts.chunks(2).all(|call| call == [5, 60_000]));
+    }
+
+    #[tokio::test]
+    async fn cancelled_requests_are_dropped() {
+        let client = Hung::default();
+        let (sent, dropped) = (client.sent.clone(), client.dropped.clone());
+        let mut node = node(client);
+        let command = node.get_fw_version_command().unwrap();
+        let cancelled = time::timeout(Duration::from_millis(20), node.send_command(command)).await;
+        assert!(cancelled.is_err());
+        assert_eq!(sent.load(Ordering::SeqCst), 1);
+        assert!(dropped.load(Ordering::SeqCst));
     }
 }
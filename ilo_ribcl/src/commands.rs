@@ -1,6 +1,9 @@
-use crate::{builder_parse, client, into_ribcl, write_ribcl, xml};
+use crate::{builder_parse, client, into_ribcl, redact, write_ribcl, xml};
+use ilo_console::ilo2::auth::Auth;
+use serde::Serialize;
 #[cfg(feature = "backtrace")]
 use std::backtrace::Backtrace;
+use std::fmt;
 use thiserror::Error;
 
 /// Errors occurring while making API calls.
@@ -78,14 +81,322 @@ pub enum Error {
         // name of the unset field
         target: &'static str,
     },
+    /// A certificate to import couldn't be parsed
+    #[error("invalid certificate: {reason}")]
+    InvalidCertificate { reason: String },
+    /// An SSH public key to import couldn't be parsed or isn't supported by the firmware
+    #[error("invalid ssh key: {reason}")]
+    InvalidSshKey { reason: String },
+    /// A firmware image doesn't look like one for the node's management processor
+    #[error("invalid firmware image: {reason}")]
+    InvalidFirmwareImage { reason: String },
+    /// The node didn't come back with the new firmware after an update
+    #[error("the new firmware wasn't reported within {timeout:?}, last seen {found:?}")]
+    FirmwareNotUpdated {
+        timeout: std::time::Duration,
+        found: Option<String>,
+    },
+    /// The iLO is still generating the certificate signing request
+    #[error("the certificate signing request isn't ready: {message}")]
+    CsrPending { message: String },
+    /// A batch handle doesn't refer to a command in the batch response
+    #[error("no response document for batch command {index}")]
+    UnknownHandle { index: usize },
+    /// A batch response doesn't have a document for the login and each command
+    #[error("expected response documents for the login and {commands} commands, got {documents}")]
+    ResponseCount { documents: usize, commands: usize },
     /// Error occurred processing xml
     #[error("{source}")]
     Xml {
-        #[from]
         source: xml::Error,
         #[cfg(feature = "backtrace")]
         backtrace: Backtrace,
     },
+
+    /// The Endpoint returned an error status for the command
+    #[error("{code:?}: 0x{status:04X} {message:?}")]
+    Ribcl {
+        /// classification of the status
+        code: ResponseCode,
+        /// the STATUS attribute of the response
+        status: u16,
+        /// the MESSAGE attribute of the response
+        message: String,
+    },
+}
+
+impl Error {
+    /// Wraps a builder error, lifting error statuses returned by the Endpoint
+    /// into [Error::Ribcl]
+    pub(crate) fn builder_parse(target: &'static str, source: builder_parse::Error) -> Self {
+        match source {
+            builder_parse::Error::XmlError(source) => source.into(),
+            source => Error::BuilderParse {
+                target,
+                source,
+                #[cfg(feature = "backtrace")]
+                backtrace: Backtrace::capture(),
+            },
+        }
+    }
+
+    /// Returns true if the command failed before it reached the Endpoint, so
+    /// sending it again can't repeat it
+    pub fn is_unsent(&self) -> bool {
+        match self {
+            Error::Client { source, .. } => source.is_unsent(),
+            _ => false,
+        }
+    }
+
+    /// The classified error status returned by the Endpoint, if any
+    pub fn response_code(&self) -> Option<ResponseCode> {
+        match self {
+            Error::Ribcl { code, .. } => Some(*code),
+            Error::Client {
+                source: client::Error::Command { source, .. },
+                ..
+            } => source.response_code(),
+            _ => None,
+        }
+    }
+}
+
+impl From<xml::Error> for Error {
+    fn from(source: xml::Error) -> Self {
+        match source {
+            xml::Error::Response { status, message } => Error::Ribcl {
+                code: ResponseCode::from_response(status, &message),
+                status,
+                message,
+            },
+            source => Error::Xml {
+                source,
+                #[cfg(feature = "backtrace")]
+                backtrace: Backtrace::capture(),
+            },
+        }
+    }
+}
+
+/// Classification of the error statuses returned in RIBCL responses.
+///
+/// iLO reports many different failures with the same status (commonly 0x0001),
+/// so a code is chosen from the status when it is unambiguous and otherwise
+/// from the message.
+#[non_exhaustive]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ResponseCode {
+    /// The username or password was rejected (0x005F)
+    LoginFailed,
+    /// The user doesn't have the privilege required for the command
+    InsufficientPrivilege,
+    /// The command requires a license that isn't installed
+    LicenseRequired,
+    /// A user named in the command doesn't exist (0x000A)
+    UserNotFound,
+    /// The command or setting isn't supported by this firmware or server (0x003C)
+    NotSupported,
+    /// A value in the command was rejected
+    InvalidParameter,
+    /// The Endpoint is busy, the command may succeed if sent again
+    Busy,
+    /// Any other error status
+    Other,
+}
+
+impl fmt::Display for ResponseCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ResponseCode::*;
+        f.write_str(match self {
+            LoginFailed => "login_failed",
+            InsufficientPrivilege => "insufficient_privilege",
+            LicenseRequired => "license_required",
+            UserNotFound => "user_not_found",
+            NotSupported => "not_supported",
+            InvalidParameter => "invalid_parameter",
+            Busy => "busy",
+            Other => "other",
+        })
+    }
+}
+
+impl ResponseCode {
+    /// Classify the STATUS and MESSAGE of a RIBCL response
+    pub fn from_response(status: u16, message: &str) -> Self {
+        use ResponseCode::*;
+        match status {
+            0x005f => return LoginFailed,
+            0x000a => return UserNotFound,
+            0x003c => return NotSupported,
+            _ => {}
+        }
+        let message = message.to_ascii_lowercase();
+        let contains = |patterns: &[&str]| patterns.iter().any(|p| message.contains(p));
+        if contains(&["login failed", "login name was not found"]) {
+            LoginFailed
+        } else if contains(&["privilege"]) {
+            InsufficientPrivilege
+        } else if contains(&["license"]) {
+            LicenseRequired
+        } else if contains(&["user not found", "user does not exist"]) {
+            UserNotFound
+        } else if contains(&[
+            "busy",
+            "try again",
+            "maximum number",
+            "too many",
+            "in progress",
+        ]) {
+            Busy
+        } else if contains(&["not supported", "unsupported", "unrecognized keyword"]) {
+            NotSupported
+        } else if contains(&["syntax error", "invalid", "parameter", "out of range"]) {
+            InvalidParameter
+        } else {
+            Other
+        }
+    }
+
+    /// Returns true if the command may succeed if sent again
+    pub fn is_transient(&self) -> bool {
+        *self == ResponseCode::Busy
+    }
+}
+
+/// The section, mode, name and body of a single RIBCL command
+#[derive(Clone)]
+pub(crate) struct Request {
+    pub section: &'static str,
+    pub mode: &'static str,
+    pub name: &'static str,
+    pub body: String,
+}
+
+// the body is logged without passwords and keys
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("section", &self.section)
+            .field("mode", &self.mode)
+            .field("name", &self.name)
+            .field("body", &redact::redact(&self.body))
+            .finish()
+    }
+}
+
+/// A RIBCL command and the parser for its response.
+///
+/// Commands are built by the `*_command` methods generated alongside each
+/// [client::Node] getter and setter, and are either sent on their own with
+/// [client::Node::send_command] or queued in a [crate::batch::Batch].
+pub struct Command<T> {
+    pub(crate) request: Request,
+    pub(crate) parse: fn(&str) -> Result<T, Error>,
+    idempotent: bool,
+}
+
+impl<T> Command<T> {
+    pub(crate) fn new(
+        section: &'static str,
+        mode: &'static str,
+        name: &'static str,
+        body: String,
+        parse: fn(&str) -> Result<T, Error>,
+    ) -> Self {
+        Self {
+            request: Request {
+                section,
+                mode,
+                name,
+                body,
+            },
+            parse,
+            idempotent: mode == "read",
+        }
+    }
+
+    /// Mark a write command as safe to send more than once, e.g. one that sets
+    /// a value rather than toggling it
+    pub fn mark_idempotent(mut self) -> Self {
+        self.idempotent = true;
+        self
+    }
+
+    /// Returns true if sending the command again after a response was lost
+    /// can't repeat its effect, read commands are idempotent
+    pub fn is_idempotent(&self) -> bool {
+        self.idempotent
+    }
+
+    /// The section (`rib_info`, `server_info`) the command is sent in
+    pub fn section(&self) -> &'static str {
+        self.request.section
+    }
+
+    /// The section mode, either `read` or `write`
+    pub fn mode(&self) -> &'static str {
+        self.request.mode
+    }
+
+    /// The name of the RIBCL command sent, e.g. `get_event_log` for both event logs
+    pub fn name(&self) -> &'static str {
+        self.request.name
+    }
+
+    /// Parses the response to this command
+    pub fn parse(&self, response: &str) -> Result<T, Error> {
+        (self.parse)(response)
+    }
+}
+
+impl<T> fmt::Debug for Command<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("section", &self.request.section)
+            .field("mode", &self.request.mode)
+            .field("name", &self.request.name)
+            .field("idempotent", &self.idempotent)
+            .field("body", &redact::redact(&self.request.body))
+            .finish()
+    }
+}
+
+/// Write a RIBCL document containing all the requests under a single login.
+///
+/// Consecutive requests for the same section and mode share one section element.
+pub(crate) fn write_document<'a, W, I>(
+    writer: &mut W,
+    credentials: &Auth,
+    requests: I,
+) -> Result<(), fmt::Error>
+where
+    W: fmt::Write,
+    I: IntoIterator<Item = &'a Request>,
+{
+    write!(
+        writer,
+        "<?xml version=\"1.0\"?>\
+            <ribcl version=\"2.0\">\
+                <login user_login=\"{}\" password=\"{}\">",
+        &credentials.username, &credentials.password,
+    )?;
+    let mut open: Option<(&str, &str)> = None;
+    for request in requests {
+        let current = (request.section, request.mode);
+        if open != Some(current) {
+            if let Some((section, _)) = open {
+                write!(writer, "</{}>", section)?;
+            }
+            write!(writer, "<{} mode=\"{}\">", request.section, request.mode)?;
+            open = Some(current);
+        }
+        write!(writer, "{}", request.body)?;
+    }
+    if let Some((section, _)) = open {
+        write!(writer, "</{}>", section)?;
+    }
+    write!(writer, "</login></ribcl>\r\n")
 }
 
 macro_rules! version_geq {
@@ -192,6 +503,34 @@ macro_rules! ribcl_command {
     };
 }
 
+macro_rules! ribcl_command_body {
+    (@inner $writer:ident, $command_name:literal) => {{
+        write!($writer, "<{}/>", $command_name)?;
+    }};
+    (@inner $writer:ident, $command_name:ident) => {{
+        write!($writer, "<{}/>", stringify!($command_name))?;
+    }};
+    (@inner $writer:ident, $command_name:ident, $attr_name:literal, $value:ident) => {{
+        use $crate::into_ribcl::IntoRibcl;
+        write!(
+            $writer,
+            "<{} {}=\"{}\"/>",
+            stringify!($command_name),
+            $attr_name,
+            $value.into_ribcl()?
+        )?;
+    }};
+    (@inner $writer:ident, $command_name:ident, $command:expr) => {{
+        write!($writer, "<{}>", stringify!($command_name))?;
+        $command;
+        write!($writer, "</{}>", stringify!($command_name))?;
+    }};
+    ($($all:tt)+) => {
+        use std::fmt::Write;
+        ribcl_command_body!(@inner $($all)+)
+    };
+}
+
 macro_rules! ribcl_parse_response {
     ($response:ident, $resp_tag_regex:literal -> $($ret_type:tt)+) => {
         ribcl_parse_response!(@final $response [ $resp_tag_regex ] [ $($ret_type)+ ])
@@ -247,14 +586,9 @@ macro_rules! ribcl_parse_response {
         use $crate::{builder_parse, builder_parse::BuilderParse, commands, xml};
         use std::convert::TryInto;
 
-        let mut doc_iter = $response.split(r#"<?xml version="1.0"?>"#);
-        doc_iter
+        // bound so the documents iterator is dropped before `$response`
+        let result = $crate::xml::documents(&$response)
             .find_map(|doc| {
-                tracing::event!(tracing::Level::TRACE, doc);
-                if doc.is_empty() {
-                    return None;
-                }
-
                 tracing::event!(tracing::Level::DEBUG, document=doc);
                 let (mut xml_cursor, root) = match $crate::xml::XmlCursor::new(doc) {
                     Ok(val) => val,
@@ -286,145 +620,323 @@ macro_rules! ribcl_parse_response {
                 target: ribcl_parse_response!(@type_name $($ret_type)*),
             })
             .map_err(|source|
-                commands::Error::BuilderParse {
-                    target: ribcl_parse_response!(@type_name $($ret_type)*),
-                    source,
-            })
+                commands::Error::builder_parse(ribcl_parse_response!(@type_name $($ret_type)*), source)
+            );
+        result
     }};
  }
 
+/// Calls `$callback!` with the name, arguments and return type of each
+/// [client::Node] command method taking owned arguments, used to generate
+/// [crate::blocking::Node] and [crate::shared::Node]
+macro_rules! node_methods {
+    ($callback:ident) => {
+        $callback! {
+            // ahs
+            get_ahs_status() -> crate::ahs::AhsStatusInfo;
+            set_ahs_status(status: crate::ahs::AhsStatusInfo) -> ();
+            ahs_clear_data() -> ();
+            // authentication
+            import_ssh_key(user_login: String, key: crate::authentication::SshPublicKey) -> ();
+            del_users_ssh_key(user_login: String) -> ();
+            get_twofactor_settings() -> crate::authentication::TwofactorSettings;
+            mod_twofactor_settings(settings: crate::authentication::TwofactorSettings) -> ();
+            // boot
+            reset_rib() -> ();
+            reset_server() -> ();
+            cold_boot_server() -> ();
+            warm_boot_server() -> ();
+            get_one_time_boot() -> crate::types::Device;
+            set_one_time_boot(device: crate::types::Device) -> ();
+            get_persistent_boot() -> crate::types::BootDevices;
+            set_persistent_boot(devices: Vec<crate::types::Device>) -> ();
+            // directory
+            get_dir_config() -> crate::directory::DirectorySettings;
+            mod_dir_config(settings: crate::directory::DirectorySettings) -> ();
+            import_dir_kerberos_keytab(keytab: Vec<u8>) -> ();
+            // firmware
+            get_fw_version() -> crate::types::FwVersion;
+            // general
+            factory_defaults() -> ();
+            get_host_data() -> Vec<crate::general::SmbiosRecord>;
+            get_global_settings() -> crate::general::GlobalSettings;
+            mod_global_settings(settings: crate::general::GlobalSettings) -> ();
+            get_server_name() -> crate::general::ServerName;
+            server_name(name: crate::general::ServerName) -> ();
+            get_uid_status() -> crate::types::UidMode;
+            uid_control(uid: crate::types::UidMode) -> ();
+            get_all_languages() -> Vec<crate::general::Language>;
+            get_language() -> crate::general::Language;
+            set_language(language: crate::general::Language) -> ();
+            // health
+            get_embedded_health() -> crate::health::EmbeddedHealthData;
+            // keyboard and mouse
+            get_hotkey_config() -> crate::keyboard_mouse::HotkeyConfig;
+            hotkey_config(config: crate::keyboard_mouse::HotkeyConfig) -> ();
+            // license
+            get_license() -> crate::license::ProLiantKey;
+            activate_license(license: String) -> ();
+            // logs
+            get_ilo_event_log() -> Vec<crate::logs::LogEvent>;
+            clear_ilo_event_log() -> ();
+            get_server_event_log() -> Vec<crate::logs::LogEvent>;
+            clear_server_event_log() -> ();
+            // network
+            get_network_settings() -> crate::network::NetworkSettings;
+            mod_network_settings(settings: crate::network::NetworkSettings) -> ();
+            // power
+            press_pwr_btn() -> ();
+            hold_pwr_btn() -> ();
+            get_host_power_status() -> crate::power::PowerStatus;
+            get_host_power_reg_info() -> crate::power::HostPowerRegInfo;
+            set_host_power(status: crate::power::PowerStatus) -> ();
+            get_server_auto_pwr() -> crate::power::PowerOnDelay;
+            server_auto_pwr(delay: crate::power::PowerOnDelay) -> ();
+            get_power_readings() -> crate::power::PowerReadings;
+            get_server_power_on_time() -> crate::types::Minutes;
+            clear_server_power_on_time() -> ();
+            get_host_power_saver_status() -> crate::power::HostPowerSaverMode;
+            set_host_power_saver(mode: crate::power::HostPowerSaverMode) -> ();
+            get_power_cap() -> crate::power::PowerCap;
+            set_power_cap(cap: crate::power::PowerCap) -> ();
+            get_host_pwr_micro_ver() -> crate::power::PowerMicroVersion;
+            get_pwreg() -> crate::power::Pwreg;
+            set_pwreg(pwreg: crate::power::Pwreg) -> ();
+            // rimp
+            get_rimp() -> crate::rimp::Rimp;
+            // security
+            cert_fqdn(value: bool) -> ();
+            get_cert_subject_info() -> crate::security::CsrCertSettings;
+            csr_cert_settings(settings: crate::security::CsrCertSettings) -> ();
+            certificate_signing_request() -> crate::security::CertificateSigningRequest;
+            import_certificate(content: crate::types::Certificate) -> ();
+            computer_lock_config(lock: crate::security::ComputerLock) -> ();
+            // snmp
+            get_snmp_im_settings() -> crate::snmp::SnmpImSettings;
+            mod_snmp_im_settings(settings: crate::snmp::SnmpImSettings) -> ();
+            // user
+            get_all_users() -> Vec<crate::authentication::Login>;
+            get_all_user_info() -> Vec<crate::user::User>;
+            get_user(user_login: String) -> crate::user::User;
+            add_user(user: crate::user::NewUser) -> ();
+            mod_user(user_login: String, changes: crate::user::UserChanges) -> ();
+            delete_user(user_login: String) -> ();
+            // virtual media
+            get_vm_status(device: crate::types::Device) -> crate::virtual_media::VmStatus;
+            set_vm_status(status: crate::virtual_media::VmStatus) -> ();
+            insert_virtual_media(device: crate::types::Device, image_url: crate::types::Url) -> ();
+            eject_virtual_media(device: crate::types::Device) -> ();
+        }
+    };
+}
+
+/// Emits the `<fn_name>_command` builder of a [client::Node] command method,
+/// `$body` writes the command to `$request` and may read the firmware through
+/// the optional `$node`
+macro_rules! command_builder {
+    (
+        $mod:ident.$fn_name:ident ( $($arg:ident : $arg_type:ty),* ) -> $ret_type:ty,
+        $mode:literal, $name:expr, [$($requirements:tt)*],
+        |$request:ident $(, $node:ident)?| $body:block,
+        $parse:expr
+    ) => {
+        paste::paste! {
+            /// Builds the request for the command of the same name without sending it,
+            /// for use with [crate::batch::Batch]
+            pub fn [<$fn_name _command>](
+                &self,
+                $($arg: $arg_type),*
+            ) -> Result<crate::commands::Command<$ret_type>, crate::commands::Error> {
+                assert_fw!(self.firmware(), $($requirements)*);
+                $(let $node = self;)?
+                let mut $request = String::new();
+                $body
+                Ok(crate::commands::Command::new(
+                    stringify!($mod),
+                    $mode,
+                    $name,
+                    $request,
+                    $parse,
+                ))
+            }
+        }
+    };
+}
+
 macro_rules! get_method{
     (
         @final
-        [$(#[$outer:meta])*]
-        $mod:ident.$fn_name:tt -> [$($resp_tag:tt)+] : [$($ret_type:tt)+] [$(, $requirements_msg:literal, $( ( $($conditions:tt),+ ) ),+)?]
-    ) => {
-        $(#[$outer])*
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(&mut self) -> Result<$($ret_type)+, crate::commands::Error> {
-            assert_fw!(self.firmware(), $($requirements_msg, $( (  $($conditions),+ ) ),*)*);
-            let mut request = String::new();
-            ribcl_command!(
-                request,
-                self.auth(),
-                $mod,
-                read,
-                $fn_name
-            );
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            Ok(
-                ribcl_parse_response!(@final response [$($resp_tag)+] [$($ret_type)+])?.map_err(
-                    |source| $crate::commands::Error::BuilderParse{
-                        target:stringify!($($ret_type)+),
-                        source
-                    }
-                )?
-            )
+        [$tag:tt]
+        {
+            [$(#[$outer:meta])*]
+            $mod:ident.$fn_name:ident ( $($arg:ident : $arg_type:ty)? )
+            $request:ident $body:block
         }
+        -> [$($resp_tag:tt)+] : [$($ret_type:tt)+] [$(, $requirements_msg:literal, $( ( $($conditions:tt),+ ) ),+)?]
+    ) => {
+        command_builder!(
+            $mod.$fn_name($($arg: $arg_type)?) -> $($ret_type)+,
+            "read",
+            ribcl_parse_response!(@tag_type [$tag]),
+            [$($requirements_msg, $( (  $($conditions),+ ) ),*)*],
+            |$request| $body,
+            |response| {
+                Ok(
+                    ribcl_parse_response!(@final response [$($resp_tag)+] [$($ret_type)+])?.map_err(
+                        |source| $crate::commands::Error::builder_parse(stringify!($($ret_type)+), source)
+                    )?
+                )
+            }
+        );
 
+        paste::paste! {
+            $(#[$outer])*
+            #[tracing::instrument(skip(self))]
+            pub async fn $fn_name(
+                &mut self,
+                $($arg: $arg_type)?
+            ) -> Result<$($ret_type)+, crate::commands::Error> {
+                let command = self.[<$fn_name _command>]($($arg)?)?;
+                self.send_command(command).await
+            }
+        }
     };
     (
         @parse_ret_type
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] : [$($resp_type_tokens:tt)+] {}
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] : [$($resp_type_tokens:tt)+] {}
     ) => {
         get_method!(
             @final
-            [$(#[$outer])*]
-            $mod.$fn_name -> [$($resp_tag_tokens)+] : [ $($resp_type_tokens)+ ] []
+            [$tag] {$($head)+}
+            -> [$($resp_tag_tokens)+] : [ $($resp_type_tokens)+ ] []
         );
     };
     (
         @parse_ret_type
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] : [$($resp_type_tokens:tt)+] {, $requirements:literal $($tail:tt)+ }
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] : [$($resp_type_tokens:tt)+] {, $requirements:literal $($tail:tt)+ }
     ) => {
         get_method!(
             @final
-            [$(#[$outer])*]
-            $mod.$fn_name -> [$($resp_tag_tokens)+] : [ $($resp_type_tokens)+ ] [, $requirements $($tail)+]
+            [$tag] {$($head)+}
+            -> [$($resp_tag_tokens)+] : [ $($resp_type_tokens)+ ] [, $requirements $($tail)+]
         );
     };
 
     // parsing response type
     (
         @parse_ret_type
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] : [$($type_tokens:tt)+] { $type_token:tt $($tail:tt)*}
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] : [$($type_tokens:tt)+] { $type_token:tt $($tail:tt)*}
     ) => {
         get_method!(
             @parse_ret_type
-            [$(#[$outer])*]
-            $mod.$fn_name -> [ $($resp_tag_tokens)+ ] : [ $($type_tokens)+ $type_token ] { $($tail)* }
+            [$tag] {$($head)+}
+            -> [ $($resp_tag_tokens)+ ] : [ $($type_tokens)+ $type_token ] { $($tail)* }
         );
     };
     // start parsing response type
     (
         @parse_ret_type
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] : [] { $type_token:tt $($tail:tt)* }
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] : [] { $type_token:tt $($tail:tt)* }
     ) => {
         get_method!(
             @parse_ret_type
-            [$(#[$outer])*]
-            $mod.$fn_name -> [$($resp_tag_tokens)+] : [$type_token] {$($tail)*}
+            [$tag] {$($head)+}
+            -> [$($resp_tag_tokens)+] : [$type_token] {$($tail)*}
         );
     };
 
     // : ends response tag tokens load
     (
         @parse_ret_tag
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] { : $($tail:tt)+}
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] { : $($tail:tt)+}
     ) => {
         get_method!(
             @parse_ret_type
-            [$(#[$outer])*]
-            $mod.$fn_name -> [$($resp_tag_tokens)+] : [] {$($tail)*}
+            [$tag] {$($head)+}
+            -> [$($resp_tag_tokens)+] : [] {$($tail)*}
         );
     };
 
-    // missing return tag use function name and not requirements assumes not
+    // missing return tag use the command tag and not requirements assumes not
     (
         @parse_ret_tag
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] {}
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] {}
     ) => {
         get_method!(
             @final
-            [$(#[$outer])*]
-            $mod.$fn_name -> [$fn_name] : [$($resp_tag_tokens)+] []
+            [$tag] {$($head)+}
+            -> [$tag] : [$($resp_tag_tokens)+] []
         );
     };
-    // missing return tag use function name
+    // missing return tag use the command tag
     (
         @parse_ret_tag
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)+] {, $requirements:literal, $($tail:tt)+}
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)+] {, $requirements:literal, $($tail:tt)+}
     ) => {
         get_method!(
             @final
-            [$(#[$outer])*]
-            $mod.$fn_name -> [$fn_name] : [$($resp_tag_tokens)+] [, $requirements, $($tail)+]
+            [$tag] {$($head)+}
+            -> [$tag] : [$($resp_tag_tokens)+] [, $requirements, $($tail)+]
         );
     };
     // loading return tag tokens
     (
         @parse_ret_tag
-        [$(#[$outer:meta])*]
-        $mod:tt.$fn_name:tt -> [$($resp_tag_tokens:tt)*] { $resp_tag_token:tt $($tail:tt)*}
+        [$tag:tt] {$($head:tt)+}
+        -> [$($resp_tag_tokens:tt)*] { $resp_tag_token:tt $($tail:tt)*}
     ) => {
         get_method!(
             @parse_ret_tag
-            [$(#[$outer])*] $mod.$fn_name -> [$($resp_tag_tokens)*$resp_tag_token]
+            [$tag] {$($head)+} -> [$($resp_tag_tokens)*$resp_tag_token]
             {$($tail)*}
         );
     };
+    // the command is sent as `$tag_name` rather than the method name
+    (
+        $(#[$outer:meta])+
+        $mod:tt.$fn_name:tt : $tag_name:literal -> $($tail:tt)+
+    ) => {
+        get_method!(
+            @parse_ret_tag
+            [$tag_name] {
+                [$(#[$outer])*] $mod.$fn_name ()
+                request { ribcl_command_body!(request, $tag_name); }
+            }
+            -> [] {$($tail)+}
+        );
+    };
+    (
+        $(#[$outer:meta])+
+        $mod:tt.$fn_name:tt ($attr_name:literal : $arg_type:ty) -> $($tail:tt)+
+    ) => {
+        get_method!(
+            @parse_ret_tag
+            [$fn_name] {
+                [$(#[$outer])*] $mod.$fn_name (arg: $arg_type)
+                request { ribcl_command_body!(request, $fn_name, $attr_name, arg); }
+            }
+            -> [] {$($tail)+}
+        );
+    };
     (
         $(#[$outer:meta])+
         $mod:tt.$fn_name:tt -> $($tail:tt)+
     ) => {
-        get_method!(@parse_ret_tag [$(#[$outer])*] $mod.$fn_name -> [] {$($tail)+});
+        get_method!(
+            @parse_ret_tag
+            [$fn_name] {
+                [$(#[$outer])*] $mod.$fn_name ()
+                request { ribcl_command_body!(request, $fn_name); }
+            }
+            -> [] {$($tail)+}
+        );
     };
 
 }
@@ -434,104 +946,165 @@ macro_rules! mod_method {
             Err(crate::commands::Error::BuilderParse{
                 source: crate::builder_parse::Error::NotFound{target: _}, ..
             }) => Ok(()),
-            Ok(Err(err)) => Err(crate::commands::Error::BuilderParse{source: err.into(), target: ""}),
+            Ok(Err(err)) => Err(crate::commands::Error::builder_parse("", err)),
             Err(err) => Err(err.into()),
             Ok(_) => unreachable!()
         }
     };
+    (
+        @final
+        [$(#[$outer:meta])+]
+        $mod:ident.$fn_name:ident ( $($arg:ident : $arg_type:ty),* ) [$tag:tt]
+        [$($requirements:tt)*]
+        |$request:ident $(, $node:ident)?| $body:block
+    ) => {
+        command_builder!(
+            $mod.$fn_name($($arg: $arg_type),*) -> (),
+            "write",
+            ribcl_parse_response!(@tag_type [$tag]),
+            [$($requirements)*],
+            |$request $(, $node)?| $body,
+            |response| mod_method!(@parse_response response)
+        );
+
+        paste::paste! {
+            $(#[$outer])+
+            // settings may hold passwords or communities, the request is
+            // logged with them redacted instead
+            #[tracing::instrument(skip(self $(, $arg)*))]
+            pub async fn $fn_name(
+                &mut self,
+                $($arg: $arg_type),*
+            ) -> Result<(), crate::commands::Error> {
+                let command = self.[<$fn_name _command>]($($arg),*)?;
+                self.send_command(command).await
+            }
+        }
+    };
+    // the body is written by `$body`, sent as `$tag_name` when given
+    (
+        $(#[$outer:meta])+
+        $mod:ident.$fn_name:ident ( $($arg:ident : $arg_type:ty),+ ) : $tag_name:literal
+        $(, $requirements_msg:literal, $( ( $($conditions:tt),* ) ),*)?
+        => |$request:ident $(, $node:ident)?| $body:block
+    ) => {
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name ($($arg: $arg_type),+) [$tag_name]
+            [$($requirements_msg, $( (  $($conditions),* ) ),*)*]
+            |$request $(, $node)?| $body
+        );
+    };
+    (
+        $(#[$outer:meta])+
+        $mod:ident.$fn_name:ident ( $($arg:ident : $arg_type:ty),+ )
+        $(, $requirements_msg:literal, $( ( $($conditions:tt),* ) ),*)?
+        => |$request:ident $(, $node:ident)?| $body:block
+    ) => {
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name ($($arg: $arg_type),+) [$fn_name]
+            [$($requirements_msg, $( (  $($conditions),* ) ),*)*]
+            |$request $(, $node)?| $body
+        );
+    };
     (
         $(#[$outer:meta])+
         $mod:ident.$fn_name:ident : $tag_name:literal
     ) => {
-        $(#[$outer])+
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(
-            &mut self,
-        ) -> Result<(), crate::commands::Error> {
-            let mut request = String::new();
-            ribcl_command!(request, self.auth(), $mod, write, $tag_name);
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            mod_method!(@parse_response response)
-        }
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name () [$tag_name] []
+            |request| { ribcl_command_body!(request, $tag_name); }
+        );
     };
 
     (
         $(#[$outer:meta])+
         $mod:ident.$fn_name:ident ( $arg_type:ty )
     ) => {
-        $(#[$outer])+
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(
-            &mut self,
-            arg: $arg_type,
-        ) -> Result<(), crate::commands::Error> {
-            let mut request = String::new();
-            ribcl_command!(request, self.auth(), $mod, write, $fn_name, {
-                use crate::write_ribcl::WriteRibcl;
-                arg.write_ribcl(&mut request)?;
-            });
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            mod_method!(@parse_response response)
-        }
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name (arg: $arg_type) [$fn_name] []
+            |request| {
+                ribcl_command_body!(request, $fn_name, {
+                    use crate::write_ribcl::WriteRibcl;
+                    arg.write_ribcl(&mut request)?;
+                });
+            }
+        );
     };
 
     (
         $(#[$outer:meta])+
         $mod:ident.$fn_name:ident
     ) => {
-        $(#[$outer])+
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(&mut self) -> Result<(), crate::commands::Error> {
-            let mut request = String::new();
-            ribcl_command!(request, self.auth(), $mod, write, $fn_name);
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            mod_method!(@parse_response response)
-        }
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name () [$fn_name] []
+            |request| { ribcl_command_body!(request, $fn_name); }
+        );
     };
 
     (
         $(#[$outer:meta])+
         $mod:ident.$fn_name:ident ( $arg_type:ty ), $requirements_msg:literal, $( ( $($conditions:tt),* ) ),*
     ) => {
-        $(#[$outer])+
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(&mut self, arg: $arg_type) -> Result<(), crate::commands::Error> {
-            assert_fw!(self.firmware(), $requirements_msg, $( (  $($conditions),* ) ),*);
-            let mut request = String::new();
-            ribcl_command!(request, self.auth(), $mod, write, $fn_name, {
-                use $crate::write_ribcl::WriteRibcl;
-                arg.write_ribcl(&mut request)?;
-            });
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            mod_method!(@parse_response response)
-        }
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name (arg: $arg_type) [$fn_name]
+            [$requirements_msg, $( (  $($conditions),* ) ),*]
+            |request| {
+                ribcl_command_body!(request, $fn_name, {
+                    use $crate::write_ribcl::WriteRibcl;
+                    arg.write_ribcl(&mut request)?;
+                });
+            }
+        );
     };
     (
         $(#[$outer:meta])+
         $mod:ident.$fn_name:ident ($attr_name:literal : $($arg_type:ty)+ ) $(, $requirements_msg:literal, $( ( $($conditions:tt),* ) ),*)?
     ) => {
-        $(#[$outer])+
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(&mut self, arg: $($arg_type)+) -> Result<(), crate::commands::Error> {
-            assert_fw!(self.firmware(), $($requirements_msg, $( (  $($conditions),+ ) ),*)*);
-            let mut request = String::new();
-            ribcl_command!(request, self.auth(), $mod, write, $fn_name, $attr_name, arg);
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            mod_method!(@parse_response response)
-        }
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name (arg: $($arg_type)+) [$fn_name]
+            [$($requirements_msg, $( (  $($conditions),+ ) ),*)*]
+            |request| { ribcl_command_body!(request, $fn_name, $attr_name, arg); }
+        );
     };
     (
         $(#[$outer:meta])+
         $mod:ident.$fn_name:ident, $requirements_msg:literal, $( ( $($conditions:tt),* ) ),*
     ) => {
-        $(#[$outer])+
-        #[tracing::instrument(skip(self))]
-        pub async fn $fn_name(&mut self) -> Result<(), crate::commands::Error> {
-            assert_fw!(self.firmware(), $requirements_msg, $( (  $($conditions),* ) ),*);
-            let mut request = String::new();
-            ribcl_command!(request, self.auth(), $mod, write, $fn_name);
-            let response = self.send_ribcl(request.into_bytes()).await?;
-            mod_method!(@parse_response response)
-        }
+        mod_method!(
+            @final [$(#[$outer])+] $mod.$fn_name () [$fn_name]
+            [$requirements_msg, $( (  $($conditions),* ) ),*]
+            |request| { ribcl_command_body!(request, $fn_name); }
+        );
     };
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    /// A response document as sent by the iLO, `body` follows the status
+    pub(crate) fn response(status: u16, message: &str, body: &str) -> String {
+        format!(
+            "<?xml version=\"1.0\"?>\r\n<RIBCL VERSION=\"2.23\">\r\n<RESPONSE\r\n    STATUS=\"0x{:04X}\"\r\n    MESSAGE='{}'\r\n     />\r\n{}</RIBCL>\r\n",
+            status, message, body
+        )
+    }
+
+    #[test]
+    fn response_errors_are_classified() {
+        let login: Error = xml::Error::Response {
+            status: 0x5f,
+            message: String::from("Login failed."),
+        }
+        .into();
+        let privilege: Error = xml::Error::Response {
+            status: 0x1,
+            message: String::from("User does NOT have correct privilege for action."),
+        }
+        .into();
+        assert_eq!(login.response_code(), Some(ResponseCode::LoginFailed));
+        assert_eq!(
+            privilege.response_code(),
+            Some(ResponseCode::InsufficientPrivilege)
+        );
+    }
+}
@@ -0,0 +1,239 @@
+use crate::{
+    client,
+    into_ribcl::IntoRibcl,
+    types::{BoolBuilder, Port, PortBuilder, StringBuilder},
+};
+use ilo_ribcl_derive::{BuilderParse, WriteRibcl};
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::convert::TryInto;
+
+/// LDAP or Active Directory authentication settings
+#[skip_serializing_none]
+#[derive(Debug, Default, WriteRibcl, PartialEq, Serialize, BuilderParse)]
+pub struct DirectorySettings {
+    pub dir_authentication_enabled: Option<bool>,
+    /// Allow local users to log in as well as directory users
+    pub dir_local_user_acct: Option<bool>,
+    pub dir_server_address: Option<String>,
+    pub dir_server_port: Option<Port>,
+    /// Distinguished name of the iLO object, used with the extended schema
+    pub dir_object_dn: Option<String>,
+    /// Only written, never returned by the iLO
+    pub dir_object_password: Option<String>,
+    /// Searched in order for the login name, iLO 2 supports the first three
+    pub dir_user_context_1: Option<String>,
+    pub dir_user_context_2: Option<String>,
+    pub dir_user_context_3: Option<String>,
+    // ilo3, ilo4
+    pub dir_user_context_4: Option<String>,
+    pub dir_user_context_5: Option<String>,
+    pub dir_user_context_6: Option<String>,
+    pub dir_user_context_7: Option<String>,
+    pub dir_user_context_8: Option<String>,
+    pub dir_user_context_9: Option<String>,
+    pub dir_user_context_10: Option<String>,
+    pub dir_user_context_11: Option<String>,
+    pub dir_user_context_12: Option<String>,
+    pub dir_user_context_13: Option<String>,
+    pub dir_user_context_14: Option<String>,
+    pub dir_user_context_15: Option<String>,
+    /// Schema-free directory groups when enabled, the HP extended schema otherwise
+    pub dir_enable_grp_acct: Option<bool>,
+    pub dir_kerberos_enabled: Option<bool>,
+    pub dir_kerberos_realm: Option<String>,
+    pub dir_kerberos_kdc_address: Option<String>,
+    pub dir_kerberos_kdc_port: Option<Port>,
+    // ilo4
+    pub dir_generic_ldap_enabled: Option<bool>,
+}
+
+impl DirectorySettings {
+    fn uses_kerberos(&self) -> bool {
+        self.dir_kerberos_enabled.is_some()
+            || self.dir_kerberos_realm.is_some()
+            || self.dir_kerberos_kdc_address.is_some()
+            || self.dir_kerberos_kdc_port.is_some()
+    }
+
+    /// iLO 2 only has the first three user contexts
+    fn uses_extra_user_contexts(&self) -> bool {
+        [
+            &self.dir_user_context_4,
+            &self.dir_user_context_5,
+            &self.dir_user_context_6,
+            &self.dir_user_context_7,
+            &self.dir_user_context_8,
+            &self.dir_user_context_9,
+            &self.dir_user_context_10,
+            &self.dir_user_context_11,
+            &self.dir_user_context_12,
+            &self.dir_user_context_13,
+            &self.dir_user_context_14,
+            &self.dir_user_context_15,
+        ]
+        .iter()
+        .any(|context| context.is_some())
+    }
+}
+
+impl client::Node {
+    get_method!(
+        /// Returns the directory authentication settings
+        dir_info.get_dir_config -> DirectorySettings
+    );
+
+    mod_method!(
+        /// Updates the directory authentication settings, the Kerberos settings
+        /// and user contexts 4 to 15 need iLO 3 or iLO 4 and generic LDAP iLO 4
+        dir_info.mod_dir_config(settings: DirectorySettings)
+        => |request, node| {
+            if settings.uses_kerberos() || settings.uses_extra_user_contexts() {
+                assert_fw!(node.firmware(), "iL0 3 or iL0 4", (Ilo3), (Ilo4));
+            }
+            if settings.dir_generic_ldap_enabled.is_some() {
+                assert_fw!(node.firmware(), "iL0 4", (Ilo4));
+            }
+            ribcl_command_body!(request, mod_dir_config, {
+                use crate::write_ribcl::WriteRibcl;
+                settings.write_ribcl(&mut request)?;
+            });
+        }
+    );
+
+    mod_method!(
+        /// Import the Kerberos keytab of the iLO's directory account
+        dir_info.import_dir_kerberos_keytab(keytab: Vec<u8>) : "mod_dir_config",
+        "iL0 3 or iL0 4",
+        (Ilo3),
+        (Ilo4)
+        => |request| {
+            ribcl_command_body!(request, mod_dir_config, {
+                let encoded = base64::encode(&keytab);
+                request.push_str("<dir_kerberos_keytab>\n-----BEGIN KEYTAB-----\n");
+                for start in (0..encoded.len()).step_by(64) {
+                    writeln!(
+                        request,
+                        "{}",
+                        &encoded[start..encoded.len().min(start + 64)]
+                    )?;
+                }
+                request.push_str("-----END KEYTAB-----\n</dir_kerberos_keytab>");
+            });
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::{self, tests::response},
+        types::{FwVersion, Version},
+    };
+
+    fn node(version: Version) -> client::Node {
+        let firmware = FwVersion {
+            management_processor: Some(version),
+            ..Default::default()
+        };
+        client::Node::new_with_fw(Default::default(), firmware).unwrap()
+    }
+
+    #[test]
+    fn mod_dir_config_writes_the_set_fields() {
+        let settings = DirectorySettings {
+            dir_authentication_enabled: Some(true),
+            dir_server_address: Some(String::from("ldap.example.com")),
+            dir_object_password: Some(String::from("hunter2")),
+            dir_user_context_1: Some(String::from("CN=Users,DC=example,DC=com")),
+            ..Default::default()
+        };
+        let command = node(Version::Ilo2)
+            .mod_dir_config_command(settings)
+            .unwrap();
+        let body = &command.request.body;
+        assert!(body.starts_with("<mod_dir_config>"), "{}", body);
+        assert!(
+            body.contains("<dir_authentication_enabled value=\"Y\"/>"),
+            "{}",
+            body
+        );
+        assert!(body.contains("<dir_server_address value=\"ldap.example.com\"/>"));
+        assert!(body.contains("<dir_user_context_1 value=\"CN=Users,DC=example,DC=com\"/>"));
+        assert!(!body.contains("dir_kerberos"));
+        assert!(!format!("{:?}", command).contains("hunter2"));
+    }
+
+    #[test]
+    fn ilo2_only_has_three_user_contexts_and_no_kerberos() {
+        let node = node(Version::Ilo2);
+        let not_supported = |settings| {
+            matches!(
+                node.mod_dir_config_command(settings),
+                Err(commands::Error::NotSupported { .. })
+            )
+        };
+        assert!(not_supported(DirectorySettings {
+            dir_user_context_4: Some(String::from("OU=Admins,DC=example,DC=com")),
+            ..Default::default()
+        }));
+        assert!(not_supported(DirectorySettings {
+            dir_kerberos_enabled: Some(false),
+            ..Default::default()
+        }));
+        assert!(matches!(
+            node.import_dir_kerberos_keytab_command(vec![0x05]),
+            Err(commands::Error::NotSupported { .. })
+        ));
+    }
+
+    #[test]
+    fn keytab_is_armored_and_sent_as_mod_dir_config() {
+        let command = node(Version::Ilo4)
+            .import_dir_kerberos_keytab_command(vec![0x05; 90])
+            .unwrap();
+        assert_eq!(command.name(), "mod_dir_config");
+        let lines: Vec<_> = command.request.body.lines().collect();
+        assert_eq!(lines[0], "<mod_dir_config><dir_kerberos_keytab>");
+        assert_eq!(lines[1], "-----BEGIN KEYTAB-----");
+        assert_eq!(lines[2].len(), 64);
+        assert_eq!(lines[4], "-----END KEYTAB-----");
+    }
+
+    #[test]
+    fn get_dir_config_is_parsed() {
+        let response = response(
+            0,
+            "No error",
+            "<GET_DIR_CONFIG>\r\n\
+             <DIR_AUTHENTICATION_ENABLED VALUE=\"Y\"/>\r\n\
+             <DIR_LOCAL_USER_ACCT VALUE=\"Y\"/>\r\n\
+             <DIR_SERVER_ADDRESS VALUE=\"ldap.example.com\"/>\r\n\
+             <DIR_SERVER_PORT VALUE=\"636\"/>\r\n\
+             <DIR_USER_CONTEXT_1 VALUE=\"CN=Users,DC=example,DC=com\"/>\r\n\
+             <DIR_ENABLE_GRP_ACCT VALUE=\"N\"/>\r\n\
+             <DIR_KERBEROS_ENABLED VALUE=\"N\"/>\r\n\
+             <DIR_KERBEROS_KDC_PORT VALUE=\"88\"/>\r\n\
+             </GET_DIR_CONFIG>\r\n",
+        );
+        let settings = node(Version::Ilo4)
+            .get_dir_config_command()
+            .unwrap()
+            .parse(&response)
+            .unwrap();
+        assert_eq!(settings.dir_authentication_enabled, Some(true));
+        assert_eq!(
+            settings.dir_server_address.as_deref(),
+            Some("ldap.example.com")
+        );
+        assert_eq!(
+            settings.dir_user_context_1.as_deref(),
+            Some("CN=Users,DC=example,DC=com")
+        );
+        assert_eq!(settings.dir_enable_grp_acct, Some(false));
+        assert_eq!(settings.dir_kerberos_enabled, Some(false));
+        assert!(settings.dir_server_port.is_some());
+        assert_eq!(settings.dir_object_password, None);
+    }
+}
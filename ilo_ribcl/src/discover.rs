@@ -0,0 +1,289 @@
+//! Find management processors on a network.
+//!
+//! Every address in the scanned ranges is asked for the unauthenticated
+//! `/xmldata?item=All` document, the nodes that answer with a `RIMP` document
+//! are returned with an endpoint that [Node::from_json](crate::client::Node::from_json)
+//! loads once credentials are added.
+use crate::{
+    client::{self, Client, ConnectionSettings, HttpsClient},
+    commands, firmware,
+    rimp::{self, Rimp},
+    types::FwVersion,
+    xml,
+};
+use futures::{stream, StreamExt};
+use ilo_console::ilo2::auth::Auth;
+use serde::Serialize;
+use std::{fmt, net::Ipv4Addr, str::FromStr, time::Duration};
+use thiserror::Error;
+use tokio::time;
+use tracing::{event, instrument, Level};
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid address range {range:?}: {reason}")]
+    InvalidRange { range: String, reason: &'static str },
+    #[error("{hostname} couldn't be queried: {source}")]
+    Client {
+        hostname: String,
+        source: client::Error,
+    },
+    #[error("{hostname} didn't answer within {timeout:?}")]
+    Timeout { hostname: String, timeout: Duration },
+    #[error("{hostname} doesn't appear to be an iLO: {source}")]
+    NotIlo {
+        hostname: String,
+        source: xml::Error,
+    },
+    #[error("{hostname} returned an xmldata document that couldn't be parsed: {source}")]
+    Parse {
+        hostname: String,
+        source: commands::Error,
+    },
+}
+
+/// An IPv4 network in CIDR notation, e.g. `10.0.0.0/24`, or a single address
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    network: Ipv4Addr,
+    prefix: u8,
+}
+
+impl Range {
+    /// The addresses to scan, leaving out the network and broadcast
+    /// addresses of networks larger than two addresses
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let network = u32::from(self.network);
+        let last = network | (u32::MAX.checked_shr(u32::from(self.prefix)).unwrap_or(0));
+        let (first, last) = if self.prefix < 31 {
+            (network + 1, last - 1)
+        } else {
+            (network, last)
+        };
+        (first..=last).map(Ipv4Addr::from)
+    }
+}
+
+impl FromStr for Range {
+    type Err = Error;
+
+    fn from_str(range: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| Error::InvalidRange {
+            range: range.to_string(),
+            reason,
+        };
+        let (address, prefix) = match range.find('/') {
+            Some(index) => (&range[..index], &range[index + 1..]),
+            None => (range, "32"),
+        };
+        let address: Ipv4Addr = address
+            .trim()
+            .parse()
+            .map_err(|_| invalid("not an IPv4 address"))?;
+        let prefix: u8 = prefix
+            .trim()
+            .parse()
+            .ok()
+            .filter(|prefix| *prefix <= 32)
+            .ok_or_else(|| invalid("prefix length must be between 0 and 32"))?;
+        let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(address) & mask),
+            prefix,
+        })
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// A management processor found by a scan
+#[derive(Debug, Serialize)]
+pub struct Discovered {
+    pub hostname: String,
+    pub firmware: Option<FwVersion>,
+    pub rimp: Rimp,
+    #[serde(skip)]
+    auth: Auth,
+}
+
+/// The contents of an endpoint file for a [Discovered] node, without credentials
+#[derive(Debug, Serialize)]
+pub struct Endpoint {
+    pub auth: Auth,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<FwVersion>,
+}
+
+impl Discovered {
+    /// An endpoint with the certificate fingerprint seen during the scan and,
+    /// when the management processor was identified, its firmware so it isn't
+    /// detected again
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint {
+            auth: self.auth.clone(),
+            firmware: self
+                .firmware
+                .clone()
+                .filter(|firmware| firmware.management_processor.is_some()),
+        }
+    }
+}
+
+/// Scans address ranges for management processors.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), ilo_ribcl::discover::Error> {
+/// use ilo_ribcl::discover::Discovery;
+///
+/// let found = Discovery::new(64).scan(&["10.0.0.0/24".parse()?]).await;
+/// for node in &found {
+///     println!("{}", serde_json::to_string_pretty(&node.endpoint()).unwrap());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Discovery {
+    concurrency: usize,
+    connection: ConnectionSettings,
+    template: Auth,
+}
+
+impl Discovery {
+    /// Query at most `concurrency` addresses at once, with timeouts short
+    /// enough that addresses without a node don't hold up the scan
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            concurrency: concurrency.max(1),
+            connection: ConnectionSettings {
+                keep_alive: false,
+                connect_timeout_ms: 2_000,
+                read_timeout_ms: 5_000,
+                request_timeout_ms: 10_000,
+                ..Default::default()
+            },
+            template: Auth::default(),
+        }
+    }
+
+    pub fn with_connection_settings(mut self, connection: ConnectionSettings) -> Self {
+        self.connection = connection;
+        self
+    }
+
+    /// Copy the ports, proxy and certificate policy of `template` to every
+    /// address queried and the endpoints found
+    pub fn with_auth_template(mut self, template: Auth) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Query every address in `ranges`, returning the nodes found in address order
+    #[instrument(skip(self))]
+    pub async fn scan(&self, ranges: &[Range]) -> Vec<Discovered> {
+        let hosts = ranges.iter().flat_map(Range::hosts);
+        let mut found: Vec<_> = stream::iter(hosts)
+            .map(|host| async move {
+                match self.probe(&host.to_string()).await {
+                    Ok(discovered) => Some((host, discovered)),
+                    Err(err) => {
+                        event!(Level::DEBUG, %host, %err, "not found");
+                        None
+                    }
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .filter_map(futures::future::ready)
+            .collect()
+            .await;
+        found.sort_by_key(|(host, _)| *host);
+        found.dedup_by_key(|(host, _)| *host);
+        found
+            .into_iter()
+            .map(|(_, discovered)| discovered)
+            .collect()
+    }
+
+    /// Query a single node
+    #[instrument(skip(self))]
+    pub async fn probe(&self, hostname: &str) -> Result<Discovered, Error> {
+        let mut auth = self.template.clone();
+        auth.hostname = hostname.to_string();
+        let mut https = HttpsClient::with_settings(auth, self.connection.clone());
+        let timeout = Duration::from_millis(self.connection.request_timeout_ms);
+        let response = time::timeout(timeout, https.get_xmldata("All"))
+            .await
+            .map_err(|_| Error::Timeout {
+                hostname: hostname.to_string(),
+                timeout,
+            })?
+            .map_err(|source| Error::Client {
+                hostname: hostname.to_string(),
+                source,
+            })?;
+        let firmware =
+            firmware::fw_version_from_xmldata(&response).map_err(|source| Error::NotIlo {
+                hostname: hostname.to_string(),
+                source,
+            })?;
+        let rimp = rimp::parse_rimp(&response).map_err(|source| Error::Parse {
+            hostname: hostname.to_string(),
+            source,
+        })?;
+        event!(Level::INFO, hostname, ?firmware, "found");
+        Ok(Discovered {
+            hostname: hostname.to_string(),
+            firmware,
+            rimp,
+            // the https client records the certificate fingerprint on first use
+            auth: https.auth,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simulator::Simulator, types::Version};
+    use ilo_console::tls::TlsPolicy;
+
+    #[test]
+    fn range_hosts() {
+        let range: Range = "10.0.0.17/28".parse().unwrap();
+        assert_eq!(range.to_string(), "10.0.0.16/28");
+        let hosts: Vec<_> = range.hosts().collect();
+        assert_eq!(hosts.len(), 14);
+        assert_eq!(hosts[0], Ipv4Addr::new(10, 0, 0, 17));
+        assert_eq!(hosts[13], Ipv4Addr::new(10, 0, 0, 30));
+
+        let single: Vec<_> = "192.168.1.5".parse::<Range>().unwrap().hosts().collect();
+        assert_eq!(single, vec![Ipv4Addr::new(192, 168, 1, 5)]);
+        assert_eq!("10.0.0.0/31".parse::<Range>().unwrap().hosts().count(), 2);
+
+        assert!("10.0.0.0/33".parse::<Range>().is_err());
+        assert!("ilo.example.com/24".parse::<Range>().is_err());
+    }
+
+    #[tokio::test]
+    async fn probe_identifies_the_simulator() {
+        let server = Simulator::new(Version::Ilo5).serve("127.0.0.1:0").unwrap();
+        let mut template = Auth::default();
+        template.https_port = Some(server.addr.port());
+        template.tls = TlsPolicy::Pinned {
+            sha256: Some(server.fingerprint.clone()),
+        };
+        let discovered = Discovery::new(1)
+            .with_auth_template(template)
+            .probe("127.0.0.1")
+            .await
+            .unwrap();
+        let firmware = discovered.endpoint().firmware.unwrap();
+        assert_eq!(firmware.management_processor, Some(Version::Ilo5));
+        assert_eq!(firmware.firmware_version.as_deref(), Some("2.72"));
+    }
+}
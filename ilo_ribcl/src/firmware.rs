@@ -1,5 +1,173 @@
-use crate::{client, commands, types};
-use std::{fs::File, io::Read, path::Path};
+use crate::{
+    client,
+    commands::{self, Command},
+    into_ribcl::IntoRibcl,
+    metrics::Label,
+    types, xml,
+};
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    fs, iter,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::time;
+use tracing::{event, Level};
+
+lazy_static! {
+    static ref ILO_PRODUCT_REGEX: regex::Regex = regex::Regex::new(r"iLO\s*(\d+)").unwrap();
+    /// Released images are named after the processor and version, e.g. `ilo4_255.bin`
+    static ref IMAGE_NAME_REGEX: regex::Regex =
+        regex::Regex::new(r"(?i)^ilo(\d)_(\d)(\d+)\.bin$").unwrap();
+}
+
+/// Images smaller than this are truncated or not firmware at all
+const MIN_IMAGE_LEN: usize = 64 * 1024;
+/// Larger than any image released for iLO 2, 3 or 4
+const MAX_IMAGE_LEN: usize = 64 * 1024 * 1024;
+/// How long the iLO may take to receive and flash the image before answering
+const FLASH_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How long the iLO may take to reset and answer with the new firmware
+const RESET_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+/// How long to wait between requests for the firmware version while the iLO resets
+const RESET_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The stages of a firmware update, reported as it progresses
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum UpdateProgress {
+    /// `sent` of `total` bytes of the upload have been sent
+    Uploading { sent: u64, total: u64 },
+    /// The image has been received and is being written to flash
+    Flashing,
+    /// The iLO is resetting to start the new firmware
+    Resetting,
+    /// The iLO is running the new firmware
+    Updated { firmware: types::FwVersion },
+}
+
+/// Check that `image` looks like a firmware image for `processor`
+fn validate_image(
+    file_name: &str,
+    image: &[u8],
+    processor: &types::Version,
+) -> Result<(), commands::Error> {
+    let invalid = |reason: String| Err(commands::Error::InvalidFirmwareImage { reason });
+    if image.len() < MIN_IMAGE_LEN || image.len() > MAX_IMAGE_LEN {
+        return invalid(format!(
+            "{} is {} bytes, firmware images are between {} and {} bytes",
+            file_name,
+            image.len(),
+            MIN_IMAGE_LEN,
+            MAX_IMAGE_LEN
+        ));
+    }
+    if image.starts_with(b"#!") || image.starts_with(b"\x1f\x8b") || image.starts_with(b"PK") {
+        return invalid(format!(
+            "{} is an archive or installer, extract the .bin image from it",
+            file_name
+        ));
+    }
+    let expected = match processor {
+        types::Version::Ilo2 => "2",
+        types::Version::Ilo3 => "3",
+        types::Version::Ilo4 => "4",
+        types::Version::Ilo5 => "5",
+    };
+    match IMAGE_NAME_REGEX.captures(file_name) {
+        Some(captures) if &captures[1] != expected => invalid(format!(
+            "{} is for iLO {}, the node is iLO {}",
+            file_name, &captures[1], expected
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The firmware version in the name of a released image, `ilo4_255.bin` is 2.55
+fn image_version(file_name: &str) -> Option<String> {
+    IMAGE_NAME_REGEX
+        .captures(file_name)
+        .map(|captures| format!("{}.{}", &captures[2], &captures[3]))
+}
+
+/// Read the management processor and firmware version from an unauthenticated
+/// `/xmldata?item=All` response.
+///
+/// Returns `Ok(None)` when the node answers but doesn't publish its details,
+/// and an error when the response isn't a `RIMP` document.
+pub(crate) fn fw_version_from_xmldata(
+    response: &str,
+) -> Result<Option<types::FwVersion>, xml::Error> {
+    let mut reader = xml::Reader::from_str(response);
+    reader.trim_text(true);
+    let mut buf = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+    let mut found_root = false;
+    let mut product = None;
+    let mut firmware_version = None;
+    loop {
+        let event = reader.read_event(&mut buf)?;
+        match &event {
+            xml::Event::Start(element) | xml::Event::Empty(element) if !found_root => {
+                if !element.name().eq_ignore_ascii_case(b"rimp") {
+                    return Err(xml::Error::ElementNotFound {
+                        name: String::from("RIMP"),
+                    });
+                }
+                found_root = true;
+                if matches!(event, xml::Event::Start(_)) {
+                    path.push(String::from("RIMP"));
+                }
+            }
+            xml::Event::Start(element) => {
+                path.push(String::from_utf8_lossy(element.name()).to_ascii_uppercase());
+            }
+            xml::Event::End(_) => {
+                path.pop();
+            }
+            xml::Event::Text(text) => {
+                let text = text.unescape_and_decode(&reader)?;
+                match path
+                    .iter()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .as_slice()
+                {
+                    ["RIMP", "MP", "PN"] => product = Some(text),
+                    ["RIMP", "MP", "FWRI"] => firmware_version = Some(text),
+                    _ => {}
+                }
+            }
+            xml::Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    if !found_root {
+        return Err(xml::Error::DocRootNotFound);
+    }
+    let product = match product {
+        Some(product) => product,
+        None => return Ok(None),
+    };
+    let management_processor =
+        ILO_PRODUCT_REGEX
+            .captures(&product)
+            .and_then(|captures| match &captures[1] {
+                "2" => Some(types::Version::Ilo2),
+                "3" => Some(types::Version::Ilo3),
+                "4" => Some(types::Version::Ilo4),
+                "5" => Some(types::Version::Ilo5),
+                _ => None,
+            });
+    Ok(Some(types::FwVersion {
+        firmware_version,
+        management_processor,
+        ..Default::default()
+    }))
+}
 
 impl client::Node {
     get_method!(
@@ -7,24 +175,223 @@ impl client::Node {
         rib_info.get_fw_version -> types::FwVersion
     );
 
-    /// Update the iLO firmware
-    #[tracing::instrument]
-    pub async fn update_rib_firmware(&mut self, path: &Path) -> Result<(), commands::Error> {
-        let mut f = File::open(path)?;
-        let mut buffer = Vec::new();
-        f.read_to_end(&mut buffer)?;
+    /// Update the iLO firmware with the image at `path`, returning the
+    /// firmware reported once the iLO has reset
+    pub async fn update_rib_firmware(
+        &mut self,
+        path: &Path,
+    ) -> Result<types::FwVersion, commands::Error> {
+        self.update_rib_firmware_with_progress(path, |_| {}).await
+    }
+
+    /// Update the iLO firmware with the image at `path`, calling `progress` as
+    /// the image is sent and flashed and the iLO resets.
+    ///
+    /// iLO 2 receives the image after the command on its raw TLS port, iLO 3
+    /// and iLO 4 receive it as an HTTPS upload before the command.
+    #[tracing::instrument(skip(self, progress))]
+    pub async fn update_rib_firmware_with_progress<F>(
+        &mut self,
+        path: &Path,
+        progress: F,
+    ) -> Result<types::FwVersion, commands::Error>
+    where
+        F: FnMut(UpdateProgress) + Send + 'static,
+    {
+        let image = fs::read(path)?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let previous = self.get_fw_version().await?;
+        let processor = previous.management_processor.clone().ok_or_else(|| {
+            commands::Error::InvalidFirmwareImage {
+                reason: String::from("the node's management processor is unknown"),
+            }
+        })?;
+        validate_image(&file_name, &image, &processor)?;
+
+        let progress = Arc::new(Mutex::new(progress));
+        let report = move |stage: UpdateProgress| (*progress.lock().unwrap())(stage);
+        let uploading = {
+            let report = report.clone();
+            Box::new(move |sent, total| {
+                report(UpdateProgress::Uploading { sent, total });
+                if sent == total {
+                    report(UpdateProgress::Flashing);
+                }
+            })
+        };
 
         let mut request = String::new();
-        ribcl_header!(request, self.auth(), rib_info, write, update_rib_firmware)?;
-        use std::fmt::Write;
         write!(
             request,
-            "<update_rib_firmware image_location=\"{}\" image_length=\"{}\"/>\r\n",
-            &path.to_string_lossy(),
-            &buffer.len()
+            "<update_rib_firmware image_location=\"{}\" image_length=\"{}\"/>",
+            file_name.clone().into_ribcl()?,
+            image.len()
         )?;
-        let mut request = request.into_bytes();
-        request.append(&mut buffer);
-        Ok(())
+        let command = Command::new(
+            "rib_info",
+            "write",
+            "update_rib_firmware",
+            request,
+            |response| mod_method!(@parse_response response),
+        );
+        let response = if processor == types::Version::Ilo2 {
+            let auth = self.auth();
+            let mut header = String::new();
+            ribcl_header!(header, auth, rib_info, write)?;
+            write!(header, "{}\r\n", command.request.body)?;
+            let mut footer = String::new();
+            ribcl_footer!(footer, rib_info)?;
+            self.send_ribcl_with_image(
+                header.into_bytes(),
+                image,
+                footer.into_bytes(),
+                FLASH_TIMEOUT,
+                Label::commands(iter::once(&command.request)),
+                uploading,
+            )
+            .await?
+        } else {
+            let cookie = self
+                .upload_ribcl_file(&file_name, image, FLASH_TIMEOUT, uploading)
+                .await?;
+            let mut request = String::new();
+            commands::write_document(&mut request, &self.auth(), iter::once(&command.request))?;
+            match cookie {
+                Some(cookie) => {
+                    let label = Label::commands(iter::once(&command.request));
+                    self.send_ribcl_with_cookie(request.into_bytes(), &cookie, FLASH_TIMEOUT, label)
+                        .await?
+                }
+                None => {
+                    let label = Label::commands(iter::once(&command.request));
+                    self.send_labelled(request.into_bytes(), FLASH_TIMEOUT, Some(label))
+                        .await?
+                }
+            }
+        };
+        command.parse(&response)?;
+
+        report(UpdateProgress::Resetting);
+        let firmware = self
+            .wait_for_firmware(&previous, image_version(&file_name).as_deref())
+            .await?;
+        self.set_firmware(firmware.clone());
+        report(UpdateProgress::Updated {
+            firmware: firmware.clone(),
+        });
+        Ok(firmware)
+    }
+
+    /// Ask for the firmware version until the iLO answers after resetting,
+    /// with the `expected` version when the image's version is known
+    async fn wait_for_firmware(
+        &mut self,
+        previous: &types::FwVersion,
+        expected: Option<&str>,
+    ) -> Result<types::FwVersion, commands::Error> {
+        let start = Instant::now();
+        let mut reset_seen = false;
+        let mut found = previous.firmware_version.clone();
+        loop {
+            time::delay_for(RESET_POLL_INTERVAL).await;
+            match self.get_fw_version().await {
+                Ok(firmware) => {
+                    let version = firmware.firmware_version.as_deref();
+                    let previous = previous.firmware_version.as_deref();
+                    if is_updated(previous, expected, version, reset_seen) {
+                        return Ok(firmware);
+                    }
+                    event!(
+                        Level::DEBUG,
+                        ?version,
+                        "still running the previous firmware"
+                    );
+                    found = firmware.firmware_version;
+                }
+                Err(err) => {
+                    event!(Level::DEBUG, %err, "waiting for the reset");
+                    reset_seen = true;
+                }
+            }
+            if start.elapsed() >= RESET_TIMEOUT {
+                return Err(commands::Error::FirmwareNotUpdated {
+                    timeout: RESET_TIMEOUT,
+                    found,
+                });
+            }
+        }
+    }
+}
+
+/// Whether the iLO is running the new firmware, it must either report a
+/// different version or have stopped answering while it reset, so a poll made
+/// before the reset isn't mistaken for the update when the version is unchanged
+fn is_updated(
+    previous: Option<&str>,
+    expected: Option<&str>,
+    version: Option<&str>,
+    reset_seen: bool,
+) -> bool {
+    let changed = version != previous;
+    match expected {
+        Some(expected) => version == Some(expected) && (changed || reset_seen),
+        None => changed || reset_seen,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xmldata_identifies_management_processor() {
+        let firmware = fw_version_from_xmldata(
+            "<?xml version=\"1.0\"?><RIMP><HSI><SPN>ProLiant DL360 G7</SPN></HSI><MP><ST>1</ST><PN>Integrated Lights-Out 3 (iLO 3)</PN><FWRI>1.88</FWRI></MP></RIMP>",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(firmware.management_processor, Some(types::Version::Ilo3));
+        assert_eq!(firmware.firmware_version.as_deref(), Some("1.88"));
+        let firmware = fw_version_from_xmldata(
+            "<RIMP><MP><PN>Integrated Lights-Out 5 (iLO 5)</PN><FWRI>2.72</FWRI></MP></RIMP>",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(firmware.management_processor, Some(types::Version::Ilo5));
+        assert!(fw_version_from_xmldata("<RIMP/>").unwrap().is_none());
+        assert!(fw_version_from_xmldata("<html><body>Not Found</body></html>").is_err());
+    }
+
+    #[test]
+    fn image_checked_against_processor() {
+        let image = vec![0u8; MIN_IMAGE_LEN];
+        assert!(validate_image("ilo4_255.bin", &image, &types::Version::Ilo4).is_ok());
+        assert!(validate_image("custom.bin", &image, &types::Version::Ilo2).is_ok());
+        assert!(validate_image("ilo3_188.bin", &image, &types::Version::Ilo4).is_err());
+        assert!(validate_image("ilo4_255.bin", &image[1..], &types::Version::Ilo4).is_err());
+
+        let mut installer = image.clone();
+        installer[..2].copy_from_slice(b"#!");
+        assert!(validate_image("CP027911.scexe", &installer, &types::Version::Ilo4).is_err());
+
+        assert_eq!(image_version("ilo4_255.bin").as_deref(), Some("2.55"));
+        assert_eq!(image_version("ILO2_233.BIN").as_deref(), Some("2.33"));
+        assert_eq!(image_version("custom.bin"), None);
+    }
+
+    #[test]
+    fn update_needs_a_reset_or_a_new_version() {
+        assert!(is_updated(Some("2.50"), Some("2.55"), Some("2.55"), false));
+        assert!(!is_updated(Some("2.50"), Some("2.55"), Some("2.50"), true));
+        // reflashing the running version
+        assert!(!is_updated(Some("2.55"), Some("2.55"), Some("2.55"), false));
+        assert!(is_updated(Some("2.55"), Some("2.55"), Some("2.55"), true));
+        // the image version is unknown
+        assert!(!is_updated(Some("2.50"), None, Some("2.50"), false));
+        assert!(is_updated(Some("2.50"), None, Some("2.50"), true));
+        assert!(is_updated(Some("2.50"), None, Some("2.55"), false));
     }
 }
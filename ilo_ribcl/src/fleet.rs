@@ -0,0 +1,465 @@
+use crate::client::{self, Node};
+use futures::{future::LocalBoxFuture, stream, StreamExt};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+use std::{
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::{event, instrument, Level};
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("couldn't read or write endpoint file {path:?}: {source}")]
+    Io { path: PathBuf, source: io::Error },
+    #[error("couldn't load endpoint {path:?}: {source}")]
+    Client {
+        path: PathBuf,
+        source: client::Error,
+    },
+    #[error("couldn't serialize endpoint {path:?}: {source}")]
+    SerdeJson {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+}
+
+struct Member {
+    endpoint: Option<PathBuf>,
+    node: Node,
+}
+
+/// Many nodes that commands are run across concurrently.
+///
+/// ```no_run
+/// # async fn run() {
+/// use ilo_ribcl::fleet::Fleet;
+///
+/// let (mut fleet, loaded) = Fleet::load(&["a.json", "b.json"], 16).await;
+/// let report = fleet.run(|node| Box::pin(node.get_fw_version())).await;
+/// println!("{}", loaded.to_table());
+/// println!("{}", report.to_table());
+/// # }
+/// ```
+pub struct Fleet {
+    members: Vec<Member>,
+    concurrency: usize,
+}
+
+/// The result of running a command on one node
+#[derive(Debug)]
+pub struct Outcome<T, E> {
+    pub node: String,
+    pub elapsed: Duration,
+    pub result: Result<T, E>,
+}
+
+/// The results of running a command across a [Fleet], in the order the nodes were added
+#[derive(Debug)]
+pub struct Report<T, E> {
+    pub outcomes: Vec<Outcome<T, E>>,
+    pub elapsed: Duration,
+}
+
+impl Fleet {
+    /// Create an empty fleet running at most `concurrency` commands at once
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            members: vec![],
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Load a node from each endpoint file, nodes that fail to load are left
+    /// out of the fleet and reported.
+    #[instrument(skip(paths))]
+    pub async fn load<P: AsRef<Path>>(
+        paths: &[P],
+        concurrency: usize,
+    ) -> (Self, Report<(), Error>) {
+        let mut fleet = Self::new(concurrency);
+        let start = Instant::now();
+        let mut loaded: Vec<_> = stream::iter(paths.iter().enumerate())
+            .map(|(index, path)| {
+                let path = path.as_ref().to_path_buf();
+                async move {
+                    let start = Instant::now();
+                    let result = Self::load_node(&path).await;
+                    (index, path, start.elapsed(), result)
+                }
+            })
+            .buffer_unordered(fleet.concurrency)
+            .collect()
+            .await;
+        loaded.sort_by_key(|(index, ..)| *index);
+
+        let mut outcomes = vec![];
+        for (_, path, elapsed, result) in loaded {
+            let result = match result {
+                Ok(node) => {
+                    fleet.members.push(Member {
+                        endpoint: Some(path.clone()),
+                        node,
+                    });
+                    Ok(())
+                }
+                Err(err) => Err(err),
+            };
+            outcomes.push(Outcome {
+                node: path.display().to_string(),
+                elapsed,
+                result,
+            });
+        }
+        let report = Report {
+            outcomes,
+            elapsed: start.elapsed(),
+        };
+        (fleet, report)
+    }
+
+    async fn load_node(path: &Path) -> Result<Node, Error> {
+        let json = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Node::from_json(&json)
+            .await
+            .map_err(|source| Error::Client {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+
+    /// Write a node to a temporary file next to `path` and rename it over the
+    /// endpoint file, so a failed or interrupted save leaves the previous file
+    fn save_node(path: &Path, node: &Node) -> Result<(), Error> {
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| Error::Io { path, source }
+        };
+        let result = fs::File::create(&temp)
+            .map_err(io_error(&temp))
+            .and_then(|mut file| {
+                serde_json::to_writer_pretty(&mut file, node).map_err(|source| {
+                    Error::SerdeJson {
+                        path: path.to_path_buf(),
+                        source,
+                    }
+                })?;
+                file.sync_all().map_err(io_error(&temp))
+            })
+            .and_then(|_| fs::rename(&temp, path).map_err(io_error(path)));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    /// Write each node loaded from an endpoint file back to it, saving
+    /// detected firmware and certificate fingerprints
+    pub fn save(&self) -> Report<(), Error> {
+        let start = Instant::now();
+        let outcomes = self
+            .members
+            .iter()
+            .filter_map(|member| {
+                let path = member.endpoint.as_ref()?;
+                let start = Instant::now();
+                let result = Self::save_node(path, &member.node);
+                Some(Outcome {
+                    node: path.display().to_string(),
+                    elapsed: start.elapsed(),
+                    result,
+                })
+            })
+            .collect();
+        Report {
+            outcomes,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    /// Add a node to the fleet
+    pub fn push(&mut self, node: Node) {
+        self.members.push(Member {
+            endpoint: None,
+            node,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.members.iter().map(|member| &member.node)
+    }
+
+    pub fn nodes_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.members.iter_mut().map(|member| &mut member.node)
+    }
+
+    /// Run a command on every node, at most `concurrency` at a time.
+    ///
+    /// The command is given each node in turn and returns a boxed future, e.g.
+    /// `|node| Box::pin(node.get_host_power_status())`.
+    #[instrument(skip(self, command))]
+    pub async fn run<'a, T, E, F>(&'a mut self, mut command: F) -> Report<T, E>
+    where
+        F: FnMut(&'a mut Node) -> LocalBoxFuture<'a, Result<T, E>>,
+        E: fmt::Display,
+    {
+        let start = Instant::now();
+        let mut outcomes: Vec<_> = stream::iter(self.members.iter_mut().enumerate())
+            .map(|(index, Member { node, .. })| {
+                let hostname = node.auth().hostname;
+                let future = command(node);
+                async move {
+                    let start = Instant::now();
+                    let result = future.await;
+                    let elapsed = start.elapsed();
+                    if let Err(err) = &result {
+                        event!(Level::WARN, node = %hostname, %err, "command failed");
+                    }
+                    (
+                        index,
+                        Outcome {
+                            node: hostname,
+                            elapsed,
+                            result,
+                        },
+                    )
+                }
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        outcomes.sort_by_key(|(index, _)| *index);
+        Report {
+            outcomes: outcomes.into_iter().map(|(_, outcome)| outcome).collect(),
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+impl<T, E> Report<T, E> {
+    pub fn succeeded(&self) -> impl Iterator<Item = &Outcome<T, E>> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.result.is_ok())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &Outcome<T, E>> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.result.is_err())
+    }
+
+    /// Returns true if the command succeeded on every node
+    pub fn is_success(&self) -> bool {
+        self.failed().next().is_none()
+    }
+}
+
+impl<T: fmt::Debug, E: fmt::Display> Report<T, E> {
+    /// Format the report as a plain text table, one row per node
+    pub fn to_table(&self) -> String {
+        let width = self
+            .outcomes
+            .iter()
+            .map(|outcome| outcome.node.len())
+            .max()
+            .unwrap_or(0)
+            .max("NODE".len());
+        let mut table = String::new();
+        let _ = writeln!(
+            table,
+            "{:width$}  {:6}  {:>8}  RESULT",
+            "NODE",
+            "STATUS",
+            "TIME(ms)",
+            width = width
+        );
+        for outcome in &self.outcomes {
+            let (status, result) = match &outcome.result {
+                Ok(value) => ("ok", format!("{:?}", value)),
+                Err(err) => ("failed", err.to_string()),
+            };
+            let _ = writeln!(
+                table,
+                "{:width$}  {:6}  {:>8}  {}",
+                outcome.node,
+                status,
+                outcome.elapsed.as_millis(),
+                result,
+                width = width
+            );
+        }
+        let _ = write!(
+            table,
+            "{} succeeded, {} failed in {}ms",
+            self.succeeded().count(),
+            self.failed().count(),
+            self.elapsed.as_millis()
+        );
+        table
+    }
+}
+
+impl<T: Serialize, E: fmt::Display> Serialize for Outcome<T, E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Outcome", 4)?;
+        state.serialize_field("node", &self.node)?;
+        state.serialize_field("elapsed_ms", &(self.elapsed.as_millis() as u64))?;
+        match &self.result {
+            Ok(value) => {
+                state.serialize_field("ok", &true)?;
+                state.serialize_field("result", value)?;
+            }
+            Err(err) => {
+                state.serialize_field("ok", &false)?;
+                state.serialize_field("error", &err.to_string())?;
+            }
+        }
+        state.end()
+    }
+}
+
+impl<T: Serialize, E: fmt::Display> Serialize for Report<T, E> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Report", 4)?;
+        state.serialize_field("elapsed_ms", &(self.elapsed.as_millis() as u64))?;
+        state.serialize_field("succeeded", &self.succeeded().count())?;
+        state.serialize_field("failed", &self.failed().count())?;
+        state.serialize_field("nodes", &self.outcomes)?;
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{FwVersion, Version};
+    use ilo_console::{ilo2::auth::Auth, tls::TlsPolicy};
+
+    /// An empty directory for the test's endpoint files
+    fn directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("ilo_ribcl-fleet-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn node(hostname: &str) -> Node {
+        let mut auth = Auth::default();
+        auth.hostname = hostname.to_string();
+        auth.tls = TlsPolicy::Pinned {
+            sha256: Some(String::from("ab:cd")),
+        };
+        let firmware = FwVersion {
+            management_processor: Some(Version::Ilo4),
+            ..Default::default()
+        };
+        Node::new_with_fw(auth, firmware).unwrap()
+    }
+
+    fn write_endpoint(directory: &Path, hostname: &str) -> PathBuf {
+        let path = directory.join(format!("{}.json", hostname));
+        fs::write(&path, serde_json::to_string(&node(hostname)).unwrap()).unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn endpoints_are_loaded_in_order_and_saved_in_place() {
+        let directory = directory("save");
+        let paths = [
+            write_endpoint(&directory, "ilo-a"),
+            directory.join("missing.json"),
+            write_endpoint(&directory, "ilo-b"),
+        ];
+        let (fleet, loaded) = Fleet::load(&paths, 2).await;
+        assert_eq!(fleet.len(), 2);
+        let failed: Vec<_> = loaded.failed().map(|outcome| &outcome.node).collect();
+        assert_eq!(failed, [&paths[1].display().to_string()]);
+
+        let saved = fleet.save();
+        assert!(saved.is_success());
+        assert_eq!(saved.outcomes.len(), 2);
+        let (reloaded, _) = Fleet::load(&[&paths[0], &paths[2]], 1).await;
+        let hostnames: Vec<_> = reloaded.nodes().map(|node| node.auth().hostname).collect();
+        assert_eq!(hostnames, ["ilo-a", "ilo-b"]);
+        assert!(!directory.join("ilo-a.json.tmp").exists());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_saves_leave_the_endpoint_file() {
+        let directory = directory("failed");
+        let path = write_endpoint(&directory, "ilo-a");
+        let previous = fs::read_to_string(&path).unwrap();
+        let (fleet, _) = Fleet::load(&[&path], 1).await;
+        // the temporary file can't be created
+        fs::create_dir(directory.join("ilo-a.json.tmp")).unwrap();
+        let saved = fleet.save();
+        assert!(matches!(
+            saved.outcomes[0].result,
+            Err(Error::Io { ref path, .. }) if path.ends_with("ilo-a.json.tmp")
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), previous);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn commands_run_on_every_node_and_are_reported_in_order() {
+        let mut fleet = Fleet::new(2);
+        for hostname in &["ilo-a", "ilo-b", "ilo-c"] {
+            fleet.push(node(hostname));
+        }
+        let report = fleet
+            .run(|node| {
+                Box::pin(async move {
+                    let hostname = node.auth().hostname;
+                    if hostname == "ilo-b" {
+                        Err(format!("{} is busy", hostname))
+                    } else {
+                        Ok(hostname.len())
+                    }
+                })
+            })
+            .await;
+        let nodes: Vec<_> = report
+            .outcomes
+            .iter()
+            .map(|outcome| &outcome.node)
+            .collect();
+        assert_eq!(nodes, ["ilo-a", "ilo-b", "ilo-c"]);
+        assert!(!report.is_success());
+        assert_eq!(report.succeeded().count(), 2);
+        let table = report.to_table();
+        assert!(table.contains("ilo-b  failed") && table.contains("ilo-b is busy"));
+        assert!(table.ends_with(&format!(
+            "2 succeeded, 1 failed in {}ms",
+            report.elapsed.as_millis()
+        )));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            (json["succeeded"].as_u64(), json["failed"].as_u64()),
+            (Some(2), Some(1))
+        );
+        assert_eq!(json["nodes"][1]["error"], "ilo-b is busy");
+        assert_eq!(json["nodes"][2]["result"], 5);
+    }
+}
@@ -18,7 +18,7 @@ pub trait IntoRibcl {
 
 // handle vectors by running through each element
 impl<T: WriteRibcl + std::fmt::Debug> IntoRibcl for Vec<T> {
-    #[instrument]
+    #[instrument(skip(self))]
     fn into_ribcl(&self) -> Result<String, Error> {
         let mut result = String::new();
         for t in self.iter() {
@@ -14,11 +14,23 @@ pub mod xml;
 pub mod types;
 #[macro_use]
 pub mod commands;
+pub mod batch;
+pub mod blocking;
+pub mod cassette;
 pub mod cli_helpers;
+pub mod discover;
+pub mod fleet;
+pub mod metrics;
+pub mod redact;
+pub mod retry;
+pub mod shared;
+pub mod simulator;
 
 pub mod ahs;
 pub mod authentication;
 pub mod boot;
+pub mod certificate;
+pub mod directory;
 pub mod firmware;
 pub mod general;
 pub mod health;
@@ -27,6 +39,8 @@ pub mod license;
 pub mod logs;
 pub mod network;
 pub mod power;
+pub mod rimp;
 pub mod security;
 pub mod snmp;
+pub mod user;
 pub mod virtual_media;
@@ -33,23 +33,16 @@ impl client::Node {
     pub async fn get_license(&mut self) -> Result<ProLiantKey, commands::Error> {
         let response = self.get_xmldata("CpqKey").await?;
         let (mut xml_cursor, root) = XmlCursor::new(&response)?;
-        let builder: ProLiantKeyBuilder =
-            xml_cursor.builder_parse(root, None).map_err(|source| {
-                commands::Error::BuilderParse {
-                    target: "ProLiantKeyBuilder",
-                    source,
-                }
-            })?;
+        let builder: ProLiantKeyBuilder = xml_cursor
+            .builder_parse(root, None)
+            .map_err(|source| commands::Error::builder_parse("ProLiantKeyBuilder", source))?;
         Ok(builder
             .try_into()
-            .map_err(|source| commands::Error::BuilderParse {
-                target: "ProLiantKeyBuilder",
-                source,
-            })?)
+            .map_err(|source| commands::Error::builder_parse("ProLiantKeyBuilder", source))?)
     }
 
     /// Activate an iLO advanced license
-    #[tracing::instrument]
+    #[tracing::instrument(skip(license))]
     pub async fn activate_license(&mut self, license: String) -> Result<(), commands::Error> {
         let mut request = String::new();
         ribcl_command!(request, self.auth(), rib_info, write, license, {
@@ -58,11 +51,12 @@ impl client::Node {
         });
         let response = self.send_ribcl(request.into_bytes()).await?;
         match ribcl_parse_response!(response) {
-            Ok(_)
+            Ok(Ok(_))
             | Err(crate::commands::Error::BuilderParse {
                 source: crate::builder_parse::Error::NotFound { target: _ },
                 ..
             }) => Ok(()),
+            Ok(Err(err)) => Err(crate::commands::Error::builder_parse("", err)),
             Err(err) => Err(err),
         }
     }
@@ -0,0 +1,525 @@
+//! Latency, size and error metrics for each request sent to a node.
+//!
+//! A [client::Node] with a [Recorder] set reports a [RequestMetric] for every
+//! RIBCL request, firmware upload and xmldata query it sends, including each
+//! retry.  The built in [Registry] aggregates them and exports the totals in
+//! the Prometheus text format or as json.
+//!
+//! ```no_run
+//! # async fn run(node: &mut ilo_ribcl::client::Node) -> Result<(), ilo_ribcl::commands::Error> {
+//! use ilo_ribcl::metrics::Registry;
+//! use std::sync::Arc;
+//!
+//! let registry = Arc::new(Registry::default());
+//! node.set_metrics_recorder(registry.clone());
+//! node.get_fw_version().await?;
+//! println!("{}", registry.to_prometheus());
+//! # Ok(())
+//! # }
+//! ```
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    sync::Mutex,
+    time::Duration,
+};
+
+use crate::{
+    client,
+    commands::{Request, ResponseCode},
+    types::FwVersion,
+};
+
+/// Upper bounds of the latency histogram buckets in seconds
+pub const LATENCY_BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Receives a metric for every request sent by a node
+pub trait Recorder: fmt::Debug + Send + Sync {
+    fn record(&self, metric: &RequestMetric);
+}
+
+/// How a request ended
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum Outcome {
+    /// The node accepted every command in the request
+    Ok,
+    /// The node returned a RIBCL error status
+    Ribcl { code: ResponseCode, status: u16 },
+    /// The request couldn't be sent or no response was received
+    Transport { kind: &'static str },
+}
+
+impl Outcome {
+    /// A short label for the outcome, `ok`, the response code or the transport error
+    pub fn label(&self) -> String {
+        match self {
+            Outcome::Ok => String::from("ok"),
+            Outcome::Ribcl { code, .. } => code.to_string(),
+            Outcome::Transport { kind } => kind.to_string(),
+        }
+    }
+}
+
+/// A single request sent to a node
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RequestMetric {
+    pub hostname: String,
+    /// The command name, the names of all its commands joined with `,` for a
+    /// batch, or the item for xmldata
+    pub command: String,
+    /// The RIBCL section, the distinct sections joined with `,` for a batch, or `xmldata`
+    pub section: String,
+    /// The management processor, e.g. `ilo4`
+    pub processor: Option<String>,
+    pub firmware_version: Option<String>,
+    pub latency: Duration,
+    pub request_bytes: usize,
+    pub response_bytes: usize,
+    pub outcome: Outcome,
+}
+
+lazy_static! {
+    static ref COMMAND: Regex =
+        Regex::new(r#"(?is)<login\b[^>]*>\s*<([a-z_]+)\b[^>]*>\s*<([a-z0-9_]+)"#).unwrap();
+    static ref STATUS: Regex = Regex::new(
+        r#"(?is)<response\b[^>]*?\bstatus\s*=\s*"0x([0-9a-f]+)"[^>]*?\bmessage\s*=\s*['"]([^'"]*)['"]"#
+    )
+    .unwrap();
+}
+
+/// The section and command names a RIBCL request is recorded under
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Label {
+    section: String,
+    command: String,
+}
+
+impl Label {
+    /// Label a request built from commands with their sections and names
+    pub(crate) fn commands<'a, I>(requests: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Request>,
+    {
+        let mut sections: Vec<&str> = vec![];
+        let mut commands: Vec<&str> = vec![];
+        for request in requests {
+            if !sections.contains(&request.section) {
+                sections.push(request.section);
+            }
+            commands.push(request.name);
+        }
+        Self {
+            section: sections.join(","),
+            command: commands.join(","),
+        }
+    }
+
+    /// Label a raw request passed to [client::Node::send_ribcl], which has no
+    /// command names, by the first command it contains
+    pub(crate) fn scrape(request: &[u8]) -> Self {
+        let request = String::from_utf8_lossy(request);
+        let (section, command) = match COMMAND.captures(&request) {
+            Some(captures) => (
+                captures[1].to_ascii_lowercase(),
+                captures[2].to_ascii_lowercase(),
+            ),
+            None => (String::from("unknown"), String::from("unknown")),
+        };
+        Self { section, command }
+    }
+}
+
+impl RequestMetric {
+    pub(crate) fn ribcl(
+        hostname: &str,
+        firmware: Option<&FwVersion>,
+        label: Label,
+        request_bytes: usize,
+        latency: Duration,
+        result: &Result<String, client::Error>,
+    ) -> Self {
+        Self::new(
+            hostname,
+            firmware,
+            label.section,
+            label.command,
+            request_bytes,
+            latency,
+            result,
+        )
+    }
+
+    pub(crate) fn xmldata(
+        hostname: &str,
+        firmware: Option<&FwVersion>,
+        item: &str,
+        latency: Duration,
+        result: &Result<String, client::Error>,
+    ) -> Self {
+        Self::new(
+            hostname,
+            firmware,
+            String::from("xmldata"),
+            item.to_ascii_lowercase(),
+            0,
+            latency,
+            result,
+        )
+    }
+
+    /// A file uploaded to `/cgi-bin/uploadRibclFiles` before the command using it
+    pub(crate) fn upload(
+        hostname: &str,
+        firmware: Option<&FwVersion>,
+        request_bytes: usize,
+        latency: Duration,
+        result: &Result<String, client::Error>,
+    ) -> Self {
+        Self::new(
+            hostname,
+            firmware,
+            String::from("upload"),
+            String::from("upload_ribcl_files"),
+            request_bytes,
+            latency,
+            result,
+        )
+    }
+
+    fn new(
+        hostname: &str,
+        firmware: Option<&FwVersion>,
+        section: String,
+        command: String,
+        request_bytes: usize,
+        latency: Duration,
+        result: &Result<String, client::Error>,
+    ) -> Self {
+        let (response_bytes, outcome) = match result {
+            Ok(response) => (response.len(), ribcl_outcome(response)),
+            Err(err) => (0, transport_outcome(err)),
+        };
+        Self {
+            hostname: hostname.to_string(),
+            command,
+            section,
+            processor: firmware
+                .and_then(|firmware| firmware.management_processor.as_ref())
+                .map(|processor| format!("{:?}", processor).to_ascii_lowercase()),
+            firmware_version: firmware.and_then(|firmware| firmware.firmware_version.clone()),
+            latency,
+            request_bytes,
+            response_bytes,
+            outcome,
+        }
+    }
+}
+
+/// The first error status in a response
+fn ribcl_outcome(response: &str) -> Outcome {
+    STATUS
+        .captures_iter(response)
+        .filter_map(|captures| {
+            let status = u16::from_str_radix(&captures[1], 16).ok()?;
+            Some((status, captures[2].to_string()))
+        })
+        .find(|(status, _)| *status != 0)
+        .map(|(status, message)| Outcome::Ribcl {
+            code: ResponseCode::from_response(status, &message),
+            status,
+        })
+        .unwrap_or(Outcome::Ok)
+}
+
+fn transport_outcome(err: &client::Error) -> Outcome {
+    use client::Error::*;
+    let kind = match err {
+        Timeout { .. } => "timeout",
+        TlsHandshake { .. } | Tls { .. } | TlsPolicy { .. } => "tls",
+        Https { .. } | HttpStatus { .. } | HttpsConnection => "http",
+        ConnectionClosed => "connection_closed",
+        TlsWrite { .. } => "io",
+        Proxy { .. } => "proxy",
+        _ => "other",
+    };
+    Outcome::Transport { kind }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct Key {
+    hostname: String,
+    command: String,
+    section: String,
+    processor: Option<String>,
+    firmware_version: Option<String>,
+}
+
+/// Totals for one command on one node
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CommandStats {
+    pub requests: u64,
+    /// Requests by [Outcome::label]
+    pub outcomes: BTreeMap<String, u64>,
+    pub latency_seconds_sum: f64,
+    pub latency_seconds_max: f64,
+    /// Requests that took at most each of [LATENCY_BUCKETS]
+    pub latency_buckets: Vec<u64>,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+/// A [Recorder] that keeps totals for each command on each node
+#[derive(Debug, Default)]
+pub struct Registry {
+    stats: Mutex<BTreeMap<Key, CommandStats>>,
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    #[serde(flatten)]
+    key: &'a Key,
+    #[serde(flatten)]
+    stats: &'a CommandStats,
+}
+
+impl Recorder for Registry {
+    fn record(&self, metric: &RequestMetric) {
+        let key = Key {
+            hostname: metric.hostname.clone(),
+            command: metric.command.clone(),
+            section: metric.section.clone(),
+            processor: metric.processor.clone(),
+            firmware_version: metric.firmware_version.clone(),
+        };
+        let latency = metric.latency.as_secs_f64();
+        let mut stats = self.stats.lock().unwrap_or_else(|err| err.into_inner());
+        let stats = stats.entry(key).or_insert_with(|| CommandStats {
+            latency_buckets: vec![0; LATENCY_BUCKETS.len()],
+            ..Default::default()
+        });
+        stats.requests += 1;
+        *stats.outcomes.entry(metric.outcome.label()).or_default() += 1;
+        stats.latency_seconds_sum += latency;
+        stats.latency_seconds_max = stats.latency_seconds_max.max(latency);
+        for (bucket, bound) in stats.latency_buckets.iter_mut().zip(&LATENCY_BUCKETS) {
+            if latency <= *bound {
+                *bucket += 1;
+            }
+        }
+        stats.request_bytes += metric.request_bytes as u64;
+        stats.response_bytes += metric.response_bytes as u64;
+    }
+}
+
+/// Escape a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Key {
+    fn labels(&self) -> String {
+        format!(
+            r#"host="{}",command="{}",section="{}",processor="{}",firmware="{}""#,
+            escape(&self.hostname),
+            escape(&self.command),
+            escape(&self.section),
+            escape(self.processor.as_deref().unwrap_or_default()),
+            escape(self.firmware_version.as_deref().unwrap_or_default()),
+        )
+    }
+}
+
+impl Registry {
+    /// Forget all recorded totals
+    pub fn reset(&self) {
+        self.stats
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clear();
+    }
+
+    /// The totals in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let stats = self.stats.lock().unwrap_or_else(|err| err.into_inner());
+        let mut out = String::new();
+        // writing to a String can't fail
+        let _ = write_prometheus(&mut out, &stats);
+        out
+    }
+
+    /// The totals as a json array with one entry for each command on each node
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        let stats = self.stats.lock().unwrap_or_else(|err| err.into_inner());
+        let entries: Vec<Entry> = stats
+            .iter()
+            .map(|(key, stats)| Entry { key, stats })
+            .collect();
+        serde_json::to_string_pretty(&entries)
+    }
+}
+
+fn write_prometheus(out: &mut String, stats: &BTreeMap<Key, CommandStats>) -> fmt::Result {
+    writeln!(
+        out,
+        "# HELP ilo_ribcl_requests_total Requests sent to nodes by outcome"
+    )?;
+    writeln!(out, "# TYPE ilo_ribcl_requests_total counter")?;
+    for (key, stats) in stats {
+        for (outcome, count) in &stats.outcomes {
+            writeln!(
+                out,
+                r#"ilo_ribcl_requests_total{{{},outcome="{}"}} {}"#,
+                key.labels(),
+                escape(outcome),
+                count
+            )?;
+        }
+    }
+
+    writeln!(
+        out,
+        "# HELP ilo_ribcl_request_duration_seconds Time taken to receive a response"
+    )?;
+    writeln!(out, "# TYPE ilo_ribcl_request_duration_seconds histogram")?;
+    for (key, stats) in stats {
+        let labels = key.labels();
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&stats.latency_buckets) {
+            writeln!(
+                out,
+                r#"ilo_ribcl_request_duration_seconds_bucket{{{},le="{}"}} {}"#,
+                labels, bound, count
+            )?;
+        }
+        writeln!(
+            out,
+            r#"ilo_ribcl_request_duration_seconds_bucket{{{},le="+Inf"}} {}"#,
+            labels, stats.requests
+        )?;
+        writeln!(
+            out,
+            "ilo_ribcl_request_duration_seconds_sum{{{}}} {}",
+            labels, stats.latency_seconds_sum
+        )?;
+        writeln!(
+            out,
+            "ilo_ribcl_request_duration_seconds_count{{{}}} {}",
+            labels, stats.requests
+        )?;
+    }
+
+    writeln!(
+        out,
+        "# HELP ilo_ribcl_request_bytes_total Bytes sent to nodes"
+    )?;
+    writeln!(out, "# TYPE ilo_ribcl_request_bytes_total counter")?;
+    for (key, stats) in stats {
+        writeln!(
+            out,
+            "ilo_ribcl_request_bytes_total{{{}}} {}",
+            key.labels(),
+            stats.request_bytes
+        )?;
+    }
+    writeln!(
+        out,
+        "# HELP ilo_ribcl_response_bytes_total Bytes received from nodes"
+    )?;
+    writeln!(out, "# TYPE ilo_ribcl_response_bytes_total counter")?;
+    for (key, stats) in stats {
+        writeln!(
+            out,
+            "ilo_ribcl_response_bytes_total{{{}}} {}",
+            key.labels(),
+            stats.response_bytes
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commands::tests::response, types::Version};
+
+    #[test]
+    fn registry_exports_outcomes_and_latency() {
+        let firmware = FwVersion {
+            firmware_version: Some(String::from("2.55")),
+            management_processor: Some(Version::Ilo4),
+            ..Default::default()
+        };
+        let label = Label::commands(&[Request {
+            section: "rib_info",
+            mode: "read",
+            name: "get_fw_version",
+            body: String::from("<get_fw_version/>"),
+        }]);
+        let registry = Registry::default();
+        registry.record(&RequestMetric::ribcl(
+            "ilo1",
+            Some(&firmware),
+            label.clone(),
+            120,
+            Duration::from_millis(80),
+            &Ok(response(0, "No error", "")),
+        ));
+        registry.record(&RequestMetric::ribcl(
+            "ilo1",
+            Some(&firmware),
+            label,
+            120,
+            Duration::from_millis(300),
+            &Ok(response(0x005f, "Login failed.", "")),
+        ));
+        let prometheus = registry.to_prometheus();
+        let labels = r#"host="ilo1",command="get_fw_version",section="rib_info",processor="ilo4",firmware="2.55""#;
+        assert!(prometheus.contains(&format!(
+            r#"ilo_ribcl_requests_total{{{},outcome="ok"}} 1"#,
+            labels
+        )));
+        assert!(prometheus.contains(&format!(
+            r#"ilo_ribcl_requests_total{{{},outcome="login_failed"}} 1"#,
+            labels
+        )));
+        assert!(prometheus.contains(&format!(
+            r#"ilo_ribcl_request_duration_seconds_bucket{{{},le="0.1"}} 1"#,
+            labels
+        )));
+        assert!(registry
+            .to_json()
+            .unwrap()
+            .contains(r#""command": "get_fw_version""#));
+    }
+
+    #[test]
+    fn batches_are_labelled_with_all_their_commands() {
+        let request = |section, name| Request {
+            section,
+            mode: "read",
+            name,
+            body: String::new(),
+        };
+        let label = Label::commands(&[
+            request("rib_info", "get_fw_version"),
+            request("server_info", "get_host_power_status"),
+            request("rib_info", "get_network_settings"),
+        ]);
+        assert_eq!(label.section, "rib_info,server_info");
+        assert_eq!(
+            label.command,
+            "get_fw_version,get_host_power_status,get_network_settings"
+        );
+
+        let raw = br#"<ribcl version="2.0"><login user_login="admin" password="x"><server_info mode="read"><get_host_data/></server_info></login></ribcl>"#;
+        let label = Label::scrape(raw);
+        assert_eq!(label.section, "server_info");
+        assert_eq!(label.command, "get_host_data");
+    }
+}
@@ -0,0 +1,120 @@
+//! Removes secrets from RIBCL requests and responses before they are logged
+//! or written to disk.
+//!
+//! Passwords, license keys, SNMP communities and the bodies of certificates
+//! and keys are replaced with [REDACTED], the rest of the document is left
+//! unchanged so it can still be read when debugging.
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// Replaces secrets in redacted documents
+pub const REDACTED: &str = "********";
+
+lazy_static! {
+    /// `password="..."`, `key="..."` and similar attributes
+    static ref ATTRIBUTE: Regex =
+        Regex::new(r#"(?i)(\b[a-z0-9_]*(?:password|community|key)\s*=\s*)"[^"]*""#).unwrap();
+    /// `<PASSWORD VALUE="..."/>`, `<SNMP_ADDRESS_1_ROCOMMUNITY VALUE="..."/>` and similar tags
+    static ref VALUE_TAG: Regex = Regex::new(
+        r#"(?i)(<[a-z0-9_]*(?:password|community|key)[a-z0-9_]*\b[^>]*?\bvalue\s*=\s*)"[^"]*""#
+    )
+    .unwrap();
+    /// `<KEY>...</KEY>`, `<DIR_KERBEROS_KEYTAB>...</DIR_KERBEROS_KEYTAB>` and similar elements
+    static ref TEXT_TAG: Regex =
+        Regex::new(r#"(?i)(<[a-z0-9_]*(?:password|community|key|keytab)>)[^<]*(</)"#).unwrap();
+    /// PEM certificates, requests and keys
+    static ref PEM: Regex =
+        Regex::new(r#"(?s)(-----BEGIN [A-Z0-9 ]+-----).*?(-----END [A-Z0-9 ]+-----)"#).unwrap();
+    /// OpenSSH public keys
+    static ref SSH_KEY: Regex =
+        Regex::new(r#"\b(ssh-(?:rsa|dss|ed25519)|ecdsa-sha2-[a-z0-9-]+)\s+[A-Za-z0-9+/]+=*"#)
+            .unwrap();
+}
+
+/// Replace the secrets in a RIBCL or xmldata document
+pub fn redact(document: &str) -> String {
+    let document = ATTRIBUTE.replace_all(document, format!(r#"${{1}}"{}""#, REDACTED).as_str());
+    let document = VALUE_TAG.replace_all(&document, format!(r#"${{1}}"{}""#, REDACTED).as_str());
+    let document = TEXT_TAG.replace_all(&document, format!("${{1}}{}${{2}}", REDACTED).as_str());
+    let document = PEM.replace_all(&document, format!("${{1}}\n{}\n${{2}}", REDACTED).as_str());
+    SSH_KEY
+        .replace_all(&document, format!("${{1}} {}", REDACTED).as_str())
+        .into_owned()
+}
+
+/// Redact a request sent as bytes, as it's passed to [crate::client::Client::send_ribcl]
+pub fn redact_bytes(document: &[u8]) -> String {
+    redact(&String::from_utf8_lossy(document))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{general::GlobalSettings, simulator::Simulator, types::Version};
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    #[test]
+    fn secrets_are_replaced() {
+        let request = r#"<ribcl version="2.0"><login user_login="admin" password="secret"><rib_info mode="write"><license><activate key="AAAAA-BBBBB"/></license><mod_snmp_im_settings><snmp_address_1_trapcommunity version="v2c" value="private"/></mod_snmp_im_settings><mod_global_settings><capture_auto_export_password value="hunter2"/></mod_global_settings><import_certificate>
+-----BEGIN CERTIFICATE-----
+MIIBszCCAVmgAwIBAgIU
+-----END CERTIFICATE-----
+</import_certificate></rib_info><dir_info mode="write"><mod_dir_config><dir_kerberos_keytab>BQIAAABHAAIA</dir_kerberos_keytab></mod_dir_config></dir_info></login></ribcl>"#;
+        let redacted = redact(request);
+        for secret in &["secret", "AAAAA", "private", "hunter2", "MIIB", "BQIA"] {
+            assert!(!redacted.contains(secret), "{} in {}", secret, redacted);
+        }
+        assert!(redacted.contains(r#"user_login="admin""#));
+        assert!(redacted.contains(r#"version="v2c""#));
+        assert!(redacted.contains("-----BEGIN CERTIFICATE-----"));
+        assert_eq!(
+            redact("<PROLIANTKEY><KEY>XXXXX-XXXXX</KEY><LNAME>iLO Advanced</LNAME></PROLIANTKEY>"),
+            "<PROLIANTKEY><KEY>********</KEY><LNAME>iLO Advanced</LNAME></PROLIANTKEY>"
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn command_passwords_are_not_logged() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_writer(move || writer.clone())
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let simulator = Simulator::new(Version::Ilo4);
+        let mut node = simulator.node();
+        let settings = GlobalSettings {
+            capture_auto_export_password: Some(String::from("hunter2")),
+            ..Default::default()
+        };
+        let command = node.mod_global_settings_command(settings).unwrap();
+        assert!(!format!("{:?}", command).contains("hunter2"));
+        let _ = node
+            .send_command_with_timeout(command, Duration::from_secs(5))
+            .await;
+
+        let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(logged.contains("send_command_with_timeout"));
+        assert!(!logged.contains("hunter2"));
+    }
+}
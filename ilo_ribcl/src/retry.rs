@@ -0,0 +1,212 @@
+use crate::{builder_parse, client, commands, xml};
+use ilo_console::{proxy, tls};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{io, time::Duration};
+
+/// Controls how commands are retried when the node reports a transient error
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// The maximum number of times a command is sent, 1 disables retries
+    pub max_attempts: u32,
+    /// Delay before the first retry
+    pub initial_backoff_ms: u64,
+    /// Upper bound on the delay between retries
+    pub max_backoff_ms: u64,
+    /// Factor the delay is multiplied by after each retry
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomized, between 0.0 and 1.0
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 10_000,
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that never retries
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns true if another attempt should be made after `attempt` failed with `error`
+    pub fn should_retry<E: Transient>(&self, attempt: u32, error: &E) -> bool {
+        attempt < self.max_attempts && error.is_transient()
+    }
+
+    /// Returns the delay before retrying after the given attempt (starting at 1)
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1) as i32;
+        let delay = (self.initial_backoff_ms as f64 * self.multiplier.powi(exponent))
+            .min(self.max_backoff_ms as f64);
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let delay = if jitter > 0.0 {
+            delay * (1.0 - jitter * rand::thread_rng().gen::<f64>())
+        } else {
+            delay
+        };
+        Duration::from_millis(delay as u64)
+    }
+}
+
+/// Errors that may succeed if the command is sent again
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for io::Error {
+    fn is_transient(&self) -> bool {
+        use io::ErrorKind::*;
+        matches!(
+            self.kind(),
+            ConnectionRefused
+                | ConnectionReset
+                | ConnectionAborted
+                | BrokenPipe
+                | TimedOut
+                | UnexpectedEof
+                | Interrupted
+        )
+    }
+}
+
+impl Transient for xml::Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            xml::Error::Response { status, message } => {
+                commands::ResponseCode::from_response(*status, message).is_transient()
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Transient for builder_parse::Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            builder_parse::Error::XmlError(source) => source.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl Transient for tls::Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            tls::Error::Io(source) => source.is_transient(),
+            tls::Error::Handshake(_) => true,
+            tls::Error::Proxy(proxy::Error::Io(source)) => source.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl Transient for client::Error {
+    fn is_transient(&self) -> bool {
+        use client::Error::*;
+        match self {
+            TlsHandshake { .. } | ConnectionClosed | HttpsConnection | Timeout { .. } => true,
+            TlsWrite { source, .. } | RequestWrite { source } => source.is_transient(),
+            TlsPolicy { source, .. } => source.is_transient(),
+            Proxy {
+                source: proxy::Error::Io(source),
+                ..
+            } => source.is_transient(),
+            // the web server is busy
+            HttpStatus { status } => *status == 503,
+            Command { source, .. } | AutodetectFailed { source } => source.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+impl Transient for commands::Error {
+    fn is_transient(&self) -> bool {
+        use commands::Error::*;
+        match self {
+            Client { source, .. } => source.is_transient(),
+            Xml { source, .. } => source.is_transient(),
+            BuilderParse { source, .. } => source.is_transient(),
+            Ribcl { code, .. } => code.is_transient(),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_and_is_capped() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(10), Duration::from_millis(10_000));
+    }
+
+    #[test]
+    fn busy_response_is_transient() {
+        let busy = xml::Error::Response {
+            status: 0x1,
+            message: String::from("Server is busy. Please try again."),
+        };
+        let login = xml::Error::Response {
+            status: 0x5f,
+            message: String::from("Login failed."),
+        };
+        let privilege = xml::Error::Response {
+            status: 0x1,
+            message: String::from("User does NOT have correct privilege for action."),
+        };
+        assert!(busy.is_transient());
+        assert!(!login.is_transient());
+        assert!(!privilege.is_transient());
+    }
+
+    #[test]
+    fn writes_are_retried_only_when_unsent() {
+        let parse = |_: &str| Ok(());
+        let read =
+            commands::Command::new("rib_info", "read", "get_fw_version", String::new(), parse);
+        let write = commands::Command::new(
+            "rib_info",
+            "write",
+            "clear_ilo_event_log",
+            String::new(),
+            parse,
+        );
+        assert!(read.is_idempotent());
+        assert!(!write.is_idempotent());
+        assert!(write.mark_idempotent().is_idempotent());
+
+        let refused = client::Error::RequestWrite {
+            source: io::ErrorKind::ConnectionReset.into(),
+        };
+        let lost = client::Error::Timeout {
+            operation: "receive the response",
+            timeout: Duration::from_secs(1),
+        };
+        assert!(refused.is_unsent() && refused.is_transient());
+        assert!(!lost.is_unsent() && lost.is_transient());
+    }
+}
@@ -0,0 +1,267 @@
+//! The `RIMP` document returned by `/xmldata?item=All`
+//!
+//! It's served without authentication and identifies the server, its
+//! management processor and, for blades, the enclosure it's installed in.
+use crate::{
+    builder_parse::{BuilderParse as TraitBuilderParse, VecBuilder},
+    client, commands,
+    types::{StringBuilder, U32Builder},
+    xml::XmlCursor,
+};
+use ilo_ribcl_derive::BuilderParse;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::convert::TryInto;
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct Rimp {
+    #[ribcl(map = "hsi")]
+    pub server: Option<Server>,
+    #[ribcl(map = "mp")]
+    pub management_processor: Option<ManagementProcessor>,
+    pub health: Option<Health>,
+    #[ribcl(map = "bladesystem")]
+    pub blade_system: Option<BladeSystem>,
+}
+
+impl Rimp {
+    /// The network port used by the management processor itself
+    pub fn management_nic(&self) -> Option<&Nic> {
+        self.server.as_ref()?.nics.iter().find(|nic| {
+            nic.description
+                .as_deref()
+                .is_some_and(|description| description.starts_with("iLO"))
+        })
+    }
+}
+
+/// Host server information (`HSI`)
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct Server {
+    #[ribcl(map = "sbsn")]
+    pub serial_number: Option<String>,
+    #[ribcl(map = "spn")]
+    pub product_name: Option<String>,
+    pub uuid: Option<String>,
+    #[ribcl(map = "sp")]
+    pub server_present: Option<u32>,
+    #[ribcl(map = "c_uuid")]
+    pub cuuid: Option<String>,
+    #[ribcl(map = "virtual")]
+    pub virtual_identity: Option<VirtualIdentity>,
+    #[ribcl(map = "productid")]
+    pub product_id: Option<String>,
+    pub nics: Vec<Nic>,
+}
+
+/// Serial number and UUID assigned by Virtual Connect
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct VirtualIdentity {
+    pub state: Option<String>,
+    #[ribcl(map = "vid")]
+    pub identity: Option<VirtualId>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct VirtualId {
+    #[ribcl(map = "bsn")]
+    pub serial_number: Option<String>,
+    #[ribcl(map = "c_uuid")]
+    pub cuuid: Option<String>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct Nic {
+    pub port: Option<u32>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    #[ribcl(map = "macaddr")]
+    pub mac_address: Option<String>,
+    #[ribcl(map = "ipaddr")]
+    pub ip_address: Option<String>,
+    pub status: Option<String>,
+}
+
+/// Management processor information (`MP`)
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct ManagementProcessor {
+    #[ribcl(map = "st")]
+    pub status: Option<u32>,
+    #[ribcl(map = "pn")]
+    pub product_name: Option<String>,
+    #[ribcl(map = "fwri")]
+    pub firmware_version: Option<String>,
+    #[ribcl(map = "bblk")]
+    pub bootblock_date: Option<String>,
+    #[ribcl(map = "hwri")]
+    pub hardware_revision: Option<String>,
+    #[ribcl(map = "sn")]
+    pub serial_number: Option<String>,
+    pub uuid: Option<String>,
+    #[ribcl(map = "ipm")]
+    pub ipmi: Option<u32>,
+    #[ribcl(map = "sso")]
+    pub single_sign_on: Option<u32>,
+    #[ribcl(map = "pwrm")]
+    pub power_manager_version: Option<String>,
+    #[ribcl(map = "ers")]
+    pub embedded_remote_support: Option<u32>,
+    #[ribcl(map = "ealert")]
+    pub alert_mail: Option<u32>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct Health {
+    pub status: Option<u32>,
+}
+
+/// Enclosure placement of a blade (`BLADESYSTEM`)
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct BladeSystem {
+    pub bay: Option<u32>,
+    pub manager: Option<EnclosureManager>,
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+pub struct EnclosureManager {
+    #[ribcl(map = "type")]
+    pub manager_type: Option<String>,
+    #[ribcl(map = "mgmtipaddr")]
+    pub ip_address: Option<String>,
+    #[ribcl(map = "st")]
+    pub status: Option<u32>,
+    pub rack: Option<String>,
+    #[ribcl(map = "encl")]
+    pub enclosure: Option<String>,
+    #[ribcl(map = "encl_sn")]
+    pub enclosure_serial_number: Option<String>,
+}
+
+pub(crate) fn parse_rimp(response: &str) -> Result<Rimp, commands::Error> {
+    let (mut xml_cursor, root) = XmlCursor::new(response)?;
+    let builder: RimpBuilder = xml_cursor
+        .builder_parse(root, None)
+        .map_err(|source| commands::Error::builder_parse("RimpBuilder", source))?;
+    builder
+        .try_into()
+        .map_err(|source| commands::Error::builder_parse("RimpBuilder", source))
+}
+
+impl client::Node {
+    /// Returns the server and management processor identity, doesn't require credentials
+    #[tracing::instrument]
+    pub async fn get_rimp(&mut self) -> Result<Rimp, commands::Error> {
+        let response = self.get_xmldata("All").await?;
+        parse_rimp(&response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_blade() {
+        let rimp = parse_rimp(
+            r#"<?xml version="1.0"?>
+<RIMP>
+<HSI>
+<SBSN>CZ3000TEST</SBSN>
+<SPN>ProLiant BL460c Gen8</SPN>
+<UUID>641016CZ3000TEST</UUID>
+<SP>1</SP>
+<cUUID>30313436-3631-5A43-3330-303054455354</cUUID>
+<VIRTUAL>
+<STATE>Inactive</STATE>
+<VID>
+<BSN></BSN>
+<cUUID></cUUID>
+</VID>
+</VIRTUAL>
+<PRODUCTID>641016-B21</PRODUCTID>
+<NICS>
+<NIC>
+<PORT>1</PORT>
+<DESCRIPTION>iLO 4</DESCRIPTION>
+<LOCATION>Embedded</LOCATION>
+<MACADDR>9c:8e:99:00:00:01</MACADDR>
+<IPADDR>10.0.0.10</IPADDR>
+<STATUS>OK</STATUS>
+</NIC>
+<NIC>
+<PORT>1</PORT>
+<DESCRIPTION>N/A</DESCRIPTION>
+<LOCATION>Embedded</LOCATION>
+<MACADDR>9c:8e:99:00:00:02</MACADDR>
+<IPADDR>N/A</IPADDR>
+<STATUS>Unknown</STATUS>
+</NIC>
+</NICS>
+</HSI>
+<MP>
+<ST>1</ST>
+<PN>Integrated Lights-Out 4 (iLO 4)</PN>
+<FWRI>2.55</FWRI>
+<BBLK>03/05/2013</BBLK>
+<HWRI>ASIC: 16</HWRI>
+<SN>ILOCZ3000TEST</SN>
+<UUID>ILO641016CZ3000TEST</UUID>
+<IPM>1</IPM>
+<SSO>0</SSO>
+<PWRM>3.4</PWRM>
+<ERS>0</ERS>
+<EALERT>1</EALERT>
+</MP>
+<SPATIAL>
+<DISCOVERY_RACK>Not Supported</DISCOVERY_RACK>
+<UPOS>0</UPOS>
+</SPATIAL>
+<HEALTH>
+<STATUS>2</STATUS>
+</HEALTH>
+<BLADESYSTEM>
+<BAY>3</BAY>
+<MANAGER>
+<TYPE>Onboard Administrator</TYPE>
+<MGMTIPADDR>10.0.0.5</MGMTIPADDR>
+<ST>2</ST>
+<RACK>rack1</RACK>
+<ENCL>enclosure1</ENCL>
+</MANAGER>
+</BLADESYSTEM>
+</RIMP>"#,
+        )
+        .unwrap();
+
+        let server = rimp.server.as_ref().unwrap();
+        assert_eq!(server.serial_number.as_deref(), Some("CZ3000TEST"));
+        assert_eq!(
+            server.cuuid.as_deref(),
+            Some("30313436-3631-5A43-3330-303054455354")
+        );
+        assert_eq!(server.nics.len(), 2);
+        assert_eq!(
+            rimp.management_nic().unwrap().ip_address.as_deref(),
+            Some("10.0.0.10")
+        );
+        let mp = rimp.management_processor.as_ref().unwrap();
+        assert_eq!(mp.firmware_version.as_deref(), Some("2.55"));
+        assert_eq!(mp.uuid.as_deref(), Some("ILO641016CZ3000TEST"));
+        assert_eq!(rimp.health, Some(Health { status: Some(2) }));
+        let blade_system = rimp.blade_system.unwrap();
+        assert_eq!(blade_system.bay, Some(3));
+        assert_eq!(
+            blade_system.manager.unwrap().enclosure.as_deref(),
+            Some("enclosure1")
+        );
+    }
+}
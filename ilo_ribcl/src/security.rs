@@ -1,12 +1,20 @@
 use crate::{
-    client, commands,
+    builder_parse, client, commands,
     into_ribcl::IntoRibcl,
-    types::{BoolBuilder, Certificate, StringBuilder},
+    types::{self, BoolBuilder, Certificate, SimpleBuilder, StringBuilder},
 };
 use ilo_ribcl_derive::{BuilderParse, WriteRibcl};
+use lazy_static::lazy_static;
+use openssl::x509::{X509Req, X509};
+use regex::Regex;
 use serde::Serialize;
 use serde_with::skip_serializing_none;
-use std::convert::TryInto;
+use std::{
+    convert::TryInto,
+    time::{Duration, Instant},
+};
+use tokio::time;
+use tracing::{event, Level};
 
 #[skip_serializing_none]
 #[derive(WriteRibcl, Debug, Default, Serialize, PartialEq, BuilderParse)]
@@ -30,14 +38,112 @@ pub enum ComputerLock {
     Custom { key: String },
 }
 
-impl client::Node {
-    /// Configures whether to use the fqdn or the short hostname for certificate requests
-    #[tracing::instrument]
-    pub async fn cert_fqdn(&mut self, value: bool) -> Result<String, commands::Error> {
-        // write
-        assert_fw!(self.firmware(), "iLO 2", (Ilo2));
-        unimplemented!()
+/// A PEM encoded certificate signing request generated by the iLO
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CertificateSigningRequest(String);
+
+impl CertificateSigningRequest {
+    /// Wrap a PEM encoded request, checking that it parses
+    pub fn from_pem(pem: &str) -> Result<Self, openssl::error::ErrorStack> {
+        let pem = pem.trim();
+        X509Req::from_pem(pem.as_bytes())?;
+        Ok(CertificateSigningRequest(format!("{}\n", pem)))
+    }
+
+    pub fn pem(&self) -> &str {
+        &self.0
+    }
+}
+
+pub type CertificateSigningRequestBuilder = SimpleBuilder<CertificateSigningRequest>;
+simple_builder_def!(
+    CertificateSigningRequest,
+    {
+        |value| match CertificateSigningRequest::from_pem(&value) {
+            Ok(csr) => Ok(Some(csr)),
+            Err(_) => Err(types::Error::InvalidString {
+                target: "CertificateSigningRequest",
+                value,
+            }),
+        }
+    },
+    { |value| value.0.clone() }
+);
+
+/// How long to wait between requests for a certificate signing request that
+/// is still being generated
+const CSR_POLL_INTERVAL: Duration = Duration::from_secs(15);
+/// How long iLO 3 and iLO 4 may take to generate a certificate signing request
+const CSR_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+lazy_static! {
+    static ref MESSAGE: Regex =
+        Regex::new(r#"(?is)<response\b[^>]*?\bmessage\s*=\s*['"]([^'"]*)['"]"#).unwrap();
+}
+
+/// The status message iLO 3 and iLO 4 answer with, instead of the request,
+/// while they generate it in the background
+fn csr_pending_message(response: &str) -> Option<String> {
+    MESSAGE
+        .captures_iter(response)
+        .map(|captures| captures[1].to_string())
+        .find(|message| message.to_ascii_lowercase().contains("generating"))
+}
+
+fn csr_pending(err: &commands::Error) -> bool {
+    match err {
+        commands::Error::Ribcl { message, .. } => {
+            message.to_ascii_lowercase().contains("generating")
+        }
+        commands::Error::CsrPending { .. } => true,
+        _ => false,
     }
+}
+
+fn parse_certificate_signing_request(
+    response: &str,
+) -> Result<CertificateSigningRequest, commands::Error> {
+    ribcl_parse_response!(
+        response,
+        "certificate_signing_request" -> CertificateSigningRequest
+    )
+    .and_then(|result| {
+        result.map_err(|source| commands::Error::builder_parse("CertificateSigningRequest", source))
+    })
+    .map_err(|err| match (&err, csr_pending_message(response)) {
+        (
+            commands::Error::BuilderParse {
+                source: builder_parse::Error::NotFound { .. },
+                ..
+            },
+            Some(message),
+        ) => commands::Error::CsrPending { message },
+        _ => err,
+    })
+}
+
+/// Check that `content` holds one or more PEM certificates
+fn validate_certificate_chain(content: &str) -> Result<(), commands::Error> {
+    let chain = X509::stack_from_pem(content.as_bytes()).map_err(|err| {
+        commands::Error::InvalidCertificate {
+            reason: err.to_string(),
+        }
+    })?;
+    if chain.is_empty() {
+        return Err(commands::Error::InvalidCertificate {
+            reason: String::from("no PEM certificate found"),
+        });
+    }
+    Ok(())
+}
+
+impl client::Node {
+    mod_method!(
+        /// Configures whether to use the fqdn or the short hostname for certificate requests
+        rib_info.cert_fqdn("value": bool),
+        "iLO 2",
+        (Ilo2)
+    );
 
     get_method!(
         /// Returns the certificate subject info
@@ -52,37 +158,62 @@ impl client::Node {
         "iLO 2 version >= 2.06",
         (Ilo2, "2.06")
     );
-    /*
-    mod_method!(
-        /// set certificate subject info
-        ///  Get a certificate signing request from the iLO
-        rib_info.certificate_signing_request : "csr_cert_settings" (types::CsrCertSettings),
-        "iLO 2 version >= 2.06",
-        (Ilo2, "2.06")
-    );
 
-    mod_method!(
-        rib_info.certificate_signing_request -> types::CertificateSigningRequest
+    // generating the request is a write, though it returns the request
+    command_builder!(
+        rib_info.certificate_signing_request() -> CertificateSigningRequest,
+        "write",
+        "certificate_signing_request",
+        [],
+        |request| { ribcl_command_body!(request, certificate_signing_request); },
+        parse_certificate_signing_request
     );
-    */
-    /// Returns a certificate signing request from the iLO
-    #[tracing::instrument]
-    pub async fn certificate_signing_request(&mut self) -> Result<String, commands::Error> {
-        // write
-        unimplemented!()
+
+    /// Returns a certificate signing request from the iLO, waiting up to 15
+    /// minutes for iLO 3 and iLO 4 to generate it
+    pub async fn certificate_signing_request(
+        &mut self,
+    ) -> Result<CertificateSigningRequest, commands::Error> {
+        self.certificate_signing_request_with_timeout(CSR_TIMEOUT)
+            .await
     }
 
-    /// Import a signed SSL certificate
-    #[tracing::instrument]
-    pub async fn import_certificate(
+    /// Returns a certificate signing request from the iLO, asking again while
+    /// it's being generated until `timeout` has passed
+    #[tracing::instrument(skip(self))]
+    pub async fn certificate_signing_request_with_timeout(
         &mut self,
-        content: Certificate,
-    ) -> Result<String, commands::Error> {
-        // write
-        assert_fw!(self.firmware(), "iLO 2 version >= 1.70", (Ilo2, "1.70"));
-        unimplemented!()
+        timeout: Duration,
+    ) -> Result<CertificateSigningRequest, commands::Error> {
+        let start = Instant::now();
+        loop {
+            let command = self.certificate_signing_request_command()?;
+            match self.send_command(command).await {
+                Err(err) if csr_pending(&err) && start.elapsed() + CSR_POLL_INTERVAL < timeout => {
+                    event!(Level::INFO, %err, "certificate signing request isn't ready");
+                    time::delay_for(CSR_POLL_INTERVAL).await;
+                }
+                result => return result,
+            }
+        }
     }
 
+    mod_method!(
+        /// Import a signed PEM certificate, followed by any intermediate
+        /// certificates.  The iLO resets to start using it.
+        rib_info.import_certificate(content: Certificate),
+        "iLO 4 or iLO 3 or iLO 2 version >= 1.70",
+        (Ilo4),
+        (Ilo3),
+        (Ilo2, "1.70")
+        => |request| {
+            validate_certificate_chain(&content)?;
+            ribcl_command_body!(request, import_certificate, {
+                write!(request, "\r\n{}\r\n", content.trim())?;
+            });
+        }
+    );
+
     /// Updates the computer lock setting
     #[tracing::instrument]
     pub async fn computer_lock_config(
@@ -118,12 +249,84 @@ impl client::Node {
         );
         let response = self.send_ribcl(request.into_bytes()).await?;
         match ribcl_parse_response!(response) {
-            Ok(_)
+            Ok(Ok(_))
             | Err(crate::commands::Error::BuilderParse {
                 source: crate::builder_parse::Error::NotFound { target: _ },
                 ..
             }) => Ok(()),
+            Ok(Err(err)) => Err(crate::commands::Error::builder_parse("", err)),
             Err(err) => Err(err),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands::tests::response,
+        types::{FwVersion, Version},
+    };
+    use openssl::{
+        hash::MessageDigest,
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{X509NameBuilder, X509ReqBuilder},
+    };
+
+    fn node() -> client::Node {
+        let firmware = FwVersion {
+            management_processor: Some(Version::Ilo4),
+            ..Default::default()
+        };
+        client::Node::new_with_fw(Default::default(), firmware).unwrap()
+    }
+
+    fn csr_pem() -> String {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "ilo.example.com").unwrap();
+        let name = name.build();
+        let mut builder = X509ReqBuilder::new().unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        String::from_utf8(builder.build().to_pem().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn csr_is_read_from_element_text() {
+        let pem = csr_pem();
+        let body = format!(
+            "<CERTIFICATE_SIGNING_REQUEST>\r\n{}</CERTIFICATE_SIGNING_REQUEST>\r\n",
+            pem
+        );
+        let csr = node()
+            .certificate_signing_request_command()
+            .unwrap()
+            .parse(&response(0, "No error", &body))
+            .unwrap();
+        assert_eq!(csr.pem(), pem);
+    }
+
+    #[test]
+    fn csr_pending_only_while_generating() {
+        let command = node().certificate_signing_request_command().unwrap();
+        let generating = "The iLO subsystem is currently generating a Certificate Signing Request(CSR), run script after 10 minutes or more to receive the CSR.";
+        let err = command.parse(&response(0, generating, "")).unwrap_err();
+        assert!(matches!(err, commands::Error::CsrPending { .. }));
+        assert!(csr_pending(&err));
+
+        let err = command.parse(&response(0, "No error", "")).unwrap_err();
+        assert!(!csr_pending(&err));
+    }
+
+    #[test]
+    fn certificate_chain_must_be_pem() {
+        assert!(matches!(
+            validate_certificate_chain("not a certificate"),
+            Err(commands::Error::InvalidCertificate { .. })
+        ));
+        assert!(validate_certificate_chain(&csr_pem()).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! A cloneable [Node] handle whose futures are `Send`.
+//!
+//! The [client::Node] is owned by a worker thread with its own runtime and
+//! handles send it commands over a channel, so a single node can be stored in
+//! a service and used from many tasks.  Commands to the same node are sent one
+//! at a time, in the order they were made, as the iLO doesn't reliably handle
+//! concurrent logins, while different nodes run in parallel.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! use ilo_ribcl::shared::Node;
+//!
+//! let node = Node::from_json(std::fs::read_to_string("endpoint.json")?).await?;
+//! let other = node.clone();
+//! let power = tokio::spawn(async move { other.get_host_power_status().await });
+//! println!("{:?} {:?}", node.get_fw_version().await?, power.await??);
+//! # Ok(())
+//! # }
+//! ```
+use futures::future::LocalBoxFuture;
+use ilo_console::ilo2::auth::Auth;
+use std::{fmt, future::Future, path::PathBuf, thread};
+use tokio::{
+    runtime,
+    sync::{mpsc, oneshot},
+};
+use tracing::{event, Level};
+
+use crate::{client, commands, types::FwVersion};
+
+type Job = Box<dyn for<'a> FnOnce(&'a mut client::Node) -> LocalBoxFuture<'a, ()> + Send>;
+
+fn job<F>(job: F) -> Job
+where
+    F: for<'a> FnOnce(&'a mut client::Node) -> LocalBoxFuture<'a, ()> + Send + 'static,
+{
+    Box::new(job)
+}
+
+/// Generates shared versions of [client::Node] methods
+macro_rules! shared_methods {
+    ($( $fn_name:ident ( $($arg:ident : $arg_type:ty),* ) -> $ret_type:ty; )+) => {
+        $(
+            pub async fn $fn_name(&self, $($arg: $arg_type),*) -> Result<$ret_type, commands::Error> {
+                self.call(move |node| Box::pin(node.$fn_name($($arg),*))).await?
+            }
+        )+
+    };
+}
+
+/// A cloneable handle to a [client::Node] running on its own thread
+#[derive(Clone)]
+pub struct Node {
+    jobs: mpsc::UnboundedSender<Job>,
+    hostname: String,
+    firmware: Option<FwVersion>,
+}
+
+impl fmt::Debug for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Node")
+            .field("hostname", &self.hostname)
+            .field("firmware", &self.firmware)
+            .finish()
+    }
+}
+
+impl Node {
+    /// Load a node from the contents of an endpoint file, see [client::Node::from_json]
+    pub async fn from_json(json: String) -> Result<Self, client::Error> {
+        Self::start(move || async move { client::Node::from_json(&json).await }).await
+    }
+
+    /// Connect to a node detecting its firmware, see [client::Node::new]
+    pub async fn new(auth: Auth) -> Result<Self, client::Error> {
+        Self::start(move || client::Node::new(auth)).await
+    }
+
+    /// Share an existing node
+    pub async fn from_node(node: client::Node) -> Result<Self, client::Error> {
+        Self::start(move || async move { Ok(node) }).await
+    }
+
+    /// Start the worker thread, the firmware is detected once by `connect`
+    async fn start<C, F>(connect: C) -> Result<Self, client::Error>
+    where
+        C: FnOnce() -> F + Send + 'static,
+        F: Future<Output = Result<client::Node, client::Error>>,
+    {
+        let (ready, started) = oneshot::channel();
+        thread::Builder::new()
+            .name(String::from("ilo-node"))
+            .spawn(move || {
+                let mut runtime = match runtime::Builder::new()
+                    .basic_scheduler()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(source) => {
+                        let _ = ready.send(Err(client::Error::Runtime { source }));
+                        return;
+                    }
+                };
+                runtime.block_on(async move {
+                    let mut node = match connect().await {
+                        Ok(node) => node,
+                        Err(err) => {
+                            let _ = ready.send(Err(err));
+                            return;
+                        }
+                    };
+                    let (jobs, mut queue) = mpsc::unbounded_channel();
+                    let handle = Node {
+                        jobs,
+                        hostname: node.auth().hostname,
+                        firmware: node.firmware(),
+                    };
+                    if ready.send(Ok(handle)).is_err() {
+                        return;
+                    }
+                    while let Some(job) = queue.recv().await {
+                        job(&mut node).await;
+                    }
+                    event!(Level::DEBUG, hostname = %node.auth().hostname, "node handles dropped");
+                });
+            })
+            .map_err(|source| client::Error::Runtime { source })?;
+        started.await.map_err(|_| client::Error::NodeStopped)?
+    }
+
+    /// Run `call` with exclusive access to the node
+    ///
+    /// ```no_run
+    /// # async fn run(node: ilo_ribcl::shared::Node) -> Result<(), ilo_ribcl::commands::Error> {
+    /// let firmware = node.call(|node| Box::pin(node.get_fw_version())).await??;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn call<T, F>(&self, call: F) -> Result<T, client::Error>
+    where
+        T: Send + 'static,
+        F: for<'a> FnOnce(&'a mut client::Node) -> LocalBoxFuture<'a, T> + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.jobs
+            .send(job(move |node| {
+                Box::pin(async move {
+                    let _ = reply.send(call(node).await);
+                })
+            }))
+            .map_err(|_| client::Error::NodeStopped)?;
+        response.await.map_err(|_| client::Error::NodeStopped)
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    /// The firmware detected when the node was started
+    pub fn firmware(&self) -> Option<FwVersion> {
+        self.firmware.clone()
+    }
+
+    pub async fn auth(&self) -> Result<Auth, client::Error> {
+        self.call(|node| Box::pin(async move { node.auth() })).await
+    }
+
+    pub async fn send_ribcl(&self, request: Vec<u8>) -> Result<String, client::Error> {
+        self.call(move |node| Box::pin(node.send_ribcl(request)))
+            .await?
+    }
+
+    pub async fn get_xmldata(&self, item: String) -> Result<String, client::Error> {
+        self.call(move |node| Box::pin(async move { node.get_xmldata(&item).await }))
+            .await?
+    }
+
+    /// Send a command built with one of the `*_command` methods of [client::Node]
+    pub async fn send_command<T: Send + 'static>(
+        &self,
+        command: commands::Command<T>,
+    ) -> Result<T, commands::Error> {
+        self.call(move |node| Box::pin(node.send_command(command)))
+            .await?
+    }
+
+    pub async fn update_rib_firmware(
+        &self,
+        path: PathBuf,
+    ) -> Result<crate::types::FwVersion, commands::Error> {
+        self.call(move |node| Box::pin(async move { node.update_rib_firmware(&path).await }))
+            .await?
+    }
+
+    pub async fn update_rib_firmware_with_progress<F>(
+        &self,
+        path: PathBuf,
+        progress: F,
+    ) -> Result<crate::types::FwVersion, commands::Error>
+    where
+        F: FnMut(crate::firmware::UpdateProgress) + Send + 'static,
+    {
+        self.call(move |node| {
+            Box::pin(async move {
+                node.update_rib_firmware_with_progress(&path, progress)
+                    .await
+            })
+        })
+        .await?
+    }
+
+    node_methods!(shared_methods);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{power::PowerStatus, simulator::Simulator, types::Version};
+
+    #[tokio::test]
+    async fn handles_are_used_from_spawned_tasks() {
+        let simulator = Simulator::new(Version::Ilo4);
+        let node = Node::from_node(simulator.node()).await.unwrap();
+        let other = node.clone();
+        tokio::spawn(async move { other.set_host_power(PowerStatus::On).await })
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(node.get_host_power_status().await.unwrap(), PowerStatus::On);
+    }
+}
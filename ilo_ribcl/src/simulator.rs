@@ -0,0 +1,1235 @@
+use async_trait::async_trait;
+use chrono::Local;
+use native_tls::{Identity, TlsAcceptor, TlsStream};
+use openssl::{
+    pkey::{PKey, Private},
+    x509::X509,
+};
+use quick_xml::events::{BytesStart, Event};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    str,
+    sync::{Arc, Mutex},
+    thread,
+};
+use thiserror::Error;
+use tracing::{event, instrument, Level};
+
+use crate::{client, types::Version};
+
+const PKCS12_PASSWORD: &str = "simulator";
+
+#[non_exhaustive]
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("io error: `{0}`")]
+    Io(#[from] io::Error),
+    #[error("tls error: `{0}`")]
+    Tls(#[from] native_tls::Error),
+    #[error("couldn't generate certificate: `{0}`")]
+    Certificate(#[from] openssl::error::ErrorStack),
+}
+
+/// A command read from a RIBCL request
+#[derive(Debug, Default)]
+struct Call {
+    section: String,
+    name: String,
+    attributes: HashMap<String, String>,
+    children: Vec<(String, HashMap<String, String>)>,
+    /// Text inside the command, e.g. an imported certificate
+    text: String,
+}
+
+impl Call {
+    /// Returns an attribute of the command or the value of a child element
+    fn value(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).map(String::as_str).or_else(|| {
+            self.children
+                .iter()
+                .find(|(child, _)| child == name)
+                .and_then(|(_, attributes)| attributes.get("value"))
+                .map(String::as_str)
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Request {
+    username: Option<String>,
+    password: Option<String>,
+    calls: Vec<Call>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct VirtualMedia {
+    image_url: Option<String>,
+    boot_option: String,
+    write_protect: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PowerAlert {
+    alert_type: String,
+    threshold: u32,
+    duration: u32,
+}
+
+/// The key the simulator generated and the certificate chain it serves, which
+/// is self signed until a certificate is imported
+struct Served {
+    key: PKey<Private>,
+    chain: Vec<X509>,
+    acceptor: Option<Arc<TlsAcceptor>>,
+}
+
+// the key is left out of the logs
+impl std::fmt::Debug for Served {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Served")
+            .field("chain", &self.chain)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct LogEntry {
+    severity: &'static str,
+    class: &'static str,
+    time: String,
+    description: String,
+}
+
+/// The state of the simulated server
+#[derive(Debug)]
+struct State {
+    power: bool,
+    power_on_minutes: u32,
+    auto_power: String,
+    power_saver: &'static str,
+    power_cap: String,
+    power_alert: PowerAlert,
+    uid: String,
+    server_name: String,
+    one_time_boot: String,
+    persistent_boot: Vec<String>,
+    virtual_media: HashMap<String, VirtualMedia>,
+    ilo_log: Vec<LogEntry>,
+    server_log: Vec<LogEntry>,
+    served: Option<Served>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            power: false,
+            power_on_minutes: 1440,
+            auto_power: String::from("RESTORE"),
+            power_saver: "AUTO",
+            power_cap: String::from("OFF"),
+            power_alert: PowerAlert {
+                alert_type: String::from("DISABLED"),
+                threshold: 0,
+                duration: 0,
+            },
+            uid: String::from("OFF"),
+            server_name: String::from("simulator"),
+            one_time_boot: String::from("NORMAL"),
+            persistent_boot: ["CDROM", "FLOPPY", "USB", "HDD", "NETWORK1"]
+                .iter()
+                .map(|device| device.to_string())
+                .collect(),
+            virtual_media: HashMap::new(),
+            ilo_log: vec![],
+            server_log: vec![],
+            served: None,
+        }
+    }
+}
+
+impl State {
+    fn log(&mut self, class: &'static str, description: &str) {
+        let entry = LogEntry {
+            severity: "Informational",
+            class,
+            time: Local::now().format("%m/%d/%Y %H:%M").to_string(),
+            description: description.to_string(),
+        };
+        self.ilo_log.push(entry.clone());
+        if class == "System" {
+            self.server_log.push(entry);
+        }
+    }
+
+    fn set_power(&mut self, on: bool, description: &str) {
+        self.power = on;
+        self.log("System", description);
+    }
+}
+
+/// A simulated iLO that answers RIBCL requests and keeps the state they change.
+///
+/// The simulator can be used in process through [Simulator::client] or served
+/// over the network with [Simulator::serve], speaking the `/ribcl` and
+/// `/xmldata` HTTPS endpoints and the iLO 2 raw TLS protocol.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), ilo_ribcl::commands::Error> {
+/// use ilo_ribcl::{power::PowerStatus, simulator::Simulator, types::Version};
+///
+/// let simulator = Simulator::new(Version::Ilo4);
+/// let mut node = simulator.node();
+/// node.set_host_power(PowerStatus::On).await?;
+/// assert_eq!(node.get_host_power_status().await?, PowerStatus::On);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Simulator {
+    version: Version,
+    hostname: String,
+    username: String,
+    password: String,
+    state: Mutex<State>,
+}
+
+/// A running network simulator
+#[derive(Debug, Clone)]
+pub struct Server {
+    /// The address the simulator is listening on
+    pub addr: SocketAddr,
+    /// SHA-256 fingerprint of the simulator's self signed certificate
+    pub fingerprint: String,
+}
+
+impl Simulator {
+    /// Create a simulator with the given firmware personality accepting the
+    /// username `admin` and password `password`
+    pub fn new(version: Version) -> Arc<Self> {
+        Self::with_credentials(version, "admin", "password")
+    }
+
+    pub fn with_credentials(version: Version, username: &str, password: &str) -> Arc<Self> {
+        Arc::new(Self {
+            version,
+            hostname: String::from("localhost"),
+            username: username.to_string(),
+            password: password.to_string(),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    /// Credentials accepted by the simulator
+    pub fn auth(&self) -> ilo_console::ilo2::auth::Auth {
+        let mut auth = ilo_console::ilo2::auth::Auth::default();
+        auth.hostname = self.hostname.clone();
+        auth.username = self.username.clone();
+        auth.password = self.password.clone();
+        auth
+    }
+
+    /// The firmware version reported by the simulator
+    pub fn firmware(&self) -> crate::types::FwVersion {
+        let (version, date) = self.firmware_version();
+        crate::types::FwVersion {
+            firmware_version: Some(version.to_string()),
+            firmware_date: chrono::NaiveDate::parse_from_str(date, "%b %d %Y").ok(),
+            management_processor: Some(self.version.clone()),
+            license_type: Some(String::from("iLO Advanced")),
+        }
+    }
+
+    fn firmware_version(&self) -> (&'static str, &'static str) {
+        match self.version {
+            Version::Ilo2 => ("2.33", "Jun 14 2018"),
+            Version::Ilo3 => ("1.94", "Jun 14 2019"),
+            Version::Ilo4 => ("2.78", "Apr 28 2021"),
+            Version::Ilo5 => ("2.72", "Sep 04 2022"),
+        }
+    }
+
+    fn processor_name(&self) -> &'static str {
+        match self.version {
+            Version::Ilo2 => "iLO2",
+            Version::Ilo3 => "iLO3",
+            Version::Ilo4 => "iLO4",
+            Version::Ilo5 => "iLO5",
+        }
+    }
+
+    /// A client that sends requests directly to this simulator
+    pub fn client(self: &Arc<Self>) -> SimulatorClient {
+        SimulatorClient {
+            simulator: Arc::clone(self),
+        }
+    }
+
+    /// A node connected to this simulator through [Simulator::client]
+    pub fn node(self: &Arc<Self>) -> client::Node {
+        client::Node::new_with_fw_and_client(self.auth(), self.firmware(), Box::new(self.client()))
+    }
+
+    /// Answer a RIBCL request, returning a response document for the login
+    /// followed by one per command
+    #[instrument(skip(self, request))]
+    pub fn handle_ribcl(&self, request: &str) -> String {
+        let request = match parse_request(request) {
+            Ok(request) => request,
+            Err(err) => {
+                event!(Level::DEBUG, %err, "invalid request");
+                return document(0x0001, "Syntax error: invalid xml", "");
+            }
+        };
+        if request.username.as_deref() != Some(self.username.as_str())
+            || request.password.as_deref() != Some(self.password.as_str())
+        {
+            return document(0x005f, "Login failed.", "");
+        }
+        let mut response = document(0, "No error", "");
+        let mut state = self.state.lock().unwrap();
+        for call in &request.calls {
+            event!(Level::DEBUG, section = %call.section, command = %call.name);
+            response.push_str(&self.handle_call(&mut state, call));
+        }
+        response
+    }
+
+    fn handle_call(&self, state: &mut State, call: &Call) -> String {
+        let ok = |body: String| document(0, "No error", &body);
+        let not_supported = || document(0x003c, "Command not supported.", "");
+        match call.name.as_str() {
+            "get_fw_version" => {
+                let (version, date) = self.firmware_version();
+                ok(format!(
+                    "<GET_FW_VERSION FIRMWARE_VERSION=\"{}\" FIRMWARE_DATE=\"{}\" MANAGEMENT_PROCESSOR=\"{}\" LICENSE_TYPE=\"iLO Advanced\"/>\r\n",
+                    version,
+                    date,
+                    self.processor_name()
+                ))
+            }
+            "get_host_power_status" => ok(format!(
+                "<GET_HOST_POWER HOST_POWER=\"{}\"/>\r\n",
+                if state.power { "ON" } else { "OFF" }
+            )),
+            "set_host_power" => match call.value("host_power").map(str::to_ascii_lowercase) {
+                Some(value) if value == "yes" || value == "on" => {
+                    state.set_power(true, "Server power turned on.");
+                    ok(String::new())
+                }
+                Some(value) if value == "no" || value == "off" => {
+                    state.set_power(false, "Server power turned off.");
+                    ok(String::new())
+                }
+                _ => document(0x0001, "Syntax error: invalid HOST_POWER value.", ""),
+            },
+            "press_pwr_btn" => {
+                let on = !state.power;
+                state.set_power(on, "Power button pressed.");
+                ok(String::new())
+            }
+            "hold_pwr_btn" => {
+                state.set_power(false, "Power button held.");
+                ok(String::new())
+            }
+            "get_host_power_reg_info" if self.version == Version::Ilo2 => {
+                let mut body = String::from(
+                    "<GET_HOST_POWER_REG_INFO>\r\n<NUMBER_PROCESSORS>2</NUMBER_PROCESSORS>\r\n<NUMBER_PSTATES>2</NUMBER_PSTATES>\r\n",
+                );
+                for processor in 0..2 {
+                    let _ = write!(
+                        body,
+                        "<PROCESSOR_{0}>\r\n<CURRENT_PSTATE>0</CURRENT_PSTATE>\r\n<PSTATE_0>\r\n<TOTAL_AVERAGE>{1:.1}</TOTAL_AVERAGE>\r\n</PSTATE_0>\r\n<PSTATE_1>\r\n<TOTAL_AVERAGE>{2:.1}</TOTAL_AVERAGE>\r\n</PSTATE_1>\r\n</PROCESSOR_{0}>\r\n",
+                        processor,
+                        if state.power { 92.5 } else { 0.0 },
+                        if state.power { 7.5 } else { 0.0 },
+                    );
+                }
+                body.push_str("</GET_HOST_POWER_REG_INFO>\r\n");
+                ok(body)
+            }
+            "get_power_readings" => {
+                let readings = if state.power {
+                    [140, 135, 182, 96]
+                } else {
+                    [0; 4]
+                };
+                let mut body = String::from("<GET_POWER_READINGS>\r\n");
+                for (name, value) in ["PRESENT", "AVERAGE", "MAXIMUM", "MINIMUM"]
+                    .iter()
+                    .zip(readings.iter())
+                {
+                    let _ = write!(
+                        body,
+                        "<{}_POWER_READING VALUE=\"{}\" UNIT=\"Watts\"/>\r\n",
+                        name, value
+                    );
+                }
+                body.push_str("</GET_POWER_READINGS>\r\n");
+                ok(body)
+            }
+            "get_server_auto_pwr" => ok(format!(
+                "<SERVER_AUTO_PWR VALUE=\"{}\"/>\r\n",
+                state.auto_power
+            )),
+            "server_auto_pwr" => match call.value("value") {
+                Some(value) => {
+                    state.auto_power = value.to_ascii_uppercase();
+                    ok(String::new())
+                }
+                None => document(0x0001, "Syntax error: missing VALUE.", ""),
+            },
+            "get_server_power_on_time" => ok(format!(
+                "<SERVER_POWER_ON_MINUTES VALUE=\"{}\"/>\r\n",
+                state.power_on_minutes
+            )),
+            "clear_server_power_on_time" => {
+                state.power_on_minutes = 0;
+                ok(String::new())
+            }
+            "get_host_power_saver_status" => ok(format!(
+                "<GET_HOST_POWER_SAVER HOST_POWER_SAVER=\"{}\"/>\r\n",
+                state.power_saver
+            )),
+            "set_host_power_saver" => {
+                state.power_saver = match call.value("host_power_saver") {
+                    Some("1") => "OFF",
+                    Some("2") => "MIN",
+                    Some("3") => "AUTO",
+                    Some("4") => "MAX",
+                    _ => return document(0x0001, "Syntax error: invalid HOST_POWER_SAVER.", ""),
+                };
+                ok(String::new())
+            }
+            "get_power_cap" => ok(format!(
+                "<POWER_CAP VALUE=\"{}\"/>\r\n",
+                state.power_cap
+            )),
+            "set_power_cap" => match call.value("power_cap") {
+                Some(cap) if cap.eq_ignore_ascii_case("off") || cap.parse::<u32>().is_ok() => {
+                    state.power_cap = cap.to_ascii_uppercase();
+                    ok(String::new())
+                }
+                _ => document(0x0001, "Syntax error: invalid POWER_CAP.", ""),
+            },
+            "get_host_pwr_micro_ver" => ok(String::from(
+                "<GET_HOST_PWR_MICRO_VER>\r\n<PWR_MICRO VERSION=\"2.3\"/>\r\n</GET_HOST_PWR_MICRO_VER>\r\n",
+            )),
+            "get_pwreg" => {
+                let alert = &state.power_alert;
+                let mut body = format!(
+                    "<GET_PWREG>\r\n<PCAP MODE=\"{}\"/>\r\n<EFFICIENCY_MODE>{}</EFFICIENCY_MODE>\r\n<PWRALERT TYPE=\"{}\"",
+                    state.power_cap, state.power_saver, alert.alert_type
+                );
+                if alert.alert_type != "DISABLED" {
+                    let _ = write!(
+                        body,
+                        " THRESHOLD=\"{}\" DURATION=\"{}\"",
+                        alert.threshold, alert.duration
+                    );
+                }
+                let _ = write!(
+                    body,
+                    "/>\r\n<GET_HOST_POWER HOST_POWER=\"{}\"/>\r\n</GET_PWREG>\r\n",
+                    if state.power { "ON" } else { "OFF" }
+                );
+                ok(body)
+            }
+            "set_pwreg" => {
+                let child = |name: &str| {
+                    call.children
+                        .iter()
+                        .find(|(child, _)| child == name)
+                        .map(|(_, attributes)| attributes)
+                };
+                let setting = |name: &str| {
+                    child("pwralert_settings")
+                        .and_then(|settings| settings.get(name))
+                        .and_then(|value| value.parse().ok())
+                };
+                let alert_type = child("pwralert")
+                    .and_then(|alert| alert.get("type"))
+                    .map(|alert_type| alert_type.to_ascii_uppercase());
+                match alert_type.as_deref() {
+                    Some("DISABLED") => {
+                        state.power_alert.alert_type = String::from("DISABLED");
+                        ok(String::new())
+                    }
+                    Some(alert_type @ "PEAK") | Some(alert_type @ "AVERAGE") => {
+                        match (setting("threshold"), setting("duration")) {
+                            (Some(threshold), Some(duration)) => {
+                                state.power_alert = PowerAlert {
+                                    alert_type: alert_type.to_string(),
+                                    threshold,
+                                    duration,
+                                };
+                                ok(String::new())
+                            }
+                            _ => document(0x0001, "Syntax error: missing PWRALERT_SETTINGS.", ""),
+                        }
+                    }
+                    _ => document(0x0001, "Syntax error: invalid PWRALERT TYPE.", ""),
+                }
+            }
+            "certificate_signing_request" => match self.certificate_signing_request(state) {
+                Ok(pem) => ok(format!(
+                    "<CERTIFICATE_SIGNING_REQUEST>\r\n{}</CERTIFICATE_SIGNING_REQUEST>\r\n",
+                    pem
+                )),
+                Err(err) => document(0x0001, &format!("Error generating the request: {}", err), ""),
+            },
+            "import_certificate" => match self.import_certificate(state, &call.text) {
+                Ok(()) => {
+                    state.log("iLO", "Certificate imported, iLO reset.");
+                    ok(String::new())
+                }
+                Err(message) => document(0x0001, &message, ""),
+            },
+            "reset_rib" => {
+                state.log("iLO", "iLO reset.");
+                ok(String::new())
+            }
+            "cold_boot_server" | "warm_boot_server" | "reset_server" => {
+                if state.power {
+                    state.log("System", "Server reset.");
+                    ok(String::new())
+                } else {
+                    document(0x0001, "Server is currently powered off.", "")
+                }
+            }
+            "get_uid_status" => ok(format!("<GET_UID_STATUS UID=\"{}\"/>\r\n", state.uid)),
+            "uid_control" => {
+                state.uid = match call.value("uid").map(str::to_ascii_lowercase) {
+                    Some(value) if value == "yes" || value == "on" => String::from("ON"),
+                    _ => String::from("OFF"),
+                };
+                ok(String::new())
+            }
+            "get_server_name" => ok(format!(
+                "<SERVER_NAME VALUE=\"{}\"/>\r\n",
+                escape(&state.server_name)
+            )),
+            "server_name" => {
+                if let Some(name) = call.value("value") {
+                    state.server_name = name.to_string();
+                }
+                ok(String::new())
+            }
+            "get_one_time_boot" => ok(format!(
+                "<ONE_TIME_BOOT>\r\n<BOOT_TYPE VALUE=\"{}\"/>\r\n</ONE_TIME_BOOT>\r\n",
+                state.one_time_boot
+            )),
+            "set_one_time_boot" => {
+                if let Some(device) = call.value("value") {
+                    state.one_time_boot = device.to_ascii_uppercase();
+                }
+                ok(String::new())
+            }
+            "get_persistent_boot" => {
+                let mut body = String::from("<PERSISTENT_BOOT>\r\n");
+                for device in &state.persistent_boot {
+                    let _ = write!(body, "<DEVICE value=\"{}\"/>\r\n", device);
+                }
+                body.push_str("</PERSISTENT_BOOT>\r\n");
+                ok(body)
+            }
+            "set_persistent_boot" => {
+                let devices: Vec<String> = call
+                    .children
+                    .iter()
+                    .filter(|(name, _)| name == "device")
+                    .filter_map(|(_, attributes)| attributes.get("value"))
+                    .map(|device| device.to_ascii_uppercase())
+                    .collect();
+                // devices that aren't listed keep their relative order after those that are
+                let mut order = devices.clone();
+                for device in &state.persistent_boot {
+                    if !order.contains(device) {
+                        order.push(device.clone());
+                    }
+                }
+                state.persistent_boot = order;
+                ok(String::new())
+            }
+            "get_vm_status" => {
+                let device = vm_device(call);
+                let media = state
+                    .virtual_media
+                    .get(&device)
+                    .cloned()
+                    .unwrap_or_default();
+                let mut body = format!(
+                    "<GET_VM_STATUS VM_APPLET=\"DISCONNECTED\" DEVICE=\"{}\" BOOT_OPTION=\"{}\" WRITE_PROTECT=\"{}\" IMAGE_INSERTED=\"{}\"",
+                    device,
+                    if media.boot_option.is_empty() { "NO_BOOT" } else { &media.boot_option },
+                    if media.write_protect { "YES" } else { "NO" },
+                    if media.image_url.is_some() { "YES" } else { "NO" },
+                );
+                if let Some(url) = &media.image_url {
+                    let _ = write!(body, " IMAGE_URL=\"{}\"", escape(url));
+                }
+                body.push_str("/>\r\n");
+                ok(body)
+            }
+            "insert_virtual_media" => {
+                let device = vm_device(call);
+                let media = state.virtual_media.entry(device).or_default();
+                media.image_url = call.value("image_url").map(String::from);
+                state.log("iLO", "Virtual media inserted.");
+                ok(String::new())
+            }
+            "eject_virtual_media" => {
+                let device = vm_device(call);
+                match state.virtual_media.get_mut(&device) {
+                    Some(media) if media.image_url.is_some() => {
+                        media.image_url = None;
+                        state.log("iLO", "Virtual media ejected.");
+                        ok(String::new())
+                    }
+                    _ => document(0x0001, "No image present in the Virtual Media drive.", ""),
+                }
+            }
+            "set_vm_status" => {
+                let device = vm_device(call);
+                let media = state.virtual_media.entry(device).or_default();
+                if let Some(option) = call.value("vm_boot_option") {
+                    media.boot_option = option.to_ascii_uppercase();
+                }
+                if let Some(protect) = call.value("vm_write_protect") {
+                    media.write_protect =
+                        matches!(protect.to_ascii_lowercase().as_str(), "y" | "yes" | "true");
+                }
+                ok(String::new())
+            }
+            "get_event_log" => {
+                let log = if call.section == "server_info" {
+                    &state.server_log
+                } else {
+                    &state.ilo_log
+                };
+                let mut body = String::from("<EVENT_LOG DESCRIPTION=\"Event Log\">\r\n");
+                for entry in log {
+                    let _ = write!(
+                        body,
+                        "<EVENT SEVERITY=\"{}\" CLASS=\"{}\" LAST_UPDATE=\"{}\" INITIAL_UPDATE=\"{}\" COUNT=\"1\" DESCRIPTION=\"{}\"/>\r\n",
+                        entry.severity,
+                        entry.class,
+                        entry.time,
+                        entry.time,
+                        escape(&entry.description)
+                    );
+                }
+                body.push_str("</EVENT_LOG>\r\n");
+                ok(body)
+            }
+            "clear_eventlog" => {
+                state.ilo_log.clear();
+                ok(String::new())
+            }
+            "clear_iml" => {
+                state.server_log.clear();
+                ok(String::new())
+            }
+            name if name.starts_with("get_") => not_supported(),
+            name => document(
+                0x0001,
+                &format!(
+                    "Syntax error: Line #0: syntax error near \"{}\" in the line: \"<{}>\".",
+                    name.to_ascii_uppercase(),
+                    name.to_ascii_uppercase()
+                ),
+                "",
+            ),
+        }
+    }
+
+    /// The key and certificate served, generating them on first use
+    fn served<'a>(&self, state: &'a mut State) -> Result<&'a mut Served, Error> {
+        if state.served.is_none() {
+            let (key, certificate) = self_signed(&self.hostname)?;
+            state.served = Some(Served {
+                key,
+                chain: vec![certificate],
+                acceptor: None,
+            });
+        }
+        Ok(state.served.as_mut().unwrap())
+    }
+
+    /// The acceptor for TLS connections and the fingerprint of the certificate
+    /// it presents
+    fn acceptor(&self) -> Result<(Arc<TlsAcceptor>, String), Error> {
+        let mut state = self.state.lock().unwrap();
+        let served = self.served(&mut state)?;
+        let fingerprint = ilo_console::tls::fingerprint(&served.chain[0].to_der()?);
+        if let Some(acceptor) = &served.acceptor {
+            return Ok((Arc::clone(acceptor), fingerprint));
+        }
+        let acceptor = Arc::new(TlsAcceptor::new(identity(
+            &self.hostname,
+            &served.key,
+            &served.chain,
+        )?)?);
+        served.acceptor = Some(Arc::clone(&acceptor));
+        Ok((acceptor, fingerprint))
+    }
+
+    /// A PEM request for a certificate for the simulator's key
+    fn certificate_signing_request(&self, state: &mut State) -> Result<String, Error> {
+        use openssl::{hash::MessageDigest, x509::X509NameBuilder, x509::X509ReqBuilder};
+
+        let served = self.served(state)?;
+        let mut name = X509NameBuilder::new()?;
+        name.append_entry_by_text("CN", &self.hostname)?;
+        let mut request = X509ReqBuilder::new()?;
+        request.set_subject_name(&name.build())?;
+        request.set_pubkey(&served.key)?;
+        request.sign(&served.key, MessageDigest::sha256())?;
+        Ok(String::from_utf8_lossy(&request.build().to_pem()?).into_owned())
+    }
+
+    /// Serve an imported certificate chain on new connections if it's for the
+    /// simulator's key
+    fn import_certificate(&self, state: &mut State, pem: &str) -> Result<(), String> {
+        let chain = X509::stack_from_pem(pem.as_bytes())
+            .ok()
+            .filter(|chain| !chain.is_empty())
+            .ok_or_else(|| String::from("Error reading the certificate."))?;
+        let served = self.served(state).map_err(|err| err.to_string())?;
+        let matches = chain[0]
+            .public_key()
+            .map(|key| key.public_eq(&served.key))
+            .unwrap_or(false);
+        if !matches {
+            return Err(String::from(
+                "The certificate does not match the certificate signing request.",
+            ));
+        }
+        served.chain = chain;
+        served.acceptor = None;
+        Ok(())
+    }
+
+    /// Answer a `/xmldata` request
+    pub fn xmldata(&self, item: &str) -> String {
+        let (version, _) = self.firmware_version();
+        let product = match self.version {
+            Version::Ilo2 => "Integrated Lights-Out 2 (iLO 2)",
+            Version::Ilo3 => "Integrated Lights-Out 3 (iLO 3)",
+            Version::Ilo4 => "Integrated Lights-Out 4 (iLO 4)",
+            Version::Ilo5 => "Integrated Lights-Out 5 (iLO 5)",
+        };
+        match item.to_ascii_lowercase().as_str() {
+            "cpqkey" => String::from(
+                "<?xml version=\"1.0\"?><PROLIANTKEY><KEY>XXXXX-XXXXX-XXXXX-XXXXX-XXXXX</KEY><LNAME>iLO Advanced</LNAME><SBSN>SIM0000001</SBSN></PROLIANTKEY>",
+            ),
+            _ => format!(
+                "<?xml version=\"1.0\"?><RIMP><HSI><SBSN>SIM0000001</SBSN><SPN>ProLiant Simulator</SPN><UUID>SIM0000001</UUID><SP>1</SP></HSI><MP><ST>1</ST><PN>{}</PN><FWRI>{}</FWRI><HWRI>ASIC: 0</HWRI><SN>ILOSIM0000001</SN><UUID>ILOSIM0000001</UUID></MP></RIMP>",
+                product, version
+            ),
+        }
+    }
+
+    /// Serve the simulator on `addr` from background threads, returning the
+    /// bound address and certificate fingerprint
+    pub fn serve<A: ToSocketAddrs>(self: &Arc<Self>, addr: A) -> Result<Server, Error> {
+        let (_, fingerprint) = self.acceptor()?;
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let simulator = Arc::clone(self);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        event!(Level::WARN, %err, "accept failed");
+                        continue;
+                    }
+                };
+                // an imported certificate is served on the following connections
+                let acceptor = match simulator.acceptor() {
+                    Ok((acceptor, _)) => acceptor,
+                    Err(err) => {
+                        event!(Level::WARN, %err, "no certificate to serve");
+                        continue;
+                    }
+                };
+                let simulator = Arc::clone(&simulator);
+                thread::spawn(move || match acceptor.accept(stream) {
+                    Ok(stream) => {
+                        if let Err(err) = simulator.handle_connection(stream) {
+                            event!(Level::DEBUG, %err, "connection closed");
+                        }
+                    }
+                    Err(err) => event!(Level::DEBUG, %err, "tls handshake failed"),
+                });
+            }
+        });
+        event!(Level::INFO, %addr, %fingerprint, "simulator listening");
+        Ok(Server { addr, fingerprint })
+    }
+
+    fn handle_connection(&self, stream: TlsStream<TcpStream>) -> io::Result<()> {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let start = reader.fill_buf()?;
+            if start.is_empty() {
+                return Ok(());
+            }
+            let http = start.starts_with(b"GET ") || start.starts_with(b"POST ");
+            let keep_open = if http {
+                self.handle_http(&mut reader)?
+            } else {
+                self.handle_raw(&mut reader)?
+            };
+            if !keep_open {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Answer a request on the iLO 2 raw TLS protocol
+    fn handle_raw(&self, reader: &mut BufReader<TlsStream<TcpStream>>) -> io::Result<bool> {
+        let mut request = Vec::new();
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            let len = buf.len();
+            request.extend_from_slice(buf);
+            reader.consume(len);
+            let complete = String::from_utf8_lossy(&request)
+                .trim_end()
+                .to_ascii_lowercase()
+                .ends_with("</ribcl>");
+            if complete {
+                break;
+            }
+        }
+        if request.is_empty() {
+            return Ok(false);
+        }
+        let response = self.handle_ribcl(&String::from_utf8_lossy(&request));
+        reader.get_mut().write_all(response.as_bytes())?;
+        reader.get_mut().flush()?;
+        Ok(true)
+    }
+
+    /// Answer an HTTP request for `/ribcl` or `/xmldata`
+    fn handle_http(&self, reader: &mut BufReader<TlsStream<TcpStream>>) -> io::Result<bool> {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let target = parts.next().unwrap_or_default().to_string();
+
+        let mut content_length = 0;
+        let mut keep_open = true;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.trim().parse().unwrap_or(0),
+                    "connection" => keep_open = !value.trim().eq_ignore_ascii_case("close"),
+                    _ => {}
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body)?;
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (target.as_str(), ""),
+        };
+        event!(Level::DEBUG, %method, path);
+        let (status, response) = match (method.as_str(), path) {
+            // iLO 2 only accepts RIBCL on the raw TLS protocol
+            ("POST", "/ribcl") if self.version != Version::Ilo2 => {
+                ("200 OK", self.handle_ribcl(&String::from_utf8_lossy(&body)))
+            }
+            ("GET", "/xmldata") => {
+                let item = query
+                    .split('&')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| *key == "item")
+                    .map(|(_, value)| value)
+                    .unwrap_or("All");
+                ("200 OK", self.xmldata(item))
+            }
+            _ => (
+                "404 Not Found",
+                String::from("<html><body>404 Not Found</body></html>"),
+            ),
+        };
+        write!(
+            reader.get_mut(),
+            "HTTP/1.1 {}\r\nContent-Type: text/xml\r\nContent-Length: {}\r\n\r\n{}",
+            status,
+            response.len(),
+            response
+        )?;
+        reader.get_mut().flush()?;
+        Ok(keep_open)
+    }
+}
+
+/// A client that answers requests from a [Simulator] without a network connection
+#[derive(Debug, Clone)]
+pub struct SimulatorClient {
+    simulator: Arc<Simulator>,
+}
+
+#[async_trait]
+impl client::Client for SimulatorClient {
+    async fn send_ribcl(&mut self, request: Vec<u8>) -> Result<String, client::Error> {
+        Ok(self.simulator.handle_ribcl(str::from_utf8(&request)?))
+    }
+
+    async fn get_xmldata(&mut self, item: &str) -> Result<String, client::Error> {
+        Ok(self.simulator.xmldata(item))
+    }
+}
+
+fn vm_device(call: &Call) -> String {
+    call.value("device").unwrap_or("CDROM").to_ascii_uppercase()
+}
+
+fn attributes(element: &BytesStart<'_>) -> HashMap<String, String> {
+    element
+        .attributes()
+        .filter_map(|attribute| attribute.ok())
+        .map(|attribute| {
+            let key = String::from_utf8_lossy(attribute.key).to_ascii_lowercase();
+            let value = attribute
+                .unescaped_value()
+                .map(|value| String::from_utf8_lossy(&value).into_owned())
+                .unwrap_or_default();
+            (key, value)
+        })
+        .collect()
+}
+
+/// Read the login and commands from a RIBCL request
+fn parse_request(request: &str) -> Result<Request, quick_xml::Error> {
+    let mut reader = quick_xml::Reader::from_str(request);
+    reader.trim_text(true);
+    let mut parsed = Request::default();
+    let mut section = String::new();
+    let mut depth = 0;
+    let mut buf = Vec::new();
+    loop {
+        let (element, empty) = match reader.read_event(&mut buf)? {
+            Event::Start(element) => (element.into_owned(), false),
+            Event::Empty(element) => (element.into_owned(), true),
+            Event::End(_) => {
+                depth = std::cmp::max(depth, 1) - 1;
+                buf.clear();
+                continue;
+            }
+            Event::Text(text) => {
+                let text = text.unescape_and_decode(&reader)?;
+                if let Some(call) = parsed.calls.last_mut().filter(|_| depth == 4) {
+                    call.text.push_str(&text);
+                }
+                buf.clear();
+                continue;
+            }
+            Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        let name = String::from_utf8_lossy(element.name()).to_ascii_lowercase();
+        match depth {
+            1 if name == "login" => {
+                let mut login = attributes(&element);
+                parsed.username = login.remove("user_login");
+                parsed.password = login.remove("password");
+            }
+            2 => section = name,
+            3 => parsed.calls.push(Call {
+                section: section.clone(),
+                name,
+                attributes: attributes(&element),
+                children: vec![],
+                text: String::new(),
+            }),
+            depth if depth > 3 => {
+                if let Some(call) = parsed.calls.last_mut() {
+                    call.children.push((name, attributes(&element)));
+                }
+            }
+            _ => {}
+        }
+        if !empty {
+            depth += 1;
+        }
+        buf.clear();
+    }
+    Ok(parsed)
+}
+
+fn document(status: u16, message: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\"?>\r\n<RIBCL VERSION=\"2.23\">\r\n<RESPONSE\r\n    STATUS=\"0x{:04X}\"\r\n    MESSAGE='{}'\r\n     />\r\n{}</RIBCL>\r\n",
+        status, message, body
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Generate a key and self signed certificate for the simulator
+fn self_signed(hostname: &str) -> Result<(PKey<Private>, X509), openssl::error::ErrorStack> {
+    use openssl::{
+        asn1::Asn1Time,
+        bn::{BigNum, MsbOption},
+        hash::MessageDigest,
+        rsa::Rsa,
+        x509::X509NameBuilder,
+    };
+
+    let key = PKey::from_rsa(Rsa::generate(2048)?)?;
+    let mut name = X509NameBuilder::new()?;
+    name.append_entry_by_text("CN", hostname)?;
+    let name = name.build();
+    let mut serial = BigNum::new()?;
+    serial.rand(64, MsbOption::MAYBE_ZERO, false)?;
+
+    let serial = serial.to_asn1_integer()?;
+    let not_before = Asn1Time::days_from_now(0)?;
+    let not_after = Asn1Time::days_from_now(365)?;
+
+    let mut builder = X509::builder()?;
+    builder.set_version(2)?;
+    builder.set_serial_number(&serial)?;
+    builder.set_subject_name(&name)?;
+    builder.set_issuer_name(&name)?;
+    builder.set_pubkey(&key)?;
+    builder.set_not_before(&not_before)?;
+    builder.set_not_after(&not_after)?;
+    builder.sign(&key, MessageDigest::sha256())?;
+    Ok((key, builder.build()))
+}
+
+/// Bundle the key and certificate chain into an identity to serve
+fn identity(hostname: &str, key: &PKey<Private>, chain: &[X509]) -> Result<Identity, Error> {
+    use openssl::{pkcs12::Pkcs12, stack::Stack};
+
+    let mut intermediates = Stack::new()?;
+    for certificate in &chain[1..] {
+        intermediates.push(certificate.clone())?;
+    }
+    let pkcs12 = Pkcs12::builder()
+        .name(hostname)
+        .pkey(key)
+        .cert(&chain[0])
+        .ca(intermediates)
+        .build2(PKCS12_PASSWORD)?;
+    Ok(Identity::from_pkcs12(&pkcs12.to_der()?, PKCS12_PASSWORD)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{power::PowerStatus, types::Device, xml};
+
+    #[tokio::test]
+    async fn power_and_boot_state_is_kept() {
+        let simulator = Simulator::new(Version::Ilo4);
+        let mut node = simulator.node();
+
+        assert_eq!(
+            node.get_host_power_status().await.unwrap(),
+            PowerStatus::Off
+        );
+        node.set_host_power(PowerStatus::On).await.unwrap();
+        assert_eq!(node.get_host_power_status().await.unwrap(), PowerStatus::On);
+
+        node.set_one_time_boot(Device::Cdrom).await.unwrap();
+        assert_eq!(node.get_one_time_boot().await.unwrap(), Device::Cdrom);
+        assert!(!node.get_server_event_log().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn power_commands_are_simulated() {
+        use crate::power::{HostPowerSaverMode, PowerCap, PowerOnDelay, PwrAlert, Pwreg};
+
+        let simulator = Simulator::new(Version::Ilo4);
+        let mut node = simulator.node();
+        assert_eq!(node.get_power_readings().await.unwrap().present.value, 0);
+        node.press_pwr_btn().await.unwrap();
+        assert_eq!(node.get_host_power_status().await.unwrap(), PowerStatus::On);
+        let readings = node.get_power_readings().await.unwrap();
+        assert_eq!(
+            (readings.present.value, readings.present.unit.as_str()),
+            (140, "Watts")
+        );
+        node.hold_pwr_btn().await.unwrap();
+        assert_eq!(
+            node.get_host_power_status().await.unwrap(),
+            PowerStatus::Off
+        );
+
+        assert_eq!(
+            node.get_server_auto_pwr().await.unwrap(),
+            PowerOnDelay::Restore
+        );
+        node.server_auto_pwr(PowerOnDelay::Delay30Seconds)
+            .await
+            .unwrap();
+        assert_eq!(
+            node.get_server_auto_pwr().await.unwrap(),
+            PowerOnDelay::Delay30Seconds
+        );
+
+        assert_eq!(node.get_server_power_on_time().await.unwrap(), 1440);
+        node.clear_server_power_on_time().await.unwrap();
+        assert_eq!(node.get_server_power_on_time().await.unwrap(), 0);
+
+        node.set_host_power_saver(HostPowerSaverMode::Max)
+            .await
+            .unwrap();
+        assert_eq!(
+            node.get_host_power_saver_status().await.unwrap(),
+            HostPowerSaverMode::Max
+        );
+
+        assert_eq!(node.get_power_cap().await.unwrap(), PowerCap::Off);
+        node.set_power_cap(PowerCap::Value(300)).await.unwrap();
+        assert_eq!(node.get_power_cap().await.unwrap(), PowerCap::Value(300));
+
+        assert_eq!(node.get_host_pwr_micro_ver().await.unwrap(), "2.3");
+
+        let pwr_alert = PwrAlert::Peak {
+            threshold: 200,
+            duration: 35,
+        };
+        node.set_pwreg(Pwreg {
+            efficiency_mode: None,
+            pwr_alert,
+            host_power: None,
+        })
+        .await
+        .unwrap();
+        let pwreg = node.get_pwreg().await.unwrap();
+        assert_eq!(pwreg.pwr_alert, pwr_alert);
+        assert_eq!(pwreg.host_power, Some(PowerStatus::Off));
+
+        // only iLO 2 reports processor power states
+        let simulator = Simulator::new(Version::Ilo2);
+        let mut node = simulator.node();
+        node.set_host_power(PowerStatus::On).await.unwrap();
+        let info = node.get_host_power_reg_info().await.unwrap();
+        assert_eq!((info.number_processors, info.number_pstates), (2, 2));
+        assert_eq!(info.processor_pstates.len(), 2);
+        assert_eq!(info.processor_pstates[1].pstates[0].total_average, 92.5);
+    }
+
+    #[tokio::test]
+    async fn boot_commands_are_simulated() {
+        let simulator = Simulator::new(Version::Ilo4);
+        let mut node = simulator.node();
+        node.reset_rib().await.unwrap();
+        assert!(node
+            .get_ilo_event_log()
+            .await
+            .unwrap()
+            .iter()
+            .any(|event| event.description == "iLO reset."));
+
+        // the server can only be reset while it's on
+        assert!(node.reset_server().await.is_err());
+        node.set_host_power(PowerStatus::On).await.unwrap();
+        node.reset_server().await.unwrap();
+        node.cold_boot_server().await.unwrap();
+        node.warm_boot_server().await.unwrap();
+        assert_eq!(node.get_server_event_log().await.unwrap().len(), 4);
+
+        assert_eq!(node.get_one_time_boot().await.unwrap(), Device::Normal);
+        node.set_one_time_boot(Device::Usb).await.unwrap();
+        assert_eq!(node.get_one_time_boot().await.unwrap(), Device::Usb);
+
+        node.set_persistent_boot(vec![Device::Hdd, Device::Cdrom])
+            .await
+            .unwrap();
+        let order = node.get_persistent_boot().await.unwrap().0;
+        assert_eq!(order[..3], [Device::Hdd, Device::Cdrom, Device::Floppy]);
+    }
+
+    #[tokio::test]
+    async fn virtual_media_commands_are_simulated() {
+        use crate::virtual_media::VmStatus;
+
+        let simulator = Simulator::new(Version::Ilo4);
+        let mut node = simulator.node();
+        let status = node.get_vm_status(Device::Cdrom).await.unwrap();
+        assert_eq!(
+            (status.image_inserted, status.image_url),
+            (Some(false), None)
+        );
+
+        let url = String::from("http://images.example.com/boot.iso");
+        node.insert_virtual_media(Device::Cdrom, url.clone())
+            .await
+            .unwrap();
+        node.set_vm_status(VmStatus {
+            device: Some(Device::Cdrom),
+            boot_option: Some(String::from("BOOT_ONCE")),
+            write_protect: Some(true),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let status = node.get_vm_status(Device::Cdrom).await.unwrap();
+        assert_eq!(status.image_url, Some(url));
+        assert_eq!(status.boot_option.as_deref(), Some("BOOT_ONCE"));
+        assert_eq!(
+            (status.write_protect, status.image_inserted),
+            (Some(true), Some(true))
+        );
+        // the floppy is a separate device
+        let floppy = node.get_vm_status(Device::Floppy).await.unwrap();
+        assert_eq!(floppy.image_inserted, Some(false));
+
+        node.eject_virtual_media(Device::Cdrom).await.unwrap();
+        let status = node.get_vm_status(Device::Cdrom).await.unwrap();
+        assert_eq!(status.image_inserted, Some(false));
+        assert!(node.eject_virtual_media(Device::Cdrom).await.is_err());
+    }
+
+    #[test]
+    fn login_is_checked() {
+        let simulator = Simulator::new(Version::Ilo2);
+        let response = simulator.handle_ribcl(
+            r#"<ribcl version="2.0"><login user_login="admin" password="wrong"><rib_info mode="read"><get_fw_version/></rib_info></login></ribcl>"#,
+        );
+        assert!(response.contains("0x005F"));
+    }
+
+    #[test]
+    fn unknown_commands_are_syntax_errors() {
+        let simulator = Simulator::new(Version::Ilo4);
+        let response = simulator.handle_ribcl(
+            r#"<ribcl version="2.0"><login user_login="admin" password="password"><rib_info mode="write"><mod_unknown_settings/></rib_info></login></ribcl>"#,
+        );
+        let documents: Vec<_> = xml::documents(&response).collect();
+        assert_eq!(documents.len(), 2);
+        assert!(documents[0].contains("0x0000"));
+        assert!(documents[1].contains("0x0001"));
+    }
+}
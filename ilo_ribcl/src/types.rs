@@ -191,7 +191,7 @@ macro_rules! simple_builder_into_ribcl_def {
     };
     ($type:ty, {$($func:tt)+}) => {
         impl crate::into_ribcl::IntoRibcl for $type {
-            #[tracing::instrument]
+            #[tracing::instrument(skip(self))]
             fn into_ribcl(&self) -> Result<String, crate::into_ribcl::Error> {
                 Ok(self).map($($func)+)
             }
@@ -293,6 +293,12 @@ simple_builder_def!(String, {
     }
 });
 
+impl std::default::Default for StringBuilder {
+    fn default() -> Self {
+        SimpleBuilder(String::new())
+    }
+}
+
 //simple_builder_alias!(MacAddress, String);
 pub type MacAddress = String;
 pub type MacAddressBuilder = SimpleBuilder<MacAddress>;
@@ -317,10 +323,6 @@ pub type TimezoneBuilder = SimpleBuilder<Timezone>;
 pub type Url = String;
 pub type UrlBuilder = SimpleBuilder<Url>;
 
-//simple_builder_alias!(CertificateSigningRequest, String);
-pub type CertificateSigningRequest = String;
-pub type CertificateSigningRequestBuilder = SimpleBuilder<CertificateSigningRequest>;
-
 //simple_builder_alias!(Certificate, String);
 pub type Certificate = String;
 pub type CertificateBuilder = SimpleBuilder<Certificate>;
@@ -434,6 +436,9 @@ pub enum Version {
     Ilo2,
     Ilo3,
     Ilo4,
+    /// iLO 5 accepts RIBCL over HTTPS like iLO 4, commands limited to iLO 4
+    /// aren't sent to it
+    Ilo5,
 }
 
 pub type VersionBuilder = SimpleBuilder<Version>;
@@ -446,6 +451,7 @@ simple_builder_def!(
                 "ilo2" => Ok(Some(Ilo2)),
                 "ilo3" => Ok(Some(Ilo3)),
                 "ilo4" => Ok(Some(Ilo4)),
+                "ilo5" => Ok(Some(Ilo5)),
                 _ => Err(Error::InvalidString {
                     target: "Version",
                     value,
@@ -460,6 +466,7 @@ simple_builder_def!(
                 Ilo2 => "iLO2",
                 Ilo3 => "iLO3",
                 Ilo4 => "iLO4",
+                Ilo5 => "iLO5",
             }
             .to_string()
         }
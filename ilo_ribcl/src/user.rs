@@ -0,0 +1,278 @@
+use crate::{
+    authentication::{Login, LoginBuilder},
+    client,
+    into_ribcl::IntoRibcl,
+    redact::REDACTED,
+    ribcl_into::RibclInto,
+    types::{BoolBuilder, StringBuilder},
+};
+use ilo_ribcl_derive::BuilderParse;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+use std::{convert::TryInto, fmt, fmt::Write};
+
+/// A right that can be granted to a local user
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+pub enum Privilege {
+    /// Administer user accounts
+    Admin,
+    /// Use the remote console
+    RemoteConsole,
+    /// Power cycle and reset the server
+    ResetServer,
+    /// Connect virtual media
+    VirtualMedia,
+    /// Change the iLO settings
+    ConfigIlo,
+}
+
+impl Privilege {
+    pub const ALL: [Privilege; 5] = [
+        Privilege::Admin,
+        Privilege::RemoteConsole,
+        Privilege::ResetServer,
+        Privilege::VirtualMedia,
+        Privilege::ConfigIlo,
+    ];
+
+    fn tag(self) -> &'static str {
+        use Privilege::*;
+        match self {
+            Admin => "admin_priv",
+            RemoteConsole => "remote_cons_priv",
+            ResetServer => "reset_server_priv",
+            VirtualMedia => "virtual_media_priv",
+            ConfigIlo => "config_ilo_priv",
+        }
+    }
+}
+
+/// Write every privilege, granting those in `granted` and revoking the rest
+fn write_privileges<W: Write>(writer: &mut W, granted: &[Privilege]) -> fmt::Result {
+    for privilege in Privilege::ALL.iter() {
+        let value = if granted.contains(privilege) {
+            "Y"
+        } else {
+            "N"
+        };
+        write!(writer, "<{} value=\"{}\"/>", privilege.tag(), value)?;
+    }
+    Ok(())
+}
+
+#[skip_serializing_none]
+#[derive(Debug, Serialize, PartialEq, BuilderParse)]
+#[ribcl(attributes)]
+pub struct User {
+    pub user_name: Option<String>,
+    pub user_login: Option<String>,
+    pub admin_priv: Option<bool>,
+    pub remote_cons_priv: Option<bool>,
+    pub reset_server_priv: Option<bool>,
+    pub virtual_media_priv: Option<bool>,
+    pub config_ilo_priv: Option<bool>,
+}
+
+impl User {
+    /// The privileges granted to the user
+    pub fn privileges(&self) -> Vec<Privilege> {
+        use Privilege::*;
+        [
+            (Admin, self.admin_priv),
+            (RemoteConsole, self.remote_cons_priv),
+            (ResetServer, self.reset_server_priv),
+            (VirtualMedia, self.virtual_media_priv),
+            (ConfigIlo, self.config_ilo_priv),
+        ]
+        .iter()
+        .filter(|(_, granted)| *granted == Some(true))
+        .map(|(privilege, _)| *privilege)
+        .collect()
+    }
+}
+
+/// A local user to create
+#[derive(Clone, PartialEq)]
+pub struct NewUser {
+    /// The name shown in the user list
+    pub user_name: String,
+    /// The name used to log in
+    pub user_login: String,
+    pub password: String,
+    pub privileges: Vec<Privilege>,
+}
+
+// the password isn't logged
+impl fmt::Debug for NewUser {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NewUser")
+            .field("user_name", &self.user_name)
+            .field("user_login", &self.user_login)
+            .field("password", &REDACTED)
+            .field("privileges", &self.privileges)
+            .finish()
+    }
+}
+
+/// Changes to a local user, fields left as `None` are unchanged
+#[derive(Default, Clone, PartialEq)]
+pub struct UserChanges {
+    pub user_name: Option<String>,
+    /// Rename the login
+    pub user_login: Option<String>,
+    pub password: Option<String>,
+    /// Grant these privileges and revoke the rest
+    pub privileges: Option<Vec<Privilege>>,
+}
+
+// the password isn't logged
+impl fmt::Debug for UserChanges {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UserChanges")
+            .field("user_name", &self.user_name)
+            .field("user_login", &self.user_login)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("privileges", &self.privileges)
+            .finish()
+    }
+}
+
+impl client::Node {
+    get_method!(
+        /// Returns the login names of the local users
+        user_info.get_all_users -> Vec<Login>,
+        "iL0 3, iL0 4 or iL0 2 version >= 1.75",
+        (Ilo3),
+        (Ilo4),
+        (Ilo2, "1.75")
+    );
+
+    get_method!(
+        /// Returns the names and privileges of the local users
+        user_info.get_all_user_info -> Vec<User>,
+        "iL0 3, iL0 4 or iL0 2 version >= 1.75",
+        (Ilo3),
+        (Ilo4),
+        (Ilo2, "1.75")
+    );
+
+    get_method!(
+        /// Returns the name and privileges of a local user
+        user_info.get_user("user_login": String) -> User,
+        "iL0 2, iL0 3 or iL0 4",
+        (Ilo2),
+        (Ilo3),
+        (Ilo4)
+    );
+
+    mod_method!(
+        /// Create a local user
+        user_info.add_user(user: NewUser),
+        "iL0 2, iL0 3 or iL0 4",
+        (Ilo2),
+        (Ilo3),
+        (Ilo4)
+        => |request| {
+            write!(
+                request,
+                "<add_user user_name=\"{}\" user_login=\"{}\" password=\"{}\">",
+                user.user_name.into_ribcl()?,
+                user.user_login.into_ribcl()?,
+                user.password.into_ribcl()?
+            )?;
+            write_privileges(&mut request, &user.privileges)?;
+            write!(request, "</add_user>")?;
+        }
+    );
+
+    mod_method!(
+        /// Update the name, login, password or privileges of a local user
+        user_info.mod_user(user_login: String, changes: UserChanges),
+        "iL0 2, iL0 3 or iL0 4",
+        (Ilo2),
+        (Ilo3),
+        (Ilo4)
+        => |request| {
+            write!(
+                request,
+                "<mod_user user_login=\"{}\">",
+                user_login.into_ribcl()?
+            )?;
+            ribcl_tag!(request, changes, user_name);
+            ribcl_tag!(request, changes, user_login);
+            ribcl_tag!(request, changes, password);
+            if let Some(privileges) = &changes.privileges {
+                write_privileges(&mut request, privileges)?;
+            }
+            write!(request, "</mod_user>")?;
+        }
+    );
+
+    mod_method!(
+        /// Delete a local user
+        user_info.delete_user("user_login": String),
+        "iL0 2, iL0 3 or iL0 4",
+        (Ilo2),
+        (Ilo3),
+        (Ilo4)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        commands,
+        types::{FwVersion, Version},
+    };
+
+    #[test]
+    fn privileges_are_written_in_full() {
+        let mut request = String::new();
+        write_privileges(
+            &mut request,
+            &[Privilege::RemoteConsole, Privilege::VirtualMedia],
+        )
+        .unwrap();
+        assert_eq!(
+            request,
+            "<admin_priv value=\"N\"/><remote_cons_priv value=\"Y\"/>\
+             <reset_server_priv value=\"N\"/><virtual_media_priv value=\"Y\"/>\
+             <config_ilo_priv value=\"N\"/>"
+        );
+
+        let user = User {
+            user_name: Some(String::from("Operator")),
+            user_login: Some(String::from("operator")),
+            admin_priv: Some(false),
+            remote_cons_priv: Some(true),
+            reset_server_priv: Some(true),
+            virtual_media_priv: None,
+            config_ilo_priv: Some(false),
+        };
+        assert_eq!(
+            user.privileges(),
+            vec![Privilege::RemoteConsole, Privilege::ResetServer]
+        );
+    }
+
+    #[test]
+    fn commands_are_gated_by_firmware() {
+        let firmware = FwVersion {
+            firmware_version: Some(String::from("1.50")),
+            management_processor: Some(Version::Ilo2),
+            ..Default::default()
+        };
+        let node = client::Node::new_with_fw(Default::default(), firmware).unwrap();
+        assert!(matches!(
+            node.get_all_users_command(),
+            Err(commands::Error::NotSupported { .. })
+        ));
+        let command = node.delete_user_command(String::from("operator")).unwrap();
+        assert_eq!(command.name(), "delete_user");
+        assert_eq!(
+            command.request.body,
+            "<delete_user user_login=\"operator\"/>"
+        );
+    }
+}
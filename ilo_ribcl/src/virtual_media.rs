@@ -22,33 +22,10 @@ pub struct VmStatus {
 }
 
 impl client::Node {
-    /*
     get_method!(
-        /// Return the status of the given virtual media device
-        rib_info.get_vm_status(Device) -> VmStatus
+        /// Returns the status of the given virtual media device
+        rib_info.get_vm_status("device": Device) -> VmStatus
     );
-    */
-    /// Returns the status of the given virtual media device
-    #[tracing::instrument]
-    pub async fn get_vm_status(&mut self, device: Device) -> Result<VmStatus, commands::Error> {
-        let mut request = String::new();
-        ribcl_header!(request, self.auth(), rib_info, read)?;
-        write!(
-            request,
-            "<get_vm_status device=\"{}\"/>",
-            device.into_ribcl()?
-        )?;
-        ribcl_footer!(request, rib_info)?;
-        let response = self.send_ribcl(request.into_bytes()).await?;
-        Ok(
-            ribcl_parse_response!(response, "get_vm_status" -> VmStatus)?.map_err(|source| {
-                commands::Error::BuilderParse {
-                    target: "VmStatus",
-                    source,
-                }
-            })?,
-        )
-    }
 
     // TODO: rewrite to allow separate boot_option and write_protected arguments
     // mod_method!(
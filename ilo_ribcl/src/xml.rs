@@ -51,6 +51,78 @@ impl<B: BufRead> std::fmt::Debug for XmlCursor<B> {
     }
 }
 
+/// Returns the documents in a RIBCL response.
+///
+/// The iLO replies with a separate xml document for each command in a request.
+pub fn documents(response: &str) -> impl Iterator<Item = &str> {
+    response.split(r#"<?xml version="1.0"?>"#).filter(|doc| {
+        event!(Level::TRACE, doc);
+        !doc.trim().is_empty()
+    })
+}
+
+/// Counts the documents the iLO answers a RIBCL request with, one for the login
+/// and one for each command, or `None` if the request isn't well formed.
+pub fn expected_documents(request: &[u8]) -> Option<usize> {
+    let mut reader = Reader::from_reader(request);
+    let mut buf = Vec::new();
+    let mut depth = 0;
+    let mut commands = 0;
+    loop {
+        match reader.read_event(&mut buf).ok()? {
+            // RIBCL > LOGIN > section > command
+            Event::Start(_) => {
+                depth += 1;
+                if depth == 4 {
+                    commands += 1;
+                }
+            }
+            Event::Empty(_) if depth == 3 => commands += 1,
+            Event::End(_) => depth -= 1,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Some(commands + 1)
+}
+
+/// Counts the documents in a partly received response that have been closed
+pub fn complete_documents(response: &str) -> usize {
+    documents(response)
+        .filter(|doc| doc.trim_end().to_ascii_lowercase().ends_with("</ribcl>"))
+        .count()
+}
+
+/// Returns whether the last document in a response is closed and reports an
+/// error status, after which the iLO doesn't answer the remaining commands.
+pub fn ends_with_error(response: &str) -> bool {
+    match documents(response).last() {
+        Some(last) if last.trim_end().to_ascii_lowercase().ends_with("</ribcl>") => {
+            response_status(last).is_err()
+        }
+        _ => false,
+    }
+}
+
+/// Returns the error status reported by the RESPONSE element of a document
+pub fn response_status(document: &str) -> Result<(), Error> {
+    let mut reader = Reader::from_str(document);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf) {
+            Ok(Event::Start(element)) | Ok(Event::Empty(element))
+                if element.name().eq_ignore_ascii_case(b"response") =>
+            {
+                return handle_ribcl_response_errors(element);
+            }
+            Ok(Event::Eof) | Err(_) => return Ok(()),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
 pub fn handle_ribcl_response_errors(element: Element<'_>) -> Result<(), Error> {
     let mut status: u16 = 0;
     let mut message: String = String::new();
@@ -342,4 +414,51 @@ macro_rules! ribcl_footer {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+    use crate::commands::tests::response;
+
+    #[test]
+    fn documents_skips_blank_documents() {
+        let response = "<?xml version=\"1.0\"?>\r\n<RIBCL VERSION=\"2.22\"/>\r\n\
+                        <?xml version=\"1.0\"?>\r\n<RIBCL VERSION=\"2.22\"/>\r\n";
+        assert_eq!(documents(response).count(), 2);
+        assert_eq!(documents("\r\n").count(), 0);
+    }
+
+    #[test]
+    fn expected_documents_counts_login_and_commands() {
+        let request = br#"<?xml version="1.0"?>
+<RIBCL VERSION="2.0">
+  <LOGIN USER_LOGIN="admin" PASSWORD="password">
+    <SERVER_INFO MODE="read">
+      <GET_HOST_POWER_STATUS/>
+      <GET_SERVER_NAME></GET_SERVER_NAME>
+    </SERVER_INFO>
+    <RIB_INFO MODE="write">
+      <MOD_GLOBAL_SETTINGS><SESSION_TIMEOUT VALUE="30"/></MOD_GLOBAL_SETTINGS>
+    </RIB_INFO>
+  </LOGIN>
+</RIBCL>"#;
+        assert_eq!(expected_documents(request), Some(4));
+        assert_eq!(expected_documents(b"<RIBCL><LOGIN></RIBCL>"), None);
+    }
+
+    #[test]
+    fn complete_documents_ignores_a_partial_document() {
+        let login = &response(0, "No error", "");
+        let partial = format!(
+            "{}<?xml version=\"1.0\"?>\r\n<RIBCL VERSION=\"2.22\">",
+            login
+        );
+        assert_eq!(complete_documents(login), 1);
+        assert_eq!(complete_documents(&partial), 1);
+        assert!(!ends_with_error(login));
+        let error = format!(
+            "{}{}",
+            login,
+            response(0x005f, "Login credentials rejected.", "")
+        );
+        assert!(ends_with_error(&error));
+    }
+}
@@ -15,6 +15,16 @@ pub fn impl_ribcl_auth_macro(item_struct: ItemStruct) -> syn::Result<TokenStream
             #[structopt(short, long)]
             pub proxy_cache: bool
         })?);
+        fields.named.push(syn::Field::parse_named.parse2(quote! {
+            /// directory the cassette used by --proxy-cache is kept in
+            #[structopt(long, parse(from_os_str), default_value = ".")]
+            pub cassette_dir: std::path::PathBuf
+        })?);
+        fields.named.push(syn::Field::parse_named.parse2(quote! {
+            /// how --proxy-cache uses the cassette, one of record, replay or pass_through
+            #[structopt(long, default_value = "record")]
+            pub cassette_mode: ilo_ribcl::cassette::CassetteMode
+        })?);
         fields.named.push(syn::Field::parse_named.parse2(quote! {
             // endpoint info file
             #[structopt(short, long, parse(from_os_str), default_value = "endpoint.json")]